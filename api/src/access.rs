@@ -0,0 +1,103 @@
+use poem::{http::StatusCode, Error, Result};
+use store::{
+    error::StoreError,
+    models::org::{role_allows, VIEWER},
+    store::Store,
+};
+
+use crate::{auth_middleware::AuthUser, error::ApiError};
+
+fn two_factor_required() -> Error {
+    ApiError::new(
+        StatusCode::FORBIDDEN,
+        "two_factor_required",
+        "This organization requires two-factor authentication",
+    )
+    .into()
+}
+
+/// Checks that the user has at least `required` in the organization owning
+/// `website`, and that a key limited to one organization is used on its
+/// websites only. Returns the website's organization.
+pub async fn require_website_role(s: &Store, user: &AuthUser, website: &str, required: &str) -> Result<String> {
+    let access = match s.get_website_access(website.to_string(), user.user_id.clone()).await {
+        Ok(access) => access,
+        // Unknown websites answer the same as other organizations'
+        Err(StoreError::NotFound) => return Err(Error::from_status(StatusCode::FORBIDDEN)),
+        Err(e) => return Err(ApiError::from(e).into()),
+    };
+
+    if user.org_id.as_ref().is_some_and(|o| *o != access.org_id) {
+        return Err(Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    if access.missing_two_factor {
+        return Err(two_factor_required());
+    }
+
+    if !role_allows(&access.role, required) {
+        return Err(Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(access.org_id)
+}
+
+/// Checks that the user has at least `required` in `org_id`. Returns their
+/// role.
+pub async fn require_org_role(s: &Store, user: &AuthUser, org_id: &str, required: &str) -> Result<String> {
+    if user.org_id.as_ref().is_some_and(|o| o != org_id) {
+        return Err(Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    let role = match s.get_member_role(org_id.to_string(), user.user_id.clone()).await {
+        Ok(role) => role,
+        Err(StoreError::NotFound) => return Err(Error::from_status(StatusCode::FORBIDDEN)),
+        Err(e) => return Err(ApiError::from(e).into()),
+    };
+
+    match s.is_missing_org_two_factor(org_id.to_string(), user.user_id.clone()).await {
+        Ok(false) => {}
+        Ok(true) => return Err(two_factor_required()),
+        Err(e) => return Err(ApiError::from(e).into()),
+    }
+
+    if !role_allows(&role, required) {
+        return Err(Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    Ok(role)
+}
+
+/// Checks that the caller may read `website`'s analytics: either the signed-in
+/// user is a member of the organization owning it, or `share_token` is the
+/// website's public dashboard link, which only exists once sharing is on.
+pub async fn authorize_website(
+    s: &Store,
+    user: Option<&AuthUser>,
+    website: &str,
+    share_token: Option<&str>,
+) -> Result<()> {
+    if let Some(token) = share_token {
+        return match s.is_website_shared(website.to_string(), token.to_string()).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Error::from_status(StatusCode::FORBIDDEN)),
+            Err(e) => Err(ApiError::from(e).into()),
+        };
+    }
+
+    let Some(user) = user else {
+        return Err(Error::from_status(StatusCode::UNAUTHORIZED));
+    };
+
+    require_website_role(s, user, website, VIEWER).await?;
+    Ok(())
+}
+
+/// Whether `share_token` is `website`'s public dashboard link. Badges, feeds
+/// and the public incident list only answer for websites shared this way.
+pub async fn is_shared(s: &Store, website: &str, share_token: Option<&str>) -> Result<bool, StoreError> {
+    match share_token {
+        Some(token) => s.is_website_shared(website.to_string(), token.to_string()).await,
+        None => Ok(false),
+    }
+}
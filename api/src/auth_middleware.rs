@@ -1,66 +1,275 @@
-use std::env;
+use std::{env, net::IpAddr, sync::Arc};
+
+use ipnet::IpNet;
 
 use jsonwebtoken::{decode, DecodingKey, Validation};
-use poem::{http::StatusCode, Error, FromRequest, Request, RequestBody, Result};
+use poem::{
+    http::{header, StatusCode},
+    Error, FromRequest, Request, RequestBody, Result,
+};
+use store::{
+    models::{
+        api_key::API_KEY_MARKER,
+        audit::Actor,
+    },
+    store::Store,
+};
 
-use crate::route::user::Claims;
+use crate::{
+    config::Config,
+    rate_limit::{api_key_bucket, too_many_requests, RateLimiter, API_KEY},
+    route::user::Claims,
+};
 
-pub struct UserId(pub String);
+/// Decodes an access token and checks that its session was not revoked.
+async fn authenticate(req: &Request, token: &str) -> Result<Claims> {
+    let claims = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(env::var("JWT_SECRET").map_err(|_| Error::from_string("Invalid Secret", StatusCode::UNAUTHORIZED))?.as_ref()),
+        &Validation::default(),
+    )
+    .map_err(|e| {
+        println!("{}", e);
+        Error::from_string("Error during decoding jwt", StatusCode::UNAUTHORIZED)
+    })?
+    .claims;
 
-#[poem::async_trait]
-impl<'a> FromRequest<'a> for UserId {
-    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> Result<Self> {
-        let cookies = req.cookie();
-        let cookie = cookies.get("jwt");
-
-        match cookie {
-            Some(token) => {
-                let value = token.value_str();
-                let claims = decode::<Claims>(
-                    &value,
-                    &DecodingKey::from_secret(env::var("JWT_SECRET").map_err(|_| Error::from_string("Invalid Secret", StatusCode::UNAUTHORIZED))?.as_ref()),
-                    &Validation::default(),
-                )
-                .map_err(|e| {
-                    println!("{}", e.to_string());
-                    Error::from_string("Error during decoding jwt", StatusCode::UNAUTHORIZED)
-                })?;
-
-                return Ok(UserId(claims.claims.sub));
+    let s = req
+        .data::<Arc<Store>>()
+        .ok_or_else(|| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    match s.is_session_active(claims.sid.clone()).await {
+        Ok(true) => Ok(claims),
+        Ok(false) => Err(Error::from_string("Session expired", StatusCode::UNAUTHORIZED)),
+        Err(_) => Err(Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)),
+    }
+}
+
+/// The access token from the `jwt` cookie or an `Authorization: Bearer`
+/// header, if any.
+pub fn access_token(req: &Request) -> Option<String> {
+    if let Some(cookie) = req.cookie().get("jwt") {
+        return Some(cookie.value_str().to_string());
+    }
+
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string())
+}
+
+/// The client's address. `X-Forwarded-For` is only believed when the
+/// request comes from one of the configured `TRUSTED_PROXIES`, since anyone
+/// else can send it.
+pub fn client_ip(req: &Request) -> Option<String> {
+    let peer = req.remote_addr().as_socket_addr().map(|a| a.ip());
+    let trusted = req
+        .data::<Arc<Config>>()
+        .map(|c| c.trusted_proxies.as_slice())
+        .unwrap_or_default();
+    let forwarded = req.headers().get("X-Forwarded-For").and_then(|v| v.to_str().ok());
+
+    forwarded_client(peer, forwarded, trusted).map(|ip| ip.to_string())
+}
+
+/// Walks `X-Forwarded-For` from the right, where our own proxies append, to
+/// the first hop that isn't one of them. Earlier entries were written by the
+/// client and are ignored.
+fn forwarded_client(peer: Option<IpAddr>, forwarded: Option<&str>, trusted: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+
+    let mut client = peer?;
+    if !is_trusted(&client) {
+        return Some(client);
+    }
+
+    for hop in forwarded.unwrap_or_default().rsplit(',') {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !is_trusted(&ip) {
+                    break;
+                }
             }
-            None => return Ok(UserId("".to_string())),
+            // Garbage from the client, the last good hop is as far as we trust
+            Err(_) => break,
         }
     }
+
+    Some(client)
 }
 
-pub struct UserIdFromHeader(pub String);
+/// Claims of a valid access token, for handlers that need the session id.
+pub async fn session_claims(req: &Request) -> Result<Claims> {
+    let token = access_token(req)
+        .ok_or_else(|| Error::from_string("Missing access token", StatusCode::UNAUTHORIZED))?;
+    authenticate(req, &token).await
+}
+
+/// What an API key needs to call a route, attached to it with `.data(...)`
+/// in `main`. Routes without one, like account settings and key management,
+/// only accept a session.
+#[derive(Clone, Copy)]
+pub struct KeyScope {
+    pub scope: &'static str,
+    /// The handler checks access per website, so keys limited to one
+    /// organization can call it too
+    pub per_website: bool,
+}
+
+impl KeyScope {
+    pub const fn account(scope: &'static str) -> Self {
+        KeyScope { scope, per_website: false }
+    }
+
+    pub const fn website(scope: &'static str) -> Self {
+        KeyScope { scope, per_website: true }
+    }
+}
+
+/// Checks an API key and that it has the scope the route needs.
+async fn authenticate_api_key(req: &Request, key: &str) -> Result<AuthUser> {
+    let required = *req
+        .data::<KeyScope>()
+        .ok_or_else(|| Error::from_string("API keys can't be used here", StatusCode::FORBIDDEN))?;
+
+    let s = req
+        .data::<Arc<Store>>()
+        .ok_or_else(|| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    let principal = s
+        .authenticate_api_key(key.to_string())
+        .await
+        .map_err(|_| Error::from_string("Invalid API key", StatusCode::UNAUTHORIZED))?;
+
+    // Only real keys get their own bucket, made up ones stay on the IP's
+    if let Some(limiter) = req.data::<Arc<RateLimiter>>() {
+        limiter.check(&api_key_bucket(key), API_KEY).await.map_err(too_many_requests)?;
+    }
+
+    if !principal.scopes.iter().any(|s| s == required.scope) {
+        return Err(Error::from_string(format!("API key lacks the {} scope", required.scope), StatusCode::FORBIDDEN));
+    }
+
+    if principal.org_id.is_some() && !required.per_website {
+        return Err(Error::from_string("Organization API keys can't be used here", StatusCode::FORBIDDEN));
+    }
+
+    Ok(AuthUser { user_id: principal.user_id, org_id: principal.org_id, ip: client_ip(req) })
+}
+
+/// The authenticated user, from an access token or an API key sent as
+/// `Authorization: Bearer`. Requests without a valid, unrevoked one are
+/// rejected with 401, and keys without the route's `KeyScope` with 403, before
+/// the handler runs.
+pub struct AuthUser {
+    pub user_id: String,
+    /// Set for API keys limited to one organization's websites
+    pub org_id: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl AuthUser {
+    /// The user as the actor of changes recorded in the audit log.
+    pub fn actor(&self) -> Actor {
+        Actor { user_id: self.user_id.clone(), ip: self.ip.clone() }
+    }
+}
 
 #[poem::async_trait]
-impl<'a> FromRequest<'a> for UserIdFromHeader {
+impl<'a> FromRequest<'a> for AuthUser {
     async fn from_request(req: &'a Request, _body: &mut RequestBody) -> Result<Self> {
+        let token = access_token(req)
+            .ok_or_else(|| Error::from_string("Missing access token", StatusCode::UNAUTHORIZED))?;
 
-        let jwt = req.headers().get("jwt");
-
-        match jwt {
-            Some(jwt) => {
-                let jwt = jwt.to_str().map_err(|_| {
-                    Error::from_string(
-                        "Error during parsing jwt",
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                    )
-                })?;
-                let value = jwt.to_string();
-                let claims = decode::<Claims>(
-                    &value,
-                    &DecodingKey::from_secret(env::var("JWT_SECRET").map_err(|_| Error::from_string("Invalid Secret", StatusCode::UNAUTHORIZED))?.as_ref()),
-                    &Validation::default(),
-                )
-                .map_err(|_| {
-                    Error::from_string("Error during decoding jwt", StatusCode::UNAUTHORIZED)
-                })?;
-                Ok(UserIdFromHeader(claims.claims.sub))
-            }
-            None => Ok(UserIdFromHeader("".to_string())),
+        if token.starts_with(API_KEY_MARKER) {
+            return authenticate_api_key(req, &token).await;
         }
+
+        let claims = authenticate(req, &token).await?;
+        Ok(AuthUser { user_id: claims.sub, org_id: None, ip: client_ip(req) })
+    }
+}
+
+/// `AuthUser` for routes that also serve anonymous visitors, like shared
+/// dashboards. Only a missing token makes it `None`: bad tokens, keys without
+/// the scope and rate limits are still answered with their error.
+pub struct MaybeAuthUser(pub Option<AuthUser>);
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for MaybeAuthUser {
+    async fn from_request(req: &'a Request, body: &mut RequestBody) -> Result<Self> {
+        if access_token(req).is_none() {
+            return Ok(MaybeAuthUser(None));
+        }
+
+        AuthUser::from_request(req, body).await.map(|user| MaybeAuthUser(Some(user)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, middleware::CookieJarManager, Endpoint, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    fn whoami(user: AuthUser) -> String {
+        user.user_id
+    }
+
+    #[handler]
+    fn maybe_whoami(MaybeAuthUser(user): MaybeAuthUser) -> String {
+        user.map_or_else(|| "anonymous".to_string(), |u| u.user_id)
+    }
+
+    fn key_request() -> Request {
+        Request::builder()
+            .header(header::AUTHORIZATION, format!("Bearer {}invalid", API_KEY_MARKER))
+            .finish()
+    }
+
+    #[test]
+    fn forwarded_for_is_only_believed_from_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/24".parse().unwrap()];
+
+        // Straight from the client, whatever it claims
+        assert_eq!(forwarded_client(Some(client), Some("1.1.1.1"), &trusted), Some(client));
+        // Through our proxy, the hop it appended wins over the spoofed one
+        assert_eq!(forwarded_client(Some(proxy), Some("1.1.1.1, 203.0.113.7"), &trusted), Some(client));
+        // Through two of our proxies
+        assert_eq!(forwarded_client(Some(proxy), Some("203.0.113.7, 10.0.0.3"), &trusted), Some(client));
+        // Garbage stops the walk at the last good hop
+        assert_eq!(forwarded_client(Some(proxy), Some("203.0.113.7, nope"), &trusted), Some(proxy));
+        // Without trusted proxies the header is ignored
+        assert_eq!(forwarded_client(Some(proxy), Some("203.0.113.7"), &[]), Some(proxy));
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_denied_on_routes_without_a_scope() {
+        let res = whoami.with(CookieJarManager::new()).get_response(key_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // With a scope the key gets looked up, which needs the store
+        let res = whoami
+            .data(KeyScope::account("monitors:read"))
+            .with(CookieJarManager::new())
+            .get_response(key_request())
+            .await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn optional_auth_only_ignores_missing_tokens() {
+        let res = maybe_whoami.with(CookieJarManager::new()).get_response(Request::default()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body().into_string().await.unwrap(), "anonymous");
+
+        // A key that can't be used here is refused rather than treated as anonymous
+        let res = maybe_whoami.with(CookieJarManager::new()).get_response(key_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
     }
 }
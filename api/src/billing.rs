@@ -0,0 +1,435 @@
+use chrono::{DateTime, NaiveDateTime};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use store::{
+    error::StoreError,
+    models::billing::{SubscriptionChange, PAST_DUE},
+    store::Store,
+};
+use utoipa::ToSchema;
+
+use crate::{config::PaymentConfig, error::ApiError};
+
+/// How old a signed webhook may be before it is rejected as a replay.
+const SIGNATURE_TOLERANCE_SECS: i64 = 300;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CheckoutSession {
+    pub id: String,
+    pub url: String,
+}
+
+pub type BillingError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Creates a hosted checkout for `price_id` at the payment provider, using the
+/// Stripe form-encoded API. The user id comes back as `client_reference_id` in
+/// the `checkout.session.completed` webhook.
+pub async fn create_checkout_session(
+    client: &reqwest::Client,
+    config: &PaymentConfig,
+    price_id: &str,
+    user_id: &str,
+    plan_name: &str,
+    return_url: &str,
+) -> Result<CheckoutSession, BillingError> {
+    let success_url = format!("{}?checkout=success", return_url);
+    let cancel_url = format!("{}?checkout=cancel", return_url);
+
+    let session = client
+        .post(format!("{}/v1/checkout/sessions", config.provider_url))
+        .bearer_auth(&config.api_key)
+        .form(&[
+            ("mode", "subscription"),
+            ("line_items[0][price]", price_id),
+            ("line_items[0][quantity]", "1"),
+            ("client_reference_id", user_id),
+            ("metadata[plan]", plan_name),
+            ("success_url", success_url.as_str()),
+            ("cancel_url", cancel_url.as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<CheckoutSession>()
+        .await?;
+
+    Ok(session)
+}
+
+/// Cancels the subscription at the provider. The plan changes once the
+/// provider confirms through the webhook.
+pub async fn cancel_subscription(
+    client: &reqwest::Client,
+    config: &PaymentConfig,
+    subscription_id: &str,
+) -> Result<(), BillingError> {
+    client
+        .delete(format!(
+            "{}/v1/subscriptions/{}",
+            config.provider_url, subscription_id
+        ))
+        .bearer_auth(&config.api_key)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+/// Verifies a `t=<unix time>,v1=<hex hmac>` signature header: the HMAC-SHA256
+/// of `"<t>.<body>"` under the webhook secret must match one of the `v1`
+/// values, and `t` must be within the tolerance of `now`.
+pub fn verify_signature(secret: &str, header: &str, body: &[u8], now: i64) -> bool {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", t)) => timestamp = t.parse::<i64>().ok(),
+            Some(("v1", sig)) => signatures.extend(hex::decode(sig).ok()),
+            _ => {}
+        }
+    }
+
+    let Some(timestamp) = timestamp else {
+        return false;
+    };
+    if (now - timestamp).abs() > SIGNATURE_TOLERANCE_SECS {
+        return false;
+    }
+
+    signatures.iter().any(|sig| {
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        // verify_slice compares in constant time
+        mac.verify_slice(sig).is_ok()
+    })
+}
+
+/// What applying webhook events needs from storage. `Store` is the real one;
+/// tests replay events against one kept in memory.
+#[poem::async_trait]
+pub trait BillingLedger {
+    async fn is_event_processed(&self, event_id: String) -> Result<bool, StoreError>;
+    async fn mark_event_processed(&self, event_id: String, event_type: String) -> Result<(), StoreError>;
+    async fn record_checkout(
+        &self,
+        user_id: String,
+        customer_id: String,
+        subscription_id: String,
+        plan_name: String,
+        event_at: NaiveDateTime,
+    ) -> Result<(), StoreError>;
+    async fn record_subscription_change(&self, change: SubscriptionChange) -> Result<(), StoreError>;
+}
+
+#[poem::async_trait]
+impl BillingLedger for Store {
+    async fn is_event_processed(&self, event_id: String) -> Result<bool, StoreError> {
+        self.is_billing_event_processed(event_id).await
+    }
+
+    async fn mark_event_processed(&self, event_id: String, event_type: String) -> Result<(), StoreError> {
+        self.mark_billing_event_processed(event_id, event_type).await
+    }
+
+    async fn record_checkout(
+        &self,
+        user_id: String,
+        customer_id: String,
+        subscription_id: String,
+        plan_name: String,
+        event_at: NaiveDateTime,
+    ) -> Result<(), StoreError> {
+        self.start_subscription(user_id, customer_id, subscription_id, plan_name, event_at)
+            .await
+            .map(|_| ())
+    }
+
+    async fn record_subscription_change(&self, change: SubscriptionChange) -> Result<(), StoreError> {
+        self.apply_subscription_change(change).await.map(|_| ())
+    }
+}
+
+fn timestamp(value: &Value) -> Option<NaiveDateTime> {
+    value
+        .as_i64()
+        .and_then(|t| DateTime::from_timestamp(t, 0))
+        .map(|t| t.naive_utc())
+}
+
+fn text(value: &Value) -> Option<String> {
+    value.as_str().map(|v| v.to_string())
+}
+
+/// Applies a verified webhook event once. Replays of processed events are
+/// acknowledged without applying them again. Storage failures answer 5xx and
+/// leave the event unprocessed so the provider's retry applies it.
+pub async fn process_event(ledger: &impl BillingLedger, event: &Value) -> Result<(), ApiError> {
+    let (Some(event_id), Some(event_type), Some(event_at)) = (
+        text(&event["id"]),
+        text(&event["type"]),
+        timestamp(&event["created"]),
+    ) else {
+        return Err(ApiError::invalid("The event has no id, type or created time"));
+    };
+
+    if ledger.is_event_processed(event_id.clone()).await? {
+        return Ok(());
+    }
+
+    let object = &event["data"]["object"];
+    let result = match event_type.as_str() {
+        "checkout.session.completed" => {
+            let (Some(user_id), Some(customer), Some(subscription), Some(plan)) = (
+                text(&object["client_reference_id"]),
+                text(&object["customer"]),
+                text(&object["subscription"]),
+                text(&object["metadata"]["plan"]),
+            ) else {
+                return Err(ApiError::invalid("The checkout session is missing fields"));
+            };
+
+            ledger
+                .record_checkout(user_id, customer, subscription, plan, event_at)
+                .await
+        }
+        "customer.subscription.created" | "customer.subscription.updated" | "customer.subscription.deleted" => {
+            let (Some(subscription), Some(status)) = (text(&object["id"]), text(&object["status"])) else {
+                return Err(ApiError::invalid("The subscription has no id or status"));
+            };
+
+            let change = SubscriptionChange {
+                provider_subscription_id: subscription,
+                status,
+                price_id: text(&object["items"]["data"][0]["price"]["id"]),
+                current_period_end: timestamp(&object["current_period_end"]),
+                event_at,
+            };
+            ledger.record_subscription_change(change).await
+        }
+        "invoice.payment_failed" => {
+            let Some(subscription) = text(&object["subscription"]) else {
+                return Err(ApiError::invalid("The invoice has no subscription"));
+            };
+
+            let change = SubscriptionChange {
+                provider_subscription_id: subscription,
+                status: PAST_DUE.to_string(),
+                price_id: None,
+                current_period_end: None,
+                event_at,
+            };
+            ledger.record_subscription_change(change).await
+        }
+        // Other events are acknowledged and ignored
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        println!("Billing event {} failed: {}", event_id, e);
+        return Err(e.into());
+    }
+
+    ledger.mark_event_processed(event_id, event_type).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Mutex};
+
+    use poem::http::StatusCode;
+
+    use super::*;
+    use crate::test_server::StubServer;
+
+    fn config(provider_url: &str) -> PaymentConfig {
+        PaymentConfig {
+            provider_url: provider_url.to_string(),
+            api_key: "sk_test".to_string(),
+            webhook_secret: "whsec_test".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn creates_checkout_sessions() {
+        let provider = StubServer::start(|_| {
+            (200, r#"{"id":"cs_1","url":"https://pay.example.com/cs_1"}"#.to_string())
+        })
+        .await;
+
+        let session = create_checkout_session(
+            &reqwest::Client::new(),
+            &config(&provider.url),
+            "price_pro",
+            "user-1",
+            "Pro",
+            "https://app.example.com/billing",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(session.id, "cs_1");
+        assert_eq!(session.url, "https://pay.example.com/cs_1");
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/v1/checkout/sessions");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer sk_test"));
+
+        let form: Vec<(String, String)> = url::form_urlencoded::parse(requests[0].body.as_bytes())
+            .into_owned()
+            .collect();
+        assert!(form.contains(&("line_items[0][price]".to_string(), "price_pro".to_string())));
+        assert!(form.contains(&("client_reference_id".to_string(), "user-1".to_string())));
+        assert!(form.contains(&("metadata[plan]".to_string(), "Pro".to_string())));
+        assert!(form.contains(&(
+            "success_url".to_string(),
+            "https://app.example.com/billing?checkout=success".to_string()
+        )));
+    }
+
+    #[tokio::test]
+    async fn cancels_subscriptions() {
+        let provider = StubServer::start(|_| (200, "{}".to_string())).await;
+
+        cancel_subscription(&reqwest::Client::new(), &config(&provider.url), "sub_1")
+            .await
+            .unwrap();
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "DELETE");
+        assert_eq!(requests[0].path, "/v1/subscriptions/sub_1");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer sk_test"));
+    }
+
+    #[tokio::test]
+    async fn provider_errors_fail() {
+        let provider = StubServer::start(|_| (402, r#"{"error":{"message":"card declined"}}"#.to_string())).await;
+        let client = reqwest::Client::new();
+        let config = config(&provider.url);
+
+        assert!(create_checkout_session(&client, &config, "price_pro", "user-1", "Pro", "https://app.example.com")
+            .await
+            .is_err());
+        assert!(cancel_subscription(&client, &config, "sub_1").await.is_err());
+    }
+
+    #[derive(Default)]
+    struct MemoryLedger {
+        processed: Mutex<HashSet<String>>,
+        checkouts: Mutex<Vec<String>>,
+        changes: Mutex<Vec<String>>,
+        fail_changes: bool,
+    }
+
+    #[poem::async_trait]
+    impl BillingLedger for MemoryLedger {
+        async fn is_event_processed(&self, event_id: String) -> Result<bool, StoreError> {
+            Ok(self.processed.lock().unwrap().contains(&event_id))
+        }
+
+        async fn mark_event_processed(&self, event_id: String, _event_type: String) -> Result<(), StoreError> {
+            self.processed.lock().unwrap().insert(event_id);
+            Ok(())
+        }
+
+        async fn record_checkout(
+            &self,
+            user_id: String,
+            _customer_id: String,
+            subscription_id: String,
+            plan_name: String,
+            _event_at: NaiveDateTime,
+        ) -> Result<(), StoreError> {
+            self.checkouts
+                .lock()
+                .unwrap()
+                .push(format!("{} {} {}", user_id, subscription_id, plan_name));
+            Ok(())
+        }
+
+        async fn record_subscription_change(&self, change: SubscriptionChange) -> Result<(), StoreError> {
+            if self.fail_changes {
+                return Err(StoreError::PoolExhausted);
+            }
+            self.changes
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", change.provider_subscription_id, change.status));
+            Ok(())
+        }
+    }
+
+    fn checkout_completed() -> Value {
+        serde_json::json!({
+            "id": "evt_1",
+            "type": "checkout.session.completed",
+            "created": 1_700_000_000,
+            "data": { "object": {
+                "client_reference_id": "user-1",
+                "customer": "cus_1",
+                "subscription": "sub_1",
+                "metadata": { "plan": "Pro" }
+            }}
+        })
+    }
+
+    #[tokio::test]
+    async fn replayed_events_apply_once() {
+        let ledger = MemoryLedger::default();
+        let event = checkout_completed();
+
+        process_event(&ledger, &event).await.unwrap();
+        process_event(&ledger, &event).await.unwrap();
+
+        assert_eq!(*ledger.checkouts.lock().unwrap(), vec!["user-1 sub_1 Pro".to_string()]);
+        assert!(ledger.processed.lock().unwrap().contains("evt_1"));
+    }
+
+    #[tokio::test]
+    async fn failed_events_stay_unprocessed_for_retries() {
+        let ledger = MemoryLedger { fail_changes: true, ..Default::default() };
+        let event = serde_json::json!({
+            "id": "evt_2",
+            "type": "invoice.payment_failed",
+            "created": 1_700_000_000,
+            "data": { "object": { "subscription": "sub_1" } }
+        });
+
+        assert!(process_event(&ledger, &event).await.unwrap_err().status.is_server_error());
+        assert!(ledger.processed.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_events() {
+        let ledger = MemoryLedger::default();
+        let mut event = checkout_completed();
+        event["data"]["object"]["metadata"] = serde_json::json!({});
+
+        assert_eq!(process_event(&ledger, &event).await.unwrap_err().status, StatusCode::BAD_REQUEST);
+        assert!(ledger.checkouts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let body = br#"{"id":"evt_1"}"#;
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"whsec_test").unwrap();
+        mac.update(b"1700000000.");
+        mac.update(body);
+        let header = format!("t=1700000000,v1={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(verify_signature("whsec_test", &header, body, 1_700_000_100));
+        assert!(!verify_signature("whsec_other", &header, body, 1_700_000_100));
+        assert!(!verify_signature("whsec_test", &header, body, 1_700_001_000));
+    }
+}
@@ -0,0 +1,67 @@
+use std::env;
+
+use ipnet::IpNet;
+
+/// The payment provider's API and webhook credentials.
+pub struct PaymentConfig {
+    pub provider_url: String,
+    pub api_key: String,
+    pub webhook_secret: String,
+}
+
+pub struct Config {
+    /// Billing answers 503 without it
+    pub payment: Option<PaymentConfig>,
+    /// OAuth client id Google ID tokens must be issued for, Google sign in
+    /// answers 503 without it
+    pub google_client_id: Option<String>,
+    pub google_jwks_url: String,
+    /// Shares rate limits between instances, kept in memory without it
+    pub redis_url: Option<String>,
+    /// Proxies in front of the API, whose `X-Forwarded-For` is believed
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        // Billing is on once there is an api key, which needs the rest
+        let payment = env::var("PAYMENT_API_KEY").ok().map(|api_key| PaymentConfig {
+            provider_url: env::var("PAYMENT_PROVIDER_URL")
+                .unwrap_or_else(|_| panic!("Please provide payment provider url"))
+                .trim_end_matches('/')
+                .to_string(),
+            api_key,
+            webhook_secret: env::var("PAYMENT_WEBHOOK_SECRET")
+                .unwrap_or_else(|_| panic!("Please provide payment webhook secret")),
+        });
+
+        let google_client_id = env::var("GOOGLE_CLIENT_ID").ok();
+
+        // Overridable so sign in can be tested against a local key server
+        let google_jwks_url = env::var("GOOGLE_JWKS_URL")
+            .unwrap_or_else(|_| "https://www.googleapis.com/oauth2/v3/certs".to_string());
+
+        let redis_url = env::var("REDIS_URL").ok();
+
+        // Addresses or ranges, comma separated
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                p.parse::<IpNet>()
+                    .or_else(|_| p.parse::<std::net::IpAddr>().map(IpNet::from))
+                    .unwrap_or_else(|_| panic!("Invalid trusted proxy {}", p))
+            })
+            .collect();
+
+        Self {
+            payment,
+            google_client_id,
+            google_jwks_url,
+            redis_url,
+            trusted_proxies,
+        }
+    }
+}
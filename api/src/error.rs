@@ -0,0 +1,147 @@
+use std::fmt;
+
+use poem::{
+    http::{header, StatusCode},
+    web::Json,
+    error::ResponseError,
+    IntoResponse, Response,
+};
+use store::error::StoreError;
+
+use crate::request_output::{ErrorDetails, ErrorOutput};
+
+/// An error answered with a code clients can act on, in the same envelope as
+/// every other failure.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError { status, code, message: message.into() }
+    }
+
+    /// Input the handler refuses before reaching the store.
+    pub fn invalid(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid_input", message)
+    }
+
+    /// A service we rely on, like the mailer or payment provider, failed.
+    pub fn upstream(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_GATEWAY, "upstream_failed", message)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ResponseError for ApiError {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn as_response(&self) -> Response {
+        envelope(self.status, self.code, self.message.clone())
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::NotFound => ApiError::new(StatusCode::NOT_FOUND, "not_found", "Not found"),
+            StoreError::Conflict(reason) => ApiError::new(StatusCode::CONFLICT, "conflict", sentence(reason)),
+            StoreError::Unauthorized => ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", "Invalid credentials"),
+            StoreError::Invalid(reason) => ApiError::invalid(sentence(reason)),
+            StoreError::Quota(quota) => ApiError::new(StatusCode::PAYMENT_REQUIRED, "quota_exceeded", quota.to_string()),
+            // What a database that is down or overloaded looks like from here
+            StoreError::PoolExhausted => {
+                println!("Error: {}", e);
+                ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "database_unavailable", "The database is unavailable, please try again")
+            }
+            // Details stay in the logs
+            StoreError::Database(_) => {
+                println!("Error: {}", e);
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "database_error", "Something went wrong, please try again")
+            }
+        }
+    }
+}
+
+/// The plain text answer of a page opened from an emailed link, for when the
+/// store fails. Unknown or used tokens get `not_found`, anything else the
+/// status and message it would get from the API.
+pub fn link_page_error(e: StoreError, not_found: &'static str) -> Response {
+    let e = match e {
+        StoreError::NotFound => ApiError::new(StatusCode::NOT_FOUND, "not_found", not_found),
+        e => ApiError::from(e),
+    };
+    Response::builder().status(e.status).body(e.message)
+}
+
+fn sentence(reason: &str) -> String {
+    let mut chars = reason.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn envelope(status: StatusCode, code: &str, message: String) -> Response {
+    Json(ErrorOutput {
+        error: ErrorDetails { code: code.to_string(), message },
+        success: false,
+    })
+    .with_status(status)
+    .into_response()
+}
+
+/// The code for errors raised without one, from their status.
+fn status_code_name(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "invalid_input",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        StatusCode::BAD_GATEWAY => "upstream_error",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        s if s.is_client_error() => "invalid_request",
+        _ => "internal_error",
+    }
+}
+
+/// Gives every error the `ErrorOutput` envelope, keeping headers such as
+/// `Retry-After` from the original response.
+pub async fn error_envelope(err: poem::Error) -> Response {
+    if err.is::<ApiError>() {
+        return err.into_response();
+    }
+
+    let status = err.status();
+    let message = match err.to_string() {
+        // Errors made from a bare status only repeat it
+        m if m == status.to_string() => status.canonical_reason().unwrap_or("Error").to_string(),
+        m => m,
+    };
+
+    let original = err.into_response();
+    let mut resp = envelope(status, status_code_name(status), message);
+    for (name, value) in original.headers() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            resp.headers_mut().insert(name.clone(), value.clone());
+        }
+    }
+    resp
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{jwk::JwkSet, DecodingKey};
+use tokio::sync::RwLock;
+
+/// Providers rotate signing keys over days, an hour old key set is fine.
+const JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+/// Unknown key ids refetch the set, but at most this often.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+pub type JwksError = Box<dyn std::error::Error + Send + Sync>;
+
+struct CachedKeys {
+    set: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Signing keys published by identity providers, cached per JWKS URL.
+pub struct JwksCache {
+    keys: RwLock<HashMap<String, CachedKeys>>,
+    ttl: Duration,
+    min_refresh: Duration,
+}
+
+impl Default for JwksCache {
+    fn default() -> Self {
+        Self {
+            keys: RwLock::default(),
+            ttl: JWKS_TTL,
+            min_refresh: JWKS_MIN_REFRESH,
+        }
+    }
+}
+
+impl JwksCache {
+    /// The key `kid` from the set at `url`, fetching the set when it is stale
+    /// or does not know the key yet.
+    pub async fn decoding_key(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        kid: &str,
+    ) -> Result<DecodingKey, JwksError> {
+        if let Some(cached) = self.keys.read().await.get(url) {
+            let age = cached.fetched_at.elapsed();
+            if age < self.ttl {
+                if let Some(jwk) = cached.set.find(kid) {
+                    return Ok(DecodingKey::from_jwk(jwk)?);
+                }
+                if age < self.min_refresh {
+                    return Err(format!("Unknown signing key {}", kid).into());
+                }
+            }
+        }
+
+        let set = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await?;
+
+        let key = set.find(kid).map(DecodingKey::from_jwk).transpose()?;
+        self.keys
+            .write()
+            .await
+            .insert(url.to_string(), CachedKeys { set, fetched_at: Instant::now() });
+
+        key.ok_or_else(|| format!("Unknown signing key {}", kid).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use jsonwebtoken::{decode, encode, errors::ErrorKind, Algorithm, EncodingKey, Header, Validation};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::test_server::StubServer;
+
+    // Symmetric "oct" keys keep the tests free of key generation
+    const K1_SECRET: &[u8] = b"first signing key";
+    const K2_SECRET: &[u8] = b"second signing key";
+
+    #[derive(Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: i64,
+    }
+
+    fn jwk(kid: &str, secret: &[u8]) -> serde_json::Value {
+        use base64::Engine;
+        serde_json::json!({
+            "kty": "oct",
+            "kid": kid,
+            "alg": "HS256",
+            "k": base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret),
+        })
+    }
+
+    fn key_set(keys: &[(&str, &[u8])]) -> String {
+        let keys: Vec<_> = keys.iter().map(|(kid, secret)| jwk(kid, secret)).collect();
+        serde_json::json!({ "keys": keys }).to_string()
+    }
+
+    fn token(kid: &str, secret: &[u8], exp: i64) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(kid.to_string());
+        encode(&header, &Claims { sub: "user-1".to_string(), exp }, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn cache(ttl: Duration, min_refresh: Duration) -> JwksCache {
+        JwksCache { ttl, min_refresh, ..JwksCache::default() }
+    }
+
+    /// Serves `first` for the first request and `later` after that.
+    async fn rotating_server(first: String, later: String) -> StubServer {
+        let served = Arc::new(AtomicUsize::new(0));
+        StubServer::start(move |_| {
+            let body = if served.fetch_add(1, Ordering::SeqCst) == 0 { &first } else { &later };
+            (200, body.clone())
+        })
+        .await
+    }
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    #[tokio::test]
+    async fn caches_key_sets() {
+        let server = StubServer::start(|_| (200, key_set(&[("k1", K1_SECRET)]))).await;
+        let url = format!("{}/certs", server.url);
+        let (client, jwks) = (reqwest::Client::new(), JwksCache::default());
+
+        let key = jwks.decoding_key(&client, &url, "k1").await.unwrap();
+        jwks.decoding_key(&client, &url, "k1").await.unwrap();
+
+        assert_eq!(server.requests().len(), 1);
+
+        let claims = decode::<Claims>(&token("k1", K1_SECRET, now() + 60), &key, &Validation::new(Algorithm::HS256))
+            .unwrap()
+            .claims;
+        assert_eq!(claims.sub, "user-1");
+    }
+
+    #[tokio::test]
+    async fn refetches_for_unknown_key_ids() {
+        let server = rotating_server(
+            key_set(&[("k1", K1_SECRET)]),
+            key_set(&[("k1", K1_SECRET), ("k2", K2_SECRET)]),
+        )
+        .await;
+        let url = format!("{}/certs", server.url);
+        let client = reqwest::Client::new();
+        let jwks = cache(JWKS_TTL, Duration::from_millis(100));
+
+        jwks.decoding_key(&client, &url, "k1").await.unwrap();
+
+        // Right after a fetch, unknown ids fail without hitting the provider
+        assert!(jwks.decoding_key(&client, &url, "k2").await.is_err());
+        assert_eq!(server.requests().len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let key = jwks.decoding_key(&client, &url, "k2").await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+
+        assert!(decode::<Claims>(&token("k2", K2_SECRET, now() + 60), &key, &Validation::new(Algorithm::HS256)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_tokens_and_retired_keys() {
+        let server = rotating_server(key_set(&[("k1", K1_SECRET)]), key_set(&[("k2", K2_SECRET)])).await;
+        let url = format!("{}/certs", server.url);
+        let client = reqwest::Client::new();
+        let jwks = cache(Duration::from_millis(100), Duration::from_millis(100));
+
+        let key = jwks.decoding_key(&client, &url, "k1").await.unwrap();
+        let expired = decode::<Claims>(&token("k1", K1_SECRET, now() - 3600), &key, &Validation::new(Algorithm::HS256));
+        assert!(matches!(expired.map_err(|e| e.into_kind()), Err(ErrorKind::ExpiredSignature)));
+
+        // Once the set is stale it is fetched again, and the retired key is gone
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(jwks.decoding_key(&client, &url, "k1").await.is_err());
+        assert_eq!(server.requests().len(), 2);
+    }
+}
@@ -2,23 +2,59 @@ use dotenvy::dotenv;
 use poem::http::Method;
 use poem::{get, listener::TcpListener, post, EndpointExt, Route, Server};
 
+use crate::route::api_key::{create_api_key, get_api_keys, revoke_api_key};
 use crate::route::app::{get_health, get_user, total_unique_users, total_views, total_views_per_page};
-use crate::route::user::{google_auth, logout_user, update_email, update_password};
-use crate::route::website::{create_website, get_avg_resp, get_avg_resp_by_region, get_details_daily, get_details_hourly, get_details_last_hour, get_uptime_percentage, get_uptime_percentage_by_region, get_users_websites, get_website_recent_status};
+use crate::route::audit::get_audit_log;
+use crate::route::docs::{openapi_json, swagger_ui};
+use crate::route::billing::{billing_webhook, cancel, create_checkout, get_subscription, get_usage};
+use crate::route::badge::{response_time_badge, uptime_badge};
+use crate::route::feed::{atom_feed, rss_feed, set_feed_token};
+use crate::route::incident::{add_incident_update, create_incident, delete_incident, get_incident, get_public_incidents, get_users_incidents, set_postmortem, update_incident};
+use crate::route::org::{accept_org_invitation, create_org, get_org_invitations, get_org_members, get_orgs, invite_member, remove_member, revoke_org_invitation, set_member_role, set_org_require_two_factor};
+use crate::route::report::{create_report_schedule, delete_report_schedule, get_monthly_report, get_report_schedules};
+use crate::route::slo::{create_slo, delete_slo, get_slo_alerts, get_slo_status, get_users_slos};
+use crate::route::subscriber::{confirm_subscription, subscribe, unsubscribe};
+use crate::route::sso::{sso_callback, sso_start};
+use crate::route::user::{confirm_email, confirm_email_change, confirm_password_reset, get_identities, get_sessions, google_auth, logout_user, refresh_session, request_password_reset, resend_verification_email, revoke_session, sign_in_second_factor, update_email, update_password};
+use crate::route::two_factor::{confirm_two_factor, disable_two_factor, get_two_factor_status, regenerate_recovery_codes, setup_two_factor};
+use crate::route::website::{create_website, get_avg_resp, get_avg_resp_by_region, get_details_daily, get_details_hourly, get_details_last_hour, get_uptime_percentage, get_uptime_percentage_by_region, get_users_websites, get_website_recent_status, share_website};
 use crate::route::{
     app::{snippet, track},
     user::{create_user, sign_in_user}
 };
+use notifier::{config::Config as MailConfig, mailer::Mailer};
 use poem::middleware::{CookieJarManager, Cors};
 use std::{
     sync::{Arc},
 };
-use store::store::Store;
+use store::{
+    models::api_key::{ANALYTICS_READ, MONITORS_READ, MONITORS_WRITE},
+    store::Store,
+};
+use crate::auth_middleware::KeyScope;
+use crate::config::Config;
+use crate::error::error_envelope;
+use crate::google::GoogleVerifier;
+use crate::quota_cache::QuotaCache;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::sso::SsoProviders;
 
+pub mod access;
 pub mod auth_middleware;
+pub mod billing;
+pub mod config;
+pub mod error;
+pub mod google;
+pub mod jwks;
+pub mod openapi;
+pub mod quota_cache;
+pub mod rate_limit;
 pub mod request_input;
 pub mod request_output;
 pub mod route;
+pub mod sso;
+#[cfg(test)]
+mod test_server;
 
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
@@ -26,6 +62,17 @@ async fn main() -> Result<(), std::io::Error> {
     dotenv().ok();
 
     let s = Arc::new(Store::new().await);
+    let mail_config = Arc::new(MailConfig::from_env().map_err(std::io::Error::other)?);
+    let mailer = Arc::new(Mailer::new(&mail_config).map_err(std::io::Error::other)?);
+    if !mailer.is_configured() {
+        println!("SMTP_HOST is not set, emails won't be sent");
+    }
+    let config = Arc::new(Config::default());
+    let client = reqwest::Client::new();
+    let google = Arc::new(GoogleVerifier::default());
+    let sso = Arc::new(SsoProviders::default());
+    let limiter = Arc::new(RateLimiter::new(config.redis_url.as_deref()).await);
+    let quotas = Arc::new(QuotaCache::default());
 
     let cors = Cors::new()
         .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
@@ -33,29 +80,105 @@ async fn main() -> Result<(), std::io::Error> {
 
     let app = Route::new()
     .at("/api/health", get(get_health))
-        .at("/api/website", post(create_website))
-        .at("/api/website/last_hour", post(get_details_last_hour))
-        .at("/api/website/hourly", post(get_details_hourly))
-        .at("/api/website/daily", post(get_details_daily))
+        .at("/api/openapi.json", get(openapi_json))
+        .at("/api/docs", get(swagger_ui))
+        .at("/api/website", post(create_website).data(KeyScope::website(MONITORS_WRITE)))
+        .at("/api/website/last_hour", post(get_details_last_hour).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/website/hourly", post(get_details_hourly).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/website/daily", post(get_details_daily).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/website/share", post(share_website))
         .at("/api/user/signup", post(create_user))
         .at("/api/user/signin", post(sign_in_user))
+        .at("/api/user/signin/2fa", post(sign_in_second_factor))
         .at("/api/auth/google", post(google_auth))
+        .at("/api/auth/sso/:provider/start", get(sso_start))
+        .at("/api/auth/sso/:provider/callback", get(sso_callback))
         .at("/api/snippet", get(snippet))
         .at("/api/track", post(track))
-        .at("/api/get_status", post(get_website_recent_status))
-        .at("/api/get_total_views_per_page", post(total_views_per_page))
-        .at("/api/get_total_unique_users", post(total_unique_users))
-        .at("/api/get_total_views", post(total_views))
+        .at("/api/get_status", post(get_website_recent_status).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/get_total_views_per_page", post(total_views_per_page).data(KeyScope::website(ANALYTICS_READ)))
+        .at("/api/get_total_unique_users", post(total_unique_users).data(KeyScope::website(ANALYTICS_READ)))
+        .at("/api/get_total_views", post(total_views).data(KeyScope::website(ANALYTICS_READ)))
         .at("/api/get_user", get(get_user))
         .at("/api/user/logout", post(logout_user))
-        .at("/api/user/get_all_websites", get(get_users_websites))
-        .at("/api/get_avg_resp", post(get_avg_resp))
-        .at("/api/get_avg_resp_region", post(get_avg_resp_by_region))
-        .at("/api/get_uptime_percentage", post(get_uptime_percentage))
-        .at("/api/get_uptime_percentage_region", post(get_uptime_percentage_by_region))
+        .at("/api/user/refresh", post(refresh_session))
+        .at("/api/user/sessions", get(get_sessions))
+        .at("/api/user/sessions/revoke", post(revoke_session))
+        .at("/api/user/identities", get(get_identities))
+        .at("/api/user/2fa", get(get_two_factor_status))
+        .at("/api/user/2fa/setup", post(setup_two_factor))
+        .at("/api/user/2fa/confirm", post(confirm_two_factor))
+        .at("/api/user/2fa/recovery_codes", post(regenerate_recovery_codes))
+        .at("/api/user/2fa/disable", post(disable_two_factor))
+        .at("/api/user/api_key", post(create_api_key))
+        .at("/api/user/api_keys", get(get_api_keys))
+        .at("/api/user/api_key/revoke", post(revoke_api_key))
+        .at("/api/org", post(create_org))
+        .at("/api/orgs", get(get_orgs))
+        .at("/api/org/members", post(get_org_members))
+        .at("/api/org/invite", post(invite_member))
+        .at("/api/org/invitations", post(get_org_invitations))
+        .at("/api/org/invitation/revoke", post(revoke_org_invitation))
+        .at("/api/org/invitation/accept", post(accept_org_invitation))
+        .at("/api/org/member/role", post(set_member_role))
+        .at("/api/org/member/remove", post(remove_member))
+        .at("/api/org/two_factor", post(set_org_require_two_factor))
+        .at("/api/audit", get(get_audit_log))
+        .at("/api/user/get_all_websites", get(get_users_websites).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/get_avg_resp", post(get_avg_resp).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/get_avg_resp_region", post(get_avg_resp_by_region).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/get_uptime_percentage", post(get_uptime_percentage).data(KeyScope::website(MONITORS_READ)))
+        .at("/api/get_uptime_percentage_region", post(get_uptime_percentage_by_region).data(KeyScope::website(MONITORS_READ)))
         .at("/api/update_email", post(update_email))
         .at("/api/update_password", post(update_password))
+        .at("/api/user/verify_email/send", post(resend_verification_email))
+        .at("/api/user/verify_email/confirm", get(confirm_email))
+        .at("/api/user/email_change/confirm", get(confirm_email_change))
+        .at("/api/user/password_reset", post(request_password_reset))
+        .at("/api/user/password_reset/confirm", post(confirm_password_reset))
+        .at("/api/subscribe", post(subscribe))
+        .at("/api/subscribe/confirm", get(confirm_subscription))
+        .at("/api/unsubscribe", get(unsubscribe))
+        .at("/api/incident", post(create_incident).data(KeyScope::account(MONITORS_WRITE)))
+        .at("/api/incidents", get(get_users_incidents).data(KeyScope::account(MONITORS_READ)))
+        .at("/api/incident/get", post(get_incident).data(KeyScope::account(MONITORS_READ)))
+        .at("/api/incident/update", post(update_incident).data(KeyScope::account(MONITORS_WRITE)))
+        .at("/api/incident/add_update", post(add_incident_update).data(KeyScope::account(MONITORS_WRITE)))
+        .at("/api/incident/delete", post(delete_incident).data(KeyScope::account(MONITORS_WRITE)))
+        .at("/api/incident/postmortem", post(set_postmortem).data(KeyScope::account(MONITORS_WRITE)))
+        .at("/api/status/incidents", get(get_public_incidents))
+        .at("/api/feed/atom", get(atom_feed))
+        .at("/api/feed/rss", get(rss_feed))
+        .at("/api/feed/token", post(set_feed_token))
+        .at("/api/badge/uptime", get(uptime_badge))
+        .at("/api/badge/response_time", get(response_time_badge))
+        .at("/api/slo", post(create_slo).data(KeyScope::account(MONITORS_WRITE)))
+        .at("/api/slos", get(get_users_slos).data(KeyScope::account(MONITORS_READ)))
+        .at("/api/slo/status", post(get_slo_status).data(KeyScope::account(MONITORS_READ)))
+        .at("/api/slo/alerts", post(get_slo_alerts).data(KeyScope::account(MONITORS_READ)))
+        .at("/api/slo/delete", post(delete_slo).data(KeyScope::account(MONITORS_WRITE)))
+        .at("/api/report/monthly", get(get_monthly_report).data(KeyScope::account(MONITORS_READ)))
+        .at("/api/report/schedule", post(create_report_schedule))
+        .at("/api/report/schedules", get(get_report_schedules))
+        .at("/api/report/schedule/delete", post(delete_report_schedule))
+        .at("/api/billing/checkout", post(create_checkout))
+        .at("/api/billing/subscription", get(get_subscription))
+        .at("/api/billing/cancel", post(cancel))
+        .at("/api/billing/webhook", post(billing_webhook))
+        .at("/api/usage", get(get_usage))
         .data(s)
+        .data(mailer)
+        .data(mail_config)
+        .data(client)
+        .data(google)
+        .data(sso)
+        .data(quotas)
+        .data(limiter.clone())
+        .with(RateLimit::new(limiter))
+        // Outside the rate limit, which needs the trusted proxies for client IPs
+        .data(config)
+        // Inside CORS so error responses get its headers too
+        .catch_all_error(error_envelope)
         .with(cors)
         .with(CookieJarManager::new());
 
@@ -0,0 +1,165 @@
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme},
+        ContentBuilder, Ref, RefOr, Response, ResponseBuilder,
+    },
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::{
+    request_output::ErrorOutput,
+    route::{
+        api_key, app, audit, badge, billing, feed, incident, org, report, slo, sso, subscriber, two_factor, user,
+        website,
+    },
+};
+
+/// The OpenAPI document for every route in `main`, served at
+/// `/api/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Nexus API",
+        description = "Uptime monitoring, status pages and web analytics.\n\nFailed requests answer with `ErrorOutput`, whose `code` is stable."
+    ),
+    paths(
+        user::create_user, user::sign_in_user, user::google_auth, user::sign_in_second_factor, user::refresh_session, user::get_sessions, user::revoke_session, user::get_identities, user::resend_verification_email, user::confirm_email, user::update_email, user::confirm_email_change, user::request_password_reset, user::confirm_password_reset, user::update_password, user::logout_user,
+        sso::sso_start, sso::sso_callback,
+        two_factor::get_two_factor_status, two_factor::setup_two_factor, two_factor::confirm_two_factor, two_factor::regenerate_recovery_codes, two_factor::disable_two_factor,
+        api_key::create_api_key, api_key::get_api_keys, api_key::revoke_api_key,
+        org::create_org, org::get_orgs, org::get_org_members, org::invite_member, org::get_org_invitations, org::revoke_org_invitation, org::accept_org_invitation, org::set_member_role, org::remove_member, org::set_org_require_two_factor,
+        audit::get_audit_log,
+        website::create_website, website::get_website_recent_status, website::get_details_hourly, website::get_details_daily, website::get_details_last_hour, website::get_users_websites, website::share_website, website::get_uptime_percentage, website::get_uptime_percentage_by_region, website::get_avg_resp, website::get_avg_resp_by_region,
+        app::snippet, app::track, app::total_views_per_page, app::total_unique_users, app::total_views, app::get_user, app::get_health,
+        incident::create_incident, incident::get_users_incidents, incident::get_incident, incident::update_incident, incident::add_incident_update, incident::delete_incident, incident::set_postmortem, incident::get_public_incidents,
+        subscriber::subscribe, subscriber::confirm_subscription, subscriber::unsubscribe,
+        feed::atom_feed, feed::rss_feed, feed::set_feed_token,
+        badge::uptime_badge, badge::response_time_badge,
+        slo::create_slo, slo::get_users_slos, slo::get_slo_status, slo::get_slo_alerts, slo::delete_slo,
+        report::get_monthly_report, report::create_report_schedule, report::get_report_schedules, report::delete_report_schedule,
+        billing::create_checkout, billing::get_subscription, billing::cancel, billing::get_usage, billing::billing_webhook,
+    ),
+    components(schemas(ErrorOutput)),
+    modifiers(&Conventions),
+    tags(
+        (name = "auth", description = "Signing up, in and out"),
+        (name = "users", description = "The signed in account"),
+        (name = "two_factor", description = "TOTP two-factor authentication"),
+        (name = "api_keys", description = "Keys for programmatic access"),
+        (name = "organizations", description = "Teams sharing websites"),
+        (name = "audit", description = "Who changed what"),
+        (name = "websites", description = "Monitored websites"),
+        (name = "analytics", description = "Uptime, response times and page views"),
+        (name = "incidents", description = "Incidents shown on status pages"),
+        (name = "status_pages", description = "Public subscriptions, feeds and badges"),
+        (name = "slos", description = "Service level objectives"),
+        (name = "reports", description = "Monthly availability reports"),
+        (name = "billing", description = "Plans, subscriptions and usage"),
+    )
+)]
+pub struct ApiDoc;
+
+/// What applies to every operation unless it says otherwise: a session or API
+/// key, and the error envelope.
+struct Conventions;
+
+impl Modify for Conventions {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("An access token from signing in, or an API key"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme("cookie", SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("jwt"))));
+
+        openapi.security = Some(vec![
+            SecurityRequirement::new("bearer", Vec::<String>::new()),
+            SecurityRequirement::new("cookie", Vec::<String>::new()),
+        ]);
+
+        let error: RefOr<Response> = ResponseBuilder::new()
+            .description("Failed, see `error.code`")
+            .content(
+                "application/json",
+                ContentBuilder::new().schema(Some(Ref::from_schema_name(ErrorOutput::name()))).build(),
+            )
+            .build()
+            .into();
+
+        for item in openapi.paths.paths.values_mut() {
+            for operation in [&mut item.get, &mut item.post].into_iter().flatten() {
+                operation.responses.responses.entry("default".to_string()).or_insert_with(|| error.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    /// Routes that serve the docs themselves.
+    const UNDOCUMENTED: [&str; 2] = ["/api/openapi.json", "/api/docs"];
+
+    // Every `.at("...", get(..))` and `.at("...", post(..))` in `main`, as
+    // "GET /api/..." with OpenAPI style `{param}`s
+    fn registered_routes() -> BTreeSet<String> {
+        include_str!("main.rs")
+            .split(".at(\"")
+            .skip(1)
+            .filter_map(|route| {
+                let (path, rest) = route.split_once('"').unwrap();
+                if UNDOCUMENTED.contains(&path) {
+                    return None;
+                }
+
+                let method = match rest.trim_start_matches(',').trim_start() {
+                    r if r.starts_with("get(") => "GET",
+                    r if r.starts_with("post(") => "POST",
+                    _ => panic!("Can't tell the method of {}", path),
+                };
+                let path = path
+                    .split('/')
+                    .map(|s| match s.strip_prefix(':') {
+                        Some(param) => format!("{{{}}}", param),
+                        None => s.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                Some(format!("{} {}", method, path))
+            })
+            .collect()
+    }
+
+    fn documented_routes() -> BTreeSet<String> {
+        let mut routes = BTreeSet::new();
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            if item.get.is_some() {
+                routes.insert(format!("GET {}", path));
+            }
+            if item.post.is_some() {
+                routes.insert(format!("POST {}", path));
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn documents_every_route() {
+        let registered = registered_routes();
+        let documented = documented_routes();
+
+        let undocumented: Vec<_> = registered.difference(&documented).collect();
+        let unregistered: Vec<_> = documented.difference(&registered).collect();
+        assert!(undocumented.is_empty(), "Missing from the OpenAPI document: {:?}", undocumented);
+        assert!(unregistered.is_empty(), "Documented but not routed: {:?}", unregistered);
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use store::{error::StoreError, models::usage::EventQuota, store::Store};
+use tokio::sync::RwLock;
+
+/// Plan changes reach page view metering within this long.
+const QUOTA_TTL: Duration = Duration::from_secs(60);
+
+struct CachedQuota {
+    quota: EventQuota,
+    fetched_at: Instant,
+}
+
+/// Event quotas per organization, so tracking a page view doesn't look up the
+/// plan and billing period each time.
+pub struct QuotaCache {
+    quotas: RwLock<HashMap<String, CachedQuota>>,
+    ttl: Duration,
+}
+
+impl Default for QuotaCache {
+    fn default() -> Self {
+        Self {
+            quotas: RwLock::default(),
+            ttl: QUOTA_TTL,
+        }
+    }
+}
+
+impl QuotaCache {
+    /// The organization's quota, looked up again once it is stale or its
+    /// billing period has ended.
+    pub async fn event_quota(&self, s: &Store, org_id: &str) -> Result<EventQuota, StoreError> {
+        let today = Utc::now().date_naive();
+
+        if let Some(cached) = self.quotas.read().await.get(org_id) {
+            if cached.fetched_at.elapsed() < self.ttl && today < cached.quota.period_end {
+                return Ok(cached.quota.clone());
+            }
+        }
+
+        let quota = s.get_event_quota(org_id.to_string()).await?;
+
+        let mut quotas = self.quotas.write().await;
+        // Entries of organizations that stopped tracking go once stale
+        quotas.retain(|_, cached| cached.fetched_at.elapsed() < self.ttl);
+        quotas.insert(org_id.to_string(), CachedQuota { quota: quota.clone(), fetched_at: Instant::now() });
+
+        Ok(quota)
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use poem::{
+    http::{header, StatusCode},
+    Endpoint, Error, IntoResponse, Middleware, Request, Response, Result,
+};
+use redis::{aio::MultiplexedConnection, AsyncCommands, Script};
+use sha2::{Digest, Sha256};
+
+use crate::auth_middleware::client_ip;
+
+/// A token bucket holding `capacity` requests that refills completely over
+/// `period`.
+#[derive(Clone, Copy)]
+pub struct Limit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl Limit {
+    pub const fn per_minute(capacity: u32) -> Self {
+        Limit { capacity, period: Duration::from_secs(60) }
+    }
+
+    pub const fn per_hour(capacity: u32) -> Self {
+        Limit { capacity, period: Duration::from_secs(3600) }
+    }
+}
+
+/// Sign in attempts, per IP.
+pub const AUTH: Limit = Limit::per_minute(20);
+/// Account creation and anything that sends an email, per IP.
+pub const SIGNUP: Limit = Limit::per_hour(10);
+/// Page views from the tracking snippet, per visitor IP.
+pub const TRACK: Limit = Limit::per_minute(300);
+/// Page views from the tracking snippet, per website.
+pub const TRACK_PER_WEBSITE: Limit = Limit::per_minute(6000);
+/// Requests made with an API key, per key.
+pub const API_KEY: Limit = Limit::per_minute(600);
+/// Everything else, per IP.
+pub const DEFAULT: Limit = Limit::per_minute(1200);
+
+/// Failed sign ins from one IP, or 2FA codes, before the account is locked.
+pub const MAX_FAILURES: u32 = 5;
+/// Failed sign ins from every IP together before the account is locked, so
+/// guesses spread over many addresses still run out.
+pub const MAX_ACCOUNT_FAILURES: u32 = 50;
+/// How long failures are remembered, counted from the latest one, and so how
+/// long a lockout lasts.
+pub const LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Entries kept in memory before expired ones are dropped. When that frees
+/// too little, the least recently touched go until the map is down to
+/// `MEMORY_KEEP`, so a flood of new keys can't grow it without bound.
+const MEMORY_PRUNE_AT: usize = 10_000;
+const MEMORY_KEEP: usize = MEMORY_PRUNE_AT * 9 / 10;
+
+// Takes a token from the bucket in KEYS[1], using the Redis clock so every
+// API instance agrees. Returns 0 if allowed, otherwise the seconds to wait.
+const TOKEN_BUCKET: &str = r"
+local capacity = tonumber(ARGV[1])
+local period = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'at')
+local tokens = tonumber(bucket[1]) or capacity
+local at = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + (now - at) * capacity / period)
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) * period / capacity / 1000)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'at', now)
+redis.call('PEXPIRE', KEYS[1], period)
+return wait
+";
+
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+    period: Duration,
+}
+
+struct Failures {
+    count: u32,
+    until: Instant,
+}
+
+/// Drops expired entries once `map` is full, then the oldest by `touched`
+/// until it is down to `MEMORY_KEEP`.
+fn prune<V>(map: &mut HashMap<String, V>, expired: impl Fn(&V) -> bool, touched: impl Fn(&V) -> Instant) {
+    if map.len() < MEMORY_PRUNE_AT {
+        return;
+    }
+
+    map.retain(|_, v| !expired(v));
+    if map.len() <= MEMORY_KEEP {
+        return;
+    }
+
+    let mut by_age: Vec<(Instant, String)> = map.iter().map(|(k, v)| (touched(v), k.clone())).collect();
+    by_age.sort_unstable();
+    for (_, key) in by_age.into_iter().take(map.len() - MEMORY_KEEP) {
+        map.remove(&key);
+    }
+}
+
+/// Rate limits and sign in lockouts shared by every API instance through
+/// Redis, or kept in memory when there is no Redis or it fails.
+pub struct RateLimiter {
+    redis: Option<MultiplexedConnection>,
+    script: Script,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl RateLimiter {
+    pub async fn new(redis_url: Option<&str>) -> Self {
+        let redis = match redis_url {
+            Some(url) => match redis::Client::open(url) {
+                Ok(client) => client
+                    .get_multiplexed_async_connection()
+                    .await
+                    .map_err(|e| println!("Rate limiting in memory, Redis unavailable: {}", e))
+                    .ok(),
+                Err(e) => {
+                    println!("Rate limiting in memory, invalid Redis url: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        RateLimiter {
+            redis,
+            script: Script::new(TOKEN_BUCKET),
+            buckets: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a request from `key`'s bucket. Fails with the seconds until one
+    /// is available.
+    pub async fn check(&self, key: &str, limit: Limit) -> Result<(), u64> {
+        if let Some(mut conn) = self.redis.clone() {
+            let wait = self
+                .script
+                .key(format!("ratelimit:{}", key))
+                .arg(limit.capacity)
+                .arg(limit.period.as_millis() as u64)
+                .invoke_async::<u64>(&mut conn)
+                .await;
+
+            match wait {
+                Ok(0) => return Ok(()),
+                Ok(wait) => return Err(wait),
+                Err(e) => println!("Rate limiting in memory, Redis failed: {}", e),
+            }
+        }
+
+        self.check_in_memory(key, limit)
+    }
+
+    fn check_in_memory(&self, key: &str, limit: Limit) -> Result<(), u64> {
+        let now = Instant::now();
+        let capacity = limit.capacity as f64;
+        let period = limit.period.as_secs_f64();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        prune(&mut buckets, |b| now.duration_since(b.at) >= b.period, |b| b.at);
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            at: now,
+            period: limit.period,
+        });
+
+        let refilled = now.duration_since(bucket.at).as_secs_f64() * capacity / period;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) * period / capacity).ceil() as u64)
+        }
+    }
+
+    /// Seconds left on `key`'s lockout, if it has had `max_failures` or more.
+    pub async fn locked_for(&self, key: &str, max_failures: u32) -> Option<u64> {
+        if let Some(mut conn) = self.redis.clone() {
+            let key = format!("lockout:{}", key);
+            let res = redis::pipe()
+                .get(&key)
+                .ttl(&key)
+                .query_async::<(Option<u32>, i64)>(&mut conn)
+                .await;
+
+            match res {
+                Ok((Some(count), ttl)) if count >= max_failures && ttl > 0 => return Some(ttl as u64),
+                Ok(_) => return None,
+                Err(e) => println!("Lockouts in memory, Redis failed: {}", e),
+            }
+        }
+
+        let now = Instant::now();
+        self.failures
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|f| f.count >= max_failures && f.until > now)
+            .map(|f| f.until.duration_since(now).as_secs().max(1))
+    }
+
+    /// Counts a failed attempt against `key`.
+    pub async fn record_failure(&self, key: &str) {
+        if let Some(mut conn) = self.redis.clone() {
+            let key = format!("lockout:{}", key);
+            let res = redis::pipe()
+                .atomic()
+                .incr(&key, 1)
+                .ignore()
+                .expire(&key, LOCKOUT.as_secs() as i64)
+                .ignore()
+                .query_async::<()>(&mut conn)
+                .await;
+
+            match res {
+                Ok(()) => return,
+                Err(e) => println!("Lockouts in memory, Redis failed: {}", e),
+            }
+        }
+
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap();
+
+        prune(&mut failures, |f| f.until <= now, |f| f.until);
+
+        let entry = failures.entry(key.to_string()).or_insert(Failures { count: 0, until: now });
+        if entry.until <= now {
+            entry.count = 0;
+        }
+        entry.count += 1;
+        entry.until = now + LOCKOUT;
+    }
+
+    /// Forgets `key`'s failures after a successful attempt.
+    pub async fn clear_failures(&self, key: &str) {
+        if let Some(mut conn) = self.redis.clone() {
+            let res = conn.del::<_, ()>(format!("lockout:{}", key)).await;
+            if let Err(e) = res {
+                println!("Lockouts in memory, Redis failed: {}", e);
+            }
+        }
+
+        self.failures.lock().unwrap().remove(key);
+    }
+}
+
+/// The 429 answer, telling the client when to try again.
+pub fn too_many_requests(retry_after: u64) -> Error {
+    Error::from_response(
+        Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(header::RETRY_AFTER, retry_after.max(1))
+            .body("Too many requests"),
+    )
+}
+
+/// The bucket for requests made with a valid API key, checked once the key
+/// is looked up. Hashed so full keys never reach Redis.
+pub fn api_key_bucket(key: &str) -> String {
+    format!("key:{}", hex::encode(Sha256::digest(key.as_bytes())))
+}
+
+/// The bucket a request counts against: its IP, with stricter limits on the
+/// public auth and ingest endpoints. Requests with a valid API key are also
+/// limited per key by `AuthUser`, and per-website tracking limits are applied
+/// by `track`, which has the page URL.
+fn bucket_for(req: &Request) -> (String, Limit) {
+    let ip = client_ip(req).unwrap_or_default();
+
+    match req.uri().path() {
+        "/api/user/signin" | "/api/user/signin/2fa" | "/api/auth/google" => (format!("auth:{}", ip), AUTH),
+        path if path.starts_with("/api/auth/sso/") => (format!("auth:{}", ip), AUTH),
+        "/api/user/signup"
+        | "/api/user/password_reset"
+        | "/api/user/verify_email/send"
+        | "/api/subscribe" => (format!("signup:{}", ip), SIGNUP),
+        "/api/track" => (format!("track:{}", ip), TRACK),
+        _ => (format!("ip:{}", ip), DEFAULT),
+    }
+}
+
+/// Middleware answering 429 with `Retry-After` once a client runs out of
+/// requests.
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        RateLimit { limiter }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for RateLimit {
+    type Output = RateLimitEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RateLimitEndpoint { inner: ep, limiter: self.limiter.clone() }
+    }
+}
+
+pub struct RateLimitEndpoint<E> {
+    inner: E,
+    limiter: Arc<RateLimiter>,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for RateLimitEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let (key, limit) = bucket_for(&req);
+
+        if let Err(retry_after) = self.limiter.check(&key, limit).await {
+            return Err(too_many_requests(retry_after));
+        }
+
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use store::models::api_key::API_KEY_MARKER;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn buckets_run_out_and_are_separate() {
+        let limiter = RateLimiter::new(None).await;
+        let limit = Limit::per_minute(3);
+
+        for _ in 0..3 {
+            assert!(limiter.check("ip:1.2.3.4", limit).await.is_ok());
+        }
+        let wait = limiter.check("ip:1.2.3.4", limit).await.unwrap_err();
+        assert!((1..=20).contains(&wait), "waited {}", wait);
+
+        assert!(limiter.check("ip:5.6.7.8", limit).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn buckets_refill_over_the_period() {
+        let limiter = RateLimiter::new(None).await;
+        let limit = Limit { capacity: 1, period: Duration::from_millis(50) };
+
+        assert!(limiter.check("ip:1.2.3.4", limit).await.is_ok());
+        assert!(limiter.check("ip:1.2.3.4", limit).await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(limiter.check("ip:1.2.3.4", limit).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn failures_lock_until_cleared() {
+        let limiter = RateLimiter::new(None).await;
+
+        for _ in 0..MAX_FAILURES - 1 {
+            limiter.record_failure("signin:a@example.com").await;
+        }
+        assert!(limiter.locked_for("signin:a@example.com", MAX_FAILURES).await.is_none());
+
+        limiter.record_failure("signin:a@example.com").await;
+        let wait = limiter.locked_for("signin:a@example.com", MAX_FAILURES).await.unwrap();
+        assert!(wait <= LOCKOUT.as_secs());
+        // A looser cap on the same counter isn't reached yet
+        assert!(limiter.locked_for("signin:a@example.com", MAX_ACCOUNT_FAILURES).await.is_none());
+        assert!(limiter.locked_for("signin:b@example.com", MAX_FAILURES).await.is_none());
+
+        limiter.clear_failures("signin:a@example.com").await;
+        assert!(limiter.locked_for("signin:a@example.com", MAX_FAILURES).await.is_none());
+    }
+
+    #[test]
+    fn made_up_api_keys_get_the_path_bucket() {
+        let req = Request::builder()
+            .uri_str("/api/user/signin")
+            .header(header::AUTHORIZATION, format!("Bearer {}random", API_KEY_MARKER))
+            .finish();
+        let (key, limit) = bucket_for(&req);
+
+        assert_eq!(key, "auth:");
+        assert_eq!(limit.capacity, AUTH.capacity);
+    }
+
+    #[test]
+    fn requests_count_against_their_paths_bucket() {
+        let bucket = |path: &str| {
+            let req = Request::builder().uri_str(path).finish();
+            let (key, limit) = bucket_for(&req);
+            (key, limit.capacity)
+        };
+
+        assert_eq!(bucket("/api/user/signin"), ("auth:".to_string(), AUTH.capacity));
+        assert_eq!(bucket("/api/auth/sso/acme/callback"), ("auth:".to_string(), AUTH.capacity));
+        assert_eq!(bucket("/api/user/password_reset"), ("signup:".to_string(), SIGNUP.capacity));
+        assert_eq!(bucket("/api/subscribe"), ("signup:".to_string(), SIGNUP.capacity));
+        assert_eq!(bucket("/api/track"), ("track:".to_string(), TRACK.capacity));
+        assert_eq!(bucket("/api/get_total_views"), ("ip:".to_string(), DEFAULT.capacity));
+        // Only exact paths get the stricter limits
+        assert_eq!(bucket("/api/user/signin/other"), ("ip:".to_string(), DEFAULT.capacity));
+    }
+
+    #[tokio::test]
+    async fn memory_stays_bounded_under_a_flood_of_keys() {
+        let limiter = RateLimiter::new(None).await;
+        let limit = Limit::per_hour(10);
+
+        limiter.check("ip:first", limit).await.unwrap();
+        for i in 0..MEMORY_PRUNE_AT * 2 {
+            limiter.check(&format!("ip:{}", i), limit).await.unwrap();
+            limiter.record_failure(&format!("signin:{}", i)).await;
+        }
+
+        assert!(limiter.buckets.lock().unwrap().len() <= MEMORY_PRUNE_AT);
+        assert!(limiter.failures.lock().unwrap().len() <= MEMORY_PRUNE_AT);
+        // The least recently used went first
+        assert!(!limiter.buckets.lock().unwrap().contains_key("ip:first"));
+        assert!(limiter.buckets.lock().unwrap().contains_key(&format!("ip:{}", MEMORY_PRUNE_AT * 2 - 1)));
+    }
+}
@@ -1,46 +1,46 @@
 use serde::{Deserialize, Serialize};
 use store::models::website::Website;
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateWebsiteInput {
     pub url: String,
     pub about: String,
-    pub user_id: String
+    /// Defaults to the user's personal organization
+    pub org_id: Option<String>
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct CreateUserInput {
     pub username: String,
     pub password: String,
     pub name: String
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct SignInUserInput {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct SignInUserInputWithGoogle {
-    pub username: String,
-    pub user_name: String,
+    /// The ID token from Google Sign-In, verified before anything else
+    pub id_token: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct UpdateEmailInput {
-    pub user_id: String,
     pub new_email: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct UpdatePasswordInput {
-    pub user_id: String,
     pub old_password: String,
     pub new_password: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct TrackingInput {
     pub visitor_id: String,
     pub page_url: String,
@@ -49,55 +49,313 @@ pub struct TrackingInput {
     pub time_stamp: String
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct UsersWebsites {
     pub websites: Option<Vec<Website>>,
     pub success: bool
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct  GetViewsPerPageInput {
-    pub website: String
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct GetViewsPerPageInput {
+    pub website: String,
+    pub share_token: Option<String>
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetWebsiteDetailsDailyInput {
-    pub user_id: String,
     pub website: String,
     pub day:  String
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetWebsiteDetailsHourlyInput {
-    pub user_id: String,
     pub website: String,
     pub hour:  String
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetWebsiteDetailsLastHourInput {
-    pub user_id: String,
     pub website: String
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetWebsiteAverageRespTime {
-    pub website: String
+    pub website: String,
+    pub share_token: Option<String>
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetWebsiteAverageRespTimeByRegion {
     pub website: String,
-    pub region: String
+    pub region: String,
+    pub share_token: Option<String>
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetUptimePercentage {
-    pub website: String
+    pub website: String,
+    pub share_token: Option<String>
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetUptimePercentageByRegion {
     pub website: String,
-    pub region: String
+    pub region: String,
+    pub share_token: Option<String>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SubscribeInput {
+    pub website: String,
+    /// The website's public dashboard link
+    pub share_token: String,
+    pub channel: String,
+    pub target: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TokenInput {
+    pub token: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CreateIncidentInput {
+    pub title: String,
+    pub websites: Vec<String>,
+    pub status: String,
+    pub message: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct IncidentIdInput {
+    pub incident_id: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct UpdateIncidentInput {
+    pub incident_id: String,
+    pub title: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct AddIncidentUpdateInput {
+    pub incident_id: String,
+    pub status: String,
+    pub message: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostmortemInput {
+    pub incident_id: String,
+    pub content: String
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WebsiteIncidentsQuery {
+    pub website: String,
+    /// The website's public dashboard link
+    pub share_token: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeedQuery {
+    pub website: Option<String>,
+    /// The website's public dashboard link, required with `website`
+    pub share_token: Option<String>,
+    /// A user's feed token, for all of their websites instead of one
+    pub token: Option<String>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct FeedTokenInput {
+    pub enabled: bool
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BadgeQuery {
+    pub website: String,
+    /// The website's public dashboard link
+    pub share_token: String,
+    pub window: Option<String>,
+    pub label: Option<String>,
+    pub stat: Option<String>,
+    pub good: Option<f64>,
+    pub warn: Option<f64>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CreateSloInput {
+    pub website: String,
+    pub name: String,
+    pub target_percent: f64,
+    pub window_kind: String,
+    pub window_days: Option<i32>,
+    pub burn_rate_threshold: Option<f64>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SloIdInput {
+    pub slo_id: String
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MonthlyReportQuery {
+    pub month: String,
+    pub website: Option<String>,
+    pub format: Option<String>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CreateReportScheduleInput {
+    pub website: Option<String>,
+    pub email: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ReportScheduleIdInput {
+    pub schedule_id: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CheckoutInput {
+    pub plan: String
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    pub month: Option<String>,
+    /// Defaults to the user's personal organization
+    pub org_id: Option<String>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RefreshInput {
+    pub refresh_token: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RevokeSessionInput {
+    pub session_id: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ShareWebsiteInput {
+    pub website: String,
+    pub enabled: bool
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SsoCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PasswordResetInput {
+    pub email: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ConfirmPasswordResetInput {
+    pub token: String,
+    pub new_password: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SecondFactorInput {
+    /// Left out after SSO, which sets it as a cookie
+    pub mfa_token: Option<String>,
+    pub code: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct TotpCodeInput {
+    pub code: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CreateApiKeyInput {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i64>,
+    /// Limits the key to this organization's websites; needs admin there
+    pub org_id: Option<String>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RevokeApiKeyInput {
+    pub key_id: String
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrgQuery {
+    pub org_id: Option<String>
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CreateOrgInput {
+    pub name: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct OrgInput {
+    pub org_id: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct InviteMemberInput {
+    pub org_id: String,
+    pub email: String,
+    pub role: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RevokeInvitationInput {
+    pub org_id: String,
+    pub invitation_id: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SetMemberRoleInput {
+    pub org_id: String,
+    pub user_id: String,
+    pub role: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RemoveMemberInput {
+    pub org_id: String,
+    pub user_id: String
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RequireTwoFactorInput {
+    pub org_id: String,
+    pub required: bool
+}
+
+#[derive(Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// The organization's log, for its admins. Without it, the user's own actions
+    pub org_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    /// RFC 3339 timestamps
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>
 }
\ No newline at end of file
@@ -1,82 +1,330 @@
 use serde::{Deserialize, Serialize};
+use crate::billing::CheckoutSession;
+use store::models::api_key::ApiKey;
+use store::models::audit::AuditEntry;
+use store::models::billing::Subscription;
+use store::models::org::{MemberDetails, Membership, OrgInvitation, Organization};
+use store::models::incident::{Incident, IncidentDetails, IncidentUpdate, Postmortem};
+use store::models::report::ReportSchedule;
+use store::models::usage::UsageReport;
+use store::models::user::identity::Identity;
+use store::models::user::session::Session;
+use store::models::user::two_factor::TotpEnrollment;
+use store::models::slo::{Slo, SloAlert, SloStatus};
 use store::models::website::{AvgRespTime, DailyView, HourlyView, MinuteView, TotalUniqueUsers, TotalViews, TotalViewsPerPage, UptimePercentage};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateWebsiteOutput {
     pub website_id: String,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateUserOutput {
     pub user_id: String,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+/// The new session's tokens, also set as cookies.
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct SigninUserOutput {
     pub jwt: String,
+    pub refresh_token: String,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+/// Answered instead of a session when the account has 2FA on.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TwoFactorChallengeOutput {
+    pub two_factor_required: bool,
+    /// Sent back with the code to `/api/user/signin/2fa`
+    pub mfa_token: String,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct UpdateEmailOutput {
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+/// For actions that have nothing to return.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SuccessOutput {
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetViewsPerPageOutput {
     pub data: Option<Vec<TotalViewsPerPage>>,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetTotalUniqueUsersOutput {
     pub data: Option<TotalUniqueUsers>,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetTotalViewsOutput {
     pub data: Option<TotalViews>,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetWebsiteDetailsLastHourOutput {
     pub data: Option<Vec<MinuteView>>,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetWebsiteAvgRespTimeOutput {
     pub data: Option<AvgRespTime>,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetUptimePercentageOutput {
     pub data: Option<UptimePercentage>,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetWebsiteDetailsHourlyOutput {
     pub data: Option<Vec<HourlyView>>,
     pub success: bool
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GetWebsiteDetailsDailyOutput {
     pub data: Option<Vec<DailyView>>,
     pub success: bool
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct User {
     pub id: String,
     pub name: String,
     pub email: String,
     pub plan_type: String,
     pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SubscribeOutput {
+    pub subscriber_id: Option<String>,
+    pub needs_confirmation: bool,
+    pub success: bool
+}
+
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IncidentOutput {
+    pub data: Option<Incident>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IncidentsOutput {
+    pub data: Option<Vec<Incident>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IncidentDetailsOutput {
+    pub data: Option<IncidentDetails>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WebsiteIncidentsOutput {
+    pub data: Option<Vec<IncidentDetails>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IncidentUpdateOutput {
+    pub data: Option<IncidentUpdate>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PostmortemOutput {
+    pub data: Option<Postmortem>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SloOutput {
+    pub data: Option<Slo>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SlosOutput {
+    pub data: Option<Vec<Slo>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SloStatusOutput {
+    pub data: Option<SloStatus>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SloAlertsOutput {
+    pub data: Option<Vec<SloAlert>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReportScheduleOutput {
+    pub data: Option<ReportSchedule>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReportSchedulesOutput {
+    pub data: Option<Vec<ReportSchedule>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CheckoutOutput {
+    pub data: Option<CheckoutSession>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SubscriptionOutput {
+    pub data: Option<Subscription>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UsageOutput {
+    pub data: Option<UsageReport>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SessionsOutput {
+    pub data: Option<Vec<Session>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ShareWebsiteOutput {
+    pub share_token: Option<String>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FeedTokenOutput {
+    pub feed_token: Option<String>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IdentitiesOutput {
+    pub data: Option<Vec<Identity>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TotpEnrollmentOutput {
+    pub data: Option<TotpEnrollment>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RecoveryCodesOutput {
+    pub data: Option<Vec<String>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TwoFactorStatusOutput {
+    pub data: Option<TwoFactorStatus>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreatedApiKey {
+    /// Only returned here, it can't be looked up again
+    pub key: String,
+    pub api_key: ApiKey
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateApiKeyOutput {
+    pub data: Option<CreatedApiKey>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiKeysOutput {
+    pub data: Option<Vec<ApiKey>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct OrganizationOutput {
+    pub data: Option<Organization>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MembershipsOutput {
+    pub data: Option<Vec<Membership>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MembersOutput {
+    pub data: Option<Vec<MemberDetails>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct InvitationsOutput {
+    pub data: Option<Vec<OrgInvitation>>,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AuditLogOutput {
+    pub data: Option<Vec<AuditEntry>>,
+    pub success: bool
+}
+
+/// What every failed request answers with.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErrorOutput {
+    pub error: ErrorDetails,
+    pub success: bool
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErrorDetails {
+    /// Stable and machine readable, like `not_found` or `database_unavailable`
+    pub code: String,
+    /// For people, may change
+    pub message: String
+}
+
+/// What signing in answers with, depending on whether the account has 2FA on.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum SignInOutput {
+    Session(SigninUserOutput),
+    TwoFactorRequired(TwoFactorChallengeOutput)
 }
\ No newline at end of file
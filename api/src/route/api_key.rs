@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use poem::{
+    handler,
+    web::{Data, Json},
+    Result,
+};
+use store::{models::org::ADMIN, store::Store};
+
+use crate::{
+    access::require_org_role,
+    auth_middleware::AuthUser,
+    error::ApiError,
+    request_input::{CreateApiKeyInput, RevokeApiKeyInput},
+    request_output::{ApiKeysOutput, CreateApiKeyOutput, CreatedApiKey, SuccessOutput},
+};
+
+/// Creates a key with the given scopes. API keys can't call this, so a leaked
+/// key can't mint more.
+#[utoipa::path(
+    post,
+    path = "/api/user/api_key",
+    tag = "api_keys",
+    request_body = CreateApiKeyInput,
+    responses((status = 200, body = CreateApiKeyOutput))
+)]
+#[handler]
+pub async fn create_api_key(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<CreateApiKeyInput>,
+) -> Result<Json<CreateApiKeyOutput>> {
+    let expires_in = match data.expires_in_days {
+        Some(days) if days <= 0 => return Err(ApiError::invalid("expires_in_days must be positive").into()),
+        Some(days) => Some(chrono::Duration::days(days)),
+        None => None,
+    };
+
+    if let Some(org_id) = &data.org_id {
+        require_org_role(s, &user, org_id, ADMIN).await?;
+    }
+
+    let (api_key, key) = s
+        .create_api_key(&user.actor(), data.name, data.scopes, expires_in, data.org_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(CreateApiKeyOutput {
+        data: Some(CreatedApiKey { key, api_key }),
+        success: true,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/api_keys",
+    tag = "api_keys",
+    responses((status = 200, body = ApiKeysOutput))
+)]
+#[handler]
+pub async fn get_api_keys(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<ApiKeysOutput>> {
+    let keys = s.get_users_api_keys(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(ApiKeysOutput { data: Some(keys), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/api_key/revoke",
+    tag = "api_keys",
+    request_body = RevokeApiKeyInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn revoke_api_key(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<RevokeApiKeyInput>,
+) -> Result<Json<SuccessOutput>> {
+    s.revoke_api_key(&user.actor(), data.key_id).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
+}
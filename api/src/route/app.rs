@@ -2,16 +2,27 @@ use std::sync::Arc;
 use url::Url;
 
 use poem::{
-    Response, handler, http::{StatusCode, header}, web::{Data, Json}
+    Response, Result, handler, http::{StatusCode, header}, web::{Data, Json}
 };
-use store::{models::app::PageVisit, store::Store};
+use store::{error::StoreError, models::app::PageVisit, store::Store};
 
 use crate::{
-    auth_middleware::UserIdFromHeader,
+    access::authorize_website,
+    auth_middleware::{AuthUser, MaybeAuthUser},
+    error::ApiError,
+    quota_cache::QuotaCache,
+    rate_limit::{too_many_requests, RateLimiter, TRACK_PER_WEBSITE},
     request_input::{GetViewsPerPageInput, TrackingInput},
     request_output::{GetTotalUniqueUsersOutput, GetTotalViewsOutput, GetViewsPerPageOutput, User},
 };
 
+#[utoipa::path(
+    get,
+    path = "/api/snippet",
+    tag = "analytics",
+    responses((status = 200, description = "The tracking script for websites to embed", body = String, content_type = "application/javascript")),
+    security(())
+)]
 #[handler]
 pub async fn snippet() -> Response {
     let script = r#"
@@ -70,145 +81,159 @@ pub async fn snippet() -> Response {
         .body(script)
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/track",
+    tag = "analytics",
+    request_body = TrackingInput,
+    responses((status = 200, description = "The page view was recorded")),
+    security(())
+)]
 #[handler]
-pub async fn track(Json(data): Json<TrackingInput>, Data(s): Data<&Arc<Store>>) {
+pub async fn track(
+    Json(data): Json<TrackingInput>,
+    Data(s): Data<&Arc<Store>>,
+    Data(limiter): Data<&Arc<RateLimiter>>,
+    Data(quotas): Data<&Arc<QuotaCache>>,
+) -> Result<Response> {
     let page_url = data.page_url;
     let visitor_id = data.visitor_id;
     let referrer = data.referrer;
     let user_agent = data.user_agent;
 
-    let parsed_page_url = Url::parse(&page_url);
+    let url = Url::parse(&page_url).map_err(|_| ApiError::invalid("page_url is not a valid URL"))?;
+    let domain = url.domain().ok_or_else(|| ApiError::invalid("page_url has no domain"))?;
+    let current_path = url.path();
 
-    match parsed_page_url {
-        Ok(url) => {
-            let domain = url.domain().unwrap();
-            let current_path = url.path();
+    if let Err(wait) = limiter.check(&format!("website:{}", domain), TRACK_PER_WEBSITE).await {
+        return Err(too_many_requests(wait));
+    }
 
-    
-            let website = s.search_website(domain).await;
-
-            match website {
-                Ok(w) => {
-                    let _ = s.update_website_snippet(domain).await;
-                    let page_visit = PageVisit {
-                        visitor_id,
-                        referrer,
-                        user_agent,
-                        page_path: current_path.to_string(),
-                        website: w.url,
-                    };
-
-                    let _inserted_data = s.store_tracks(page_visit).await;
-                }
-                Err(e) => {
-                    println!("{}", e.to_string())
-                }
-            }
-        }
-        Err(_) => {}
+    let w = s.search_website(domain).await.map_err(ApiError::from)?;
+
+    let metered = match quotas.event_quota(s, &w.org_id).await {
+        Ok(quota) => s.meter_event(w.org_id.clone(), &quota).await,
+        Err(e) => Err(e),
+    };
+    match metered {
+        Ok(_) => {}
+        Err(e @ StoreError::Quota(_)) => return Err(ApiError::from(e).into()),
+        // Better to keep the visit than to lose it over metering
+        Err(e) => println!("Couldn't meter a page view for {}: {}", w.org_id, e),
     }
+
+    let _ = s.update_website_snippet(domain).await;
+    let page_visit = PageVisit {
+        visitor_id,
+        referrer,
+        user_agent,
+        page_path: current_path.to_string(),
+        website: w.url,
+    };
+
+    s.store_tracks(page_visit).await.map_err(ApiError::from)?;
+
+    Ok(Response::builder().status(StatusCode::OK).finish())
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/get_total_views_per_page",
+    tag = "analytics",
+    request_body = GetViewsPerPageInput,
+    responses((status = 200, body = GetViewsPerPageOutput)),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[handler]
 pub async fn total_views_per_page(
     Data(s): Data<&Arc<Store>>,
+    MaybeAuthUser(user): MaybeAuthUser,
     Json(data): Json<GetViewsPerPageInput>,
-) -> Json<GetViewsPerPageOutput> {
-    let res = s.get_per_page_views(data.website).await;
-
-    match res {
-        Ok(d) => Json(GetViewsPerPageOutput {
-            data: Some(d),
-            success: true,
-        }),
-        Err(_) => Json(GetViewsPerPageOutput {
-            data: None,
-            success: false,
-        }),
-    }
+) -> Result<Json<GetViewsPerPageOutput>> {
+    authorize_website(s, user.as_ref(), &data.website, data.share_token.as_deref()).await?;
+    let d = s.get_per_page_views(data.website).await.map_err(ApiError::from)?;
+    Ok(Json(GetViewsPerPageOutput {
+        data: Some(d),
+        success: true,
+    }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/get_total_unique_users",
+    tag = "analytics",
+    request_body = GetViewsPerPageInput,
+    responses((status = 200, body = GetTotalUniqueUsersOutput)),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[handler]
 pub async fn total_unique_users(
     Data(s): Data<&Arc<Store>>,
+    MaybeAuthUser(user): MaybeAuthUser,
     Json(data): Json<GetViewsPerPageInput>,
-) -> Json<GetTotalUniqueUsersOutput> {
-    let res = s.get_total_unique_users(data.website).await;
-
-    match res {
-        Ok(d) => Json(GetTotalUniqueUsersOutput {
-            data: Some(d),
-            success: true,
-        }),
-        Err(_) => Json(GetTotalUniqueUsersOutput {
-            data: None,
-            success: false,
-        }),
-    }
+) -> Result<Json<GetTotalUniqueUsersOutput>> {
+    authorize_website(s, user.as_ref(), &data.website, data.share_token.as_deref()).await?;
+    let d = s.get_total_unique_users(data.website).await.map_err(ApiError::from)?;
+    Ok(Json(GetTotalUniqueUsersOutput {
+        data: Some(d),
+        success: true,
+    }))
 }
+#[utoipa::path(
+    post,
+    path = "/api/get_total_views",
+    tag = "analytics",
+    request_body = GetViewsPerPageInput,
+    responses((status = 200, body = GetTotalViewsOutput)),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[handler]
 pub async fn total_views(
     Data(s): Data<&Arc<Store>>,
+    MaybeAuthUser(user): MaybeAuthUser,
     Json(data): Json<GetViewsPerPageInput>,
-) -> Json<GetTotalViewsOutput> {
-    let res = s.get_total_views(data.website).await;
-
-    match res {
-        Ok(d) => Json(GetTotalViewsOutput {
-            data: Some(d),
-            success: true,
-        }),
-        Err(_) => Json(GetTotalViewsOutput {
-            data: None,
-            success: false,
-        }),
-    }
+) -> Result<Json<GetTotalViewsOutput>> {
+    authorize_website(s, user.as_ref(), &data.website, data.share_token.as_deref()).await?;
+    let d = s.get_total_views(data.website).await.map_err(ApiError::from)?;
+    Ok(Json(GetTotalViewsOutput {
+        data: Some(d),
+        success: true,
+    }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/get_user",
+    tag = "analytics",
+    responses((status = 200, body = User))
+)]
 #[handler]
 pub async fn get_user(
     Data(s): Data<&Arc<Store>>,
-    UserIdFromHeader(user_id): UserIdFromHeader,
-) -> Json<User> {
-    if user_id.len() <= 0 {
-        print!("User id not found");
-        return Json(User {
-            id: "()".to_string(),
-            name: "()".to_string(),
-            email: "()".to_string(),
-            plan_type: "".to_string(),
-            success: false,
-        });
-    }
-    let res = s.get_user(user_id).await;
-
-    match res {
-        Ok(user) => match user {
-            Some(user) => Json(User {
-                id: user.id.clone(),
-                name: user.name.clone(),
-                email: user.email.clone(),
-                plan_type: user.plan_type.clone(),
-                success: true,
-            }),
-            None => Json(User {
-                id: "".to_string(),
-                name: "".to_string(),
-                email: "".to_string(),
-                plan_type: "".to_string(),
-                success: false,
-            }),
-        },
-        Err(_) => Json(User {
-            id: "".to_string(),
-            name: "".to_string(),
-            email: "".to_string(),
-            plan_type: "".to_string(),
-            success: false,
-        }),
-    }
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<User>> {
+    let user = s
+        .get_user(user_id)
+        .await
+        .map_err(ApiError::from)?
+        .ok_or_else(|| ApiError::from(StoreError::NotFound))?;
+
+    Ok(Json(User {
+        id: user.id,
+        name: user.name,
+        email: user.email,
+        plan_type: user.plan_type,
+        success: true,
+    }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "analytics",
+    responses((status = 200, description = "The API is up", body = String, content_type = "text/plain")),
+    security(())
+)]
 #[handler]
 pub async fn get_health() -> Response {
     Response::builder()
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDateTime};
+use poem::{
+    handler,
+    web::{Data, Json, Query},
+    Result,
+};
+use store::{
+    models::{audit::AuditFilter, org::ADMIN},
+    store::Store,
+};
+
+use crate::{
+    access::require_org_role,
+    auth_middleware::AuthUser,
+    error::ApiError,
+    request_input::AuditQuery,
+    request_output::AuditLogOutput,
+};
+
+const DEFAULT_AUDIT_PAGE: i64 = 50;
+
+fn parse_time(value: Option<String>) -> Option<Option<NaiveDateTime>> {
+    match value {
+        Some(v) => DateTime::parse_from_rfc3339(&v).ok().map(|t| Some(t.naive_utc())),
+        None => Some(None),
+    }
+}
+
+/// Audit log entries, newest first. With `org_id`, the organization's log for
+/// its admins; otherwise the user's own actions.
+#[utoipa::path(
+    get,
+    path = "/api/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses((status = 200, body = AuditLogOutput))
+)]
+#[handler]
+pub async fn get_audit_log(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditLogOutput>> {
+    let (Some(since), Some(until)) = (parse_time(query.since), parse_time(query.until)) else {
+        return Err(ApiError::invalid("since and until must be RFC 3339 times").into());
+    };
+
+    let actor_id = match &query.org_id {
+        Some(org_id) => {
+            require_org_role(s, &user, org_id, ADMIN).await?;
+            query.actor_id
+        }
+        None => Some(user.user_id.clone()),
+    };
+
+    let filter = AuditFilter {
+        org_id: query.org_id,
+        actor_id,
+        action: query.action,
+        target_type: query.target_type,
+        target_id: query.target_id,
+        since,
+        until,
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_PAGE);
+    let offset = query.offset.unwrap_or(0);
+
+    let entries = s.get_audit_log(filter, limit, offset).await.map_err(ApiError::from)?;
+    Ok(Json(AuditLogOutput { data: Some(entries), success: true }))
+}
@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use poem::{
+    handler,
+    http::{header, StatusCode},
+    web::{Data, Query},
+    Response,
+};
+use store::store::Store;
+
+use crate::{access::is_shared, request_input::BadgeQuery};
+
+const GREEN: &str = "#4c1";
+const YELLOW: &str = "#dfb317";
+const RED: &str = "#e05d44";
+const GREY: &str = "#9f9f9f";
+
+fn parse_window(window: Option<&str>) -> Option<(Duration, &str)> {
+    match window.unwrap_or("24h") {
+        "24h" => Some((Duration::hours(24), "24h")),
+        "7d" => Some((Duration::days(7), "7d")),
+        "30d" => Some((Duration::days(30), "30d")),
+        _ => None,
+    }
+}
+
+// Rough Verdana 11px advance, good enough to size the badge segments
+fn text_width(text: &str) -> usize {
+    text.chars().count() * 7 + 10
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_badge(label: &str, message: &str, color: &str) -> String {
+    let (label, message) = (escape(label), escape(message));
+    let label_width = text_width(&label);
+    let message_width = text_width(&message);
+    let width = label_width + message_width;
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">
+  <title>{label}: {message}</title>
+  <linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+  <clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+  <g clip-path="url(#r)">
+    <rect width="{label_width}" height="20" fill="#555"/>
+    <rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/>
+    <rect width="{width}" height="20" fill="url(#s)"/>
+  </g>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+    <text x="{label_x}" y="14">{label}</text>
+    <text x="{message_x}" y="14">{message}</text>
+  </g>
+</svg>
+"##,
+        label_x = label_width / 2,
+        message_x = label_width + message_width / 2,
+    )
+}
+
+fn badge_response(svg: String) -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/svg+xml")
+        .header(header::CACHE_CONTROL, "public, max-age=300, s-maxage=300")
+        .body(svg)
+}
+
+/// Uptime badge, green at or above `good` (default 99.9), yellow at or above
+/// `warn` (default 99) and red below.
+#[utoipa::path(
+    get,
+    path = "/api/badge/uptime",
+    tag = "status_pages",
+    params(BadgeQuery),
+    responses((status = 200, description = "An SVG badge", body = String, content_type = "image/svg+xml")),
+    security(())
+)]
+#[handler]
+pub async fn uptime_badge(
+    Query(query): Query<BadgeQuery>,
+    Data(s): Data<&Arc<Store>>,
+) -> Response {
+    let Some((window, window_label)) = parse_window(query.window.as_deref()) else {
+        return Response::builder().status(StatusCode::BAD_REQUEST).finish();
+    };
+
+    match is_shared(s, &query.website, Some(&query.share_token)).await {
+        Ok(true) => {}
+        Ok(false) => return Response::builder().status(StatusCode::NOT_FOUND).finish(),
+        Err(_) => return Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).finish(),
+    }
+
+    let since = Utc::now().naive_utc() - window;
+    let uptime = s
+        .get_uptime_percentage_since(query.website, since)
+        .await
+        .ok()
+        .and_then(|u| u.uptime_percent);
+
+    let label = query
+        .label
+        .unwrap_or_else(|| format!("uptime {}", window_label));
+    let good = query.good.unwrap_or(99.9);
+    let warn = query.warn.unwrap_or(99.0);
+
+    let (message, color) = match uptime {
+        Some(u) if u >= good => (format!("{:.2}%", u), GREEN),
+        Some(u) if u >= warn => (format!("{:.2}%", u), YELLOW),
+        Some(u) => (format!("{:.2}%", u), RED),
+        None => ("no data".to_string(), GREY),
+    };
+
+    badge_response(render_badge(&label, &message, color))
+}
+
+/// Response-time badge for `stat` avg, p50, p95 (default) or p99, green at or
+/// below `good` ms (default 300), yellow at or below `warn` ms (default 1000).
+#[utoipa::path(
+    get,
+    path = "/api/badge/response_time",
+    tag = "status_pages",
+    params(BadgeQuery),
+    responses((status = 200, description = "An SVG badge", body = String, content_type = "image/svg+xml")),
+    security(())
+)]
+#[handler]
+pub async fn response_time_badge(
+    Query(query): Query<BadgeQuery>,
+    Data(s): Data<&Arc<Store>>,
+) -> Response {
+    let Some((window, window_label)) = parse_window(query.window.as_deref()) else {
+        return Response::builder().status(StatusCode::BAD_REQUEST).finish();
+    };
+
+    let stat = query.stat.clone().unwrap_or_else(|| "p95".to_string());
+    if !["avg", "p50", "p95", "p99"].contains(&stat.as_str()) {
+        return Response::builder().status(StatusCode::BAD_REQUEST).finish();
+    }
+
+    match is_shared(s, &query.website, Some(&query.share_token)).await {
+        Ok(true) => {}
+        Ok(false) => return Response::builder().status(StatusCode::NOT_FOUND).finish(),
+        Err(_) => return Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).finish(),
+    }
+
+    let now = Utc::now().naive_utc();
+    let value = s
+        .get_response_time_stats_between(query.website, now - window, now)
+        .await
+        .ok()
+        .and_then(|r| match stat.as_str() {
+            "avg" => r.avg,
+            "p50" => r.p50,
+            "p99" => r.p99,
+            _ => r.p95,
+        });
+
+    let label = query
+        .label
+        .unwrap_or_else(|| format!("{} {}", stat, window_label));
+    let good = query.good.unwrap_or(300.0);
+    let warn = query.warn.unwrap_or(1000.0);
+
+    let (message, color) = match value {
+        Some(v) if v <= good => (format!("{:.0}ms", v), GREEN),
+        Some(v) if v <= warn => (format!("{:.0}ms", v), YELLOW),
+        Some(v) => (format!("{:.0}ms", v), RED),
+        None => ("no data".to_string(), GREY),
+    };
+
+    badge_response(render_badge(&label, &message, color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_labels_and_messages() {
+        let svg = render_badge("<script>alert(1)</script>", r#"99.9% "up" & running"#, GREEN);
+
+        assert!(!svg.contains("<script>"));
+        assert!(svg.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(svg.contains("99.9% &quot;up&quot; &amp; running"));
+    }
+
+    #[test]
+    fn only_known_windows_are_accepted() {
+        assert_eq!(parse_window(None), Some((Duration::hours(24), "24h")));
+        assert_eq!(parse_window(Some("7d")), Some((Duration::days(7), "7d")));
+        assert_eq!(parse_window(Some("1y")), None);
+    }
+}
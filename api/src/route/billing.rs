@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use notifier::config::Config as MailConfig;
+use poem::{
+    handler,
+    http::{HeaderMap, StatusCode},
+    web::{Data, Json, Query},
+    Result,
+};
+use serde_json::Value;
+use store::{
+    error::StoreError,
+    models::{org::VIEWER, report::month_bounds},
+    store::Store,
+};
+
+use crate::{
+    access::require_org_role,
+    auth_middleware::AuthUser,
+    billing::{cancel_subscription, create_checkout_session, process_event, verify_signature},
+    config::{Config, PaymentConfig},
+    error::ApiError,
+    request_input::{CheckoutInput, UsageQuery},
+    request_output::{CheckoutOutput, SubscriptionOutput, UsageOutput},
+};
+
+/// The payment provider's settings, or 503 on instances without billing.
+fn payment(config: &Config) -> Result<&PaymentConfig, ApiError> {
+    config.payment.as_ref().ok_or_else(|| {
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "billing_unavailable", "Billing isn't set up on this server")
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/billing/checkout",
+    tag = "billing",
+    request_body = CheckoutInput,
+    responses((status = 200, body = CheckoutOutput))
+)]
+#[handler]
+pub async fn create_checkout(
+    Data(s): Data<&Arc<Store>>,
+    Data(config): Data<&Arc<Config>>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
+    Data(client): Data<&reqwest::Client>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(data): Json<CheckoutInput>,
+) -> Result<Json<CheckoutOutput>> {
+    let payment = payment(config)?;
+    let plan = s.get_plan(data.plan.clone()).await.map_err(ApiError::from)?;
+    // Free plans have nothing to check out
+    let Some(price_id) = plan.provider_price_id else {
+        return Err(ApiError::invalid("free plans have nothing to check out").into());
+    };
+
+    // A second checkout would start a second subscription billed alongside
+    // the first; plan changes go through the provider instead
+    let subscription = s.get_users_subscription(user_id.clone()).await.map_err(ApiError::from)?;
+    if subscription.is_some_and(|sub| sub.is_current()) {
+        return Err(ApiError::from(StoreError::Conflict("you already have a subscription")).into());
+    }
+
+    let return_url = format!("{}/billing", mail_config.public_url);
+    match create_checkout_session(client, payment, &price_id, &user_id, &data.plan, &return_url).await {
+        Ok(session) => Ok(Json(CheckoutOutput { data: Some(session), success: true })),
+        Err(e) => {
+            println!("Checkout failed: {}", e);
+            Err(ApiError::upstream("Couldn't start checkout with the payment provider").into())
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/billing/subscription",
+    tag = "billing",
+    responses((status = 200, body = SubscriptionOutput))
+)]
+#[handler]
+pub async fn get_subscription(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<SubscriptionOutput>> {
+
+    let subscription = s.get_users_subscription(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(SubscriptionOutput { data: subscription, success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/billing/cancel",
+    tag = "billing",
+    responses((status = 200, body = SubscriptionOutput))
+)]
+#[handler]
+pub async fn cancel(
+    Data(s): Data<&Arc<Store>>,
+    Data(config): Data<&Arc<Config>>,
+    Data(client): Data<&reqwest::Client>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<SubscriptionOutput>> {
+    let payment = payment(config)?;
+    let subscription = s
+        .get_users_subscription(user_id)
+        .await
+        .map_err(ApiError::from)?
+        .ok_or_else(|| ApiError::from(StoreError::NotFound))?;
+
+    match cancel_subscription(client, payment, &subscription.provider_subscription_id).await {
+        Ok(_) => Ok(Json(SubscriptionOutput { data: Some(subscription), success: true })),
+        Err(e) => {
+            println!("Cancel failed: {}", e);
+            Err(ApiError::upstream("Couldn't cancel with the payment provider").into())
+        }
+    }
+}
+
+/// An organization's usage against its plan for a `YYYY-MM` month, or the
+/// current billing period.
+#[utoipa::path(
+    get,
+    path = "/api/usage",
+    tag = "billing",
+    params(UsageQuery),
+    responses((status = 200, body = UsageOutput))
+)]
+#[handler]
+pub async fn get_usage(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageOutput>> {
+    let org_id = match query.org_id.or_else(|| user.org_id.clone()) {
+        Some(org_id) => org_id,
+        None => s
+            .get_personal_org_id(user.user_id.clone())
+            .await
+            .map_err(ApiError::from)?,
+    };
+    require_org_role(s, &user, &org_id, VIEWER).await?;
+
+    let period = match query.month {
+        Some(month) => match month_bounds(&month) {
+            Some((from, to)) => Some((from.date(), to.date())),
+            None => return Err(ApiError::invalid("month must look like 2025-01").into()),
+        },
+        None => None,
+    };
+
+    let report = s.get_usage_report(org_id, period).await.map_err(ApiError::from)?;
+    Ok(Json(UsageOutput { data: Some(report), success: true }))
+}
+
+/// Payment provider webhook. Answers 400 for bad signatures or payloads and
+/// 5xx when the change could not be stored, so the provider retries.
+#[utoipa::path(
+    post,
+    path = "/api/billing/webhook",
+    tag = "billing",
+    request_body(content = String, description = "A signed event from the payment provider", content_type = "application/json"),
+    responses((status = 200, description = "The event was applied, or was already"), (status = 400, description = "Bad signature or payload")),
+    security(())
+)]
+#[handler]
+pub async fn billing_webhook(
+    Data(s): Data<&Arc<Store>>,
+    Data(config): Data<&Arc<Config>>,
+    headers: &HeaderMap,
+    body: Vec<u8>,
+) -> Result<StatusCode> {
+    let payment = payment(config)?;
+    let signature = headers
+        .get("Stripe-Signature")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    if !verify_signature(&payment.webhook_secret, signature, &body, Utc::now().timestamp()) {
+        return Err(ApiError::invalid("Bad signature").into());
+    }
+
+    let event = serde_json::from_slice::<Value>(&body).map_err(|_| ApiError::invalid("The event isn't JSON"))?;
+
+    process_event(s.as_ref(), &event).await?;
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,36 @@
+use poem::{
+    handler,
+    web::{Html, Json},
+};
+use utoipa::OpenApi;
+
+use crate::openapi::ApiDoc;
+
+const SWAGGER_UI: &str = r##"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>Nexus API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css" integrity="sha384-wxLW6kwyHktdDGr6Pv1zgm/VGJh99lfUbzSn6HNHBENZlCN7W602k9VkGdxuFvPn" crossorigin="anonymous">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js" integrity="sha384-wmyclcVGX/WhUkdkATwhaK1X1JtiNrr2EoYJ+diV3vj4v6OC5yCeSu+yW13SYJep" crossorigin="anonymous"></script>
+    <script>
+        window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+</body>
+</html>
+"##;
+
+/// The OpenAPI 3 document, for generating clients.
+#[handler]
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Interactive docs for the API, rendered from `/api/openapi.json`.
+#[handler]
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use notifier::config::Config;
+use poem::{
+    handler,
+    http::{header, StatusCode},
+    web::{Data, Json, Query},
+    Request, Response, Result,
+};
+use store::{models::website::StatusChange, store::Store};
+
+use crate::{
+    access::is_shared,
+    auth_middleware::AuthUser,
+    error::ApiError,
+    request_input::{FeedQuery, FeedTokenInput},
+    request_output::FeedTokenOutput,
+};
+
+const FEED_DAYS: i64 = 30;
+const FEED_LIMIT: i64 = 50;
+
+struct Feed {
+    title: String,
+    self_path: String,
+    updated: NaiveDateTime,
+    changes: Vec<StatusChange>,
+}
+
+async fn load_feed(s: &Store, query: FeedQuery, kind: &str) -> Result<Feed, StatusCode> {
+    let (title, scope, websites, created) = match (query.website, query.token) {
+        (Some(website), None) => {
+            let share_token = query.share_token.unwrap_or_default();
+            match is_shared(s, &website, Some(&share_token)).await {
+                Ok(true) => {}
+                Ok(false) => return Err(StatusCode::NOT_FOUND),
+                Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+
+            let w = s
+                .search_website(&website)
+                .await
+                .map_err(|_| StatusCode::NOT_FOUND)?;
+            (
+                format!("Status of {}", w.url),
+                format!("website={}&share_token={}", w.url, share_token),
+                vec![w.url],
+                w.time_added,
+            )
+        }
+        (None, Some(token)) => {
+            let user = s
+                .get_feed_user(token.clone())
+                .await
+                .map_err(|_| StatusCode::NOT_FOUND)?;
+            let two_factor = s
+                .is_totp_enabled(user.clone())
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let websites = s
+                .get_member_websites(user, None, two_factor)
+                .await
+                .map_err(|_| StatusCode::NOT_FOUND)?;
+            let created = websites
+                .iter()
+                .map(|w| w.time_added)
+                .min()
+                .ok_or(StatusCode::NOT_FOUND)?;
+            (
+                "Status of monitored websites".to_string(),
+                format!("token={}", token),
+                websites.into_iter().map(|w| w.url).collect(),
+                created,
+            )
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let since = Utc::now().naive_utc() - Duration::days(FEED_DAYS);
+    let changes = s
+        .get_status_history(websites, since, FEED_LIMIT)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // A change is "updated" again when it ends, so take the latest of both
+    let updated = changes
+        .iter()
+        .map(|c| c.ended_at.unwrap_or(c.changed_at))
+        .max()
+        .unwrap_or(created);
+
+    Ok(Feed {
+        title,
+        self_path: format!("/api/feed/{}?{}", kind, scope),
+        updated,
+        changes,
+    })
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.num_seconds().max(0);
+    let (days, hours, minutes, seconds) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+fn entry_title(c: &StatusChange) -> String {
+    format!("{} is {} in {}", c.website_url, c.status, c.region)
+}
+
+fn entry_summary(c: &StatusChange) -> String {
+    let duration = match c.ended_at {
+        Some(end) => format!("lasted {}", format_duration(end - c.changed_at)),
+        None => format!(
+            "ongoing for {}",
+            format_duration(Utc::now().naive_utc() - c.changed_at)
+        ),
+    };
+
+    format!(
+        "{} changed from {} to {} in region {} at {} UTC ({}).",
+        c.website_url,
+        c.previous_status,
+        c.status,
+        c.region,
+        c.changed_at.format("%Y-%m-%d %H:%M:%S"),
+        duration
+    )
+}
+
+fn entry_id(c: &StatusChange) -> String {
+    format!(
+        "urn:status:{}:{}:{}",
+        c.website_url,
+        c.region,
+        c.changed_at.and_utc().timestamp_millis()
+    )
+}
+
+fn render_atom(feed: &Feed, base_url: &str) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape(&feed.title)));
+    xml.push_str(&format!(
+        "  <id>{}</id>\n",
+        escape(&format!("{}{}", base_url, feed.self_path))
+    ));
+    xml.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}\"/>\n",
+        escape(&format!("{}{}", base_url, feed.self_path))
+    ));
+    xml.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        feed.updated.and_utc().to_rfc3339()
+    ));
+
+    for c in &feed.changes {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&entry_title(c))));
+        xml.push_str(&format!("    <id>{}</id>\n", escape(&entry_id(c))));
+        xml.push_str(&format!(
+            "    <published>{}</published>\n",
+            c.changed_at.and_utc().to_rfc3339()
+        ));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            c.ended_at.unwrap_or(c.changed_at).and_utc().to_rfc3339()
+        ));
+        xml.push_str(&format!(
+            "    <category term=\"{}\" label=\"{}\"/>\n",
+            escape(&c.status),
+            escape(&c.region)
+        ));
+        xml.push_str(&format!(
+            "    <content type=\"text\">{}</content>\n",
+            escape(&entry_summary(c))
+        ));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn render_rss(feed: &Feed, base_url: &str) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\">\n  <channel>\n");
+    xml.push_str(&format!("    <title>{}</title>\n", escape(&feed.title)));
+    xml.push_str(&format!(
+        "    <link>{}</link>\n",
+        escape(&format!("{}{}", base_url, feed.self_path))
+    ));
+    xml.push_str(&format!(
+        "    <description>{}</description>\n",
+        escape(&feed.title)
+    ));
+    xml.push_str(&format!(
+        "    <lastBuildDate>{}</lastBuildDate>\n",
+        feed.updated.and_utc().to_rfc2822()
+    ));
+
+    for c in &feed.changes {
+        xml.push_str("    <item>\n");
+        xml.push_str(&format!("      <title>{}</title>\n", escape(&entry_title(c))));
+        xml.push_str(&format!(
+            "      <guid isPermaLink=\"false\">{}</guid>\n",
+            escape(&entry_id(c))
+        ));
+        xml.push_str(&format!(
+            "      <pubDate>{}</pubDate>\n",
+            c.changed_at.and_utc().to_rfc2822()
+        ));
+        xml.push_str(&format!("      <category>{}</category>\n", escape(&c.region)));
+        xml.push_str(&format!(
+            "      <description>{}</description>\n",
+            escape(&entry_summary(c))
+        ));
+        xml.push_str("    </item>\n");
+    }
+
+    xml.push_str("  </channel>\n</rss>\n");
+    xml
+}
+
+fn http_date(t: NaiveDateTime) -> String {
+    t.and_utc().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Answers 304 when the client's ETag or Last-Modified still matches the feed.
+fn not_modified(req: &Request, etag: &str, updated: NaiveDateTime) -> bool {
+    if let Some(value) = req.headers().get(header::IF_NONE_MATCH) {
+        return value
+            .to_str()
+            .map(|v| v.split(',').any(|t| t.trim() == etag || t.trim() == "*"))
+            .unwrap_or(false);
+    }
+
+    req.headers()
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+        .map(|since| updated.and_utc().timestamp() <= since.timestamp())
+        .unwrap_or(false)
+}
+
+fn feed_response(req: &Request, feed: Feed, body: String, content_type: &str) -> Response {
+    let etag = format!(
+        "W/\"{}-{}\"",
+        feed.updated.and_utc().timestamp_millis(),
+        feed.changes.len()
+    );
+    let last_modified = http_date(feed.updated);
+
+    if not_modified(req, &etag, feed.updated) {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag)
+            .header(header::LAST_MODIFIED, last_modified)
+            .finish();
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "public, max-age=60")
+        .header(header::ETAG, etag)
+        .header(header::LAST_MODIFIED, last_modified)
+        .body(body)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/feed/atom",
+    tag = "status_pages",
+    params(FeedQuery),
+    responses((status = 200, description = "Incidents as an Atom feed", body = String, content_type = "application/atom+xml"), (status = 304, description = "Unchanged since `If-None-Match` or `If-Modified-Since`")),
+    security(())
+)]
+#[handler]
+pub async fn atom_feed(
+    req: &Request,
+    Query(query): Query<FeedQuery>,
+    Data(s): Data<&Arc<Store>>,
+    Data(config): Data<&Arc<Config>>,
+) -> Response {
+    match load_feed(s, query, "atom").await {
+        Ok(feed) => {
+            let body = render_atom(&feed, &config.public_url);
+            feed_response(req, feed, body, "application/atom+xml; charset=utf-8")
+        }
+        Err(status) => Response::builder().status(status).finish(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/feed/rss",
+    tag = "status_pages",
+    params(FeedQuery),
+    responses((status = 200, description = "Incidents as an RSS feed", body = String, content_type = "application/rss+xml"), (status = 304, description = "Unchanged since `If-None-Match` or `If-Modified-Since`")),
+    security(())
+)]
+#[handler]
+pub async fn rss_feed(
+    req: &Request,
+    Query(query): Query<FeedQuery>,
+    Data(s): Data<&Arc<Store>>,
+    Data(config): Data<&Arc<Config>>,
+) -> Response {
+    match load_feed(s, query, "rss").await {
+        Ok(feed) => {
+            let body = render_rss(&feed, &config.public_url);
+            feed_response(req, feed, body, "application/rss+xml; charset=utf-8")
+        }
+        Err(status) => Response::builder().status(status).finish(),
+    }
+}
+
+/// Turns the feed of all the user's websites on or off. While it is on, the
+/// feeds answer `?token=` with the returned `feed_token`; enabling it again
+/// replaces the token, so old feed links stop working.
+#[utoipa::path(
+    post,
+    path = "/api/feed/token",
+    tag = "status_pages",
+    request_body = FeedTokenInput,
+    responses((status = 200, body = FeedTokenOutput))
+)]
+#[handler]
+pub async fn set_feed_token(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<FeedTokenInput>,
+) -> Result<Json<FeedTokenOutput>> {
+    let token = s.set_feed_token(&user.actor(), data.enabled).await.map_err(ApiError::from)?;
+    Ok(Json(FeedTokenOutput { feed_token: token, success: true }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed() -> Feed {
+        let changed_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap().naive_utc();
+        Feed {
+            title: "Status of https://example.com/?a=1&b=<2>".to_string(),
+            self_path: "/api/feed/atom?website=https://example.com/?a=1&b=<2>".to_string(),
+            updated: changed_at,
+            changes: vec![StatusChange {
+                website_url: "https://example.com/?a=1&b=<2>".to_string(),
+                region: "eu \"west\"".to_string(),
+                previous_status: "Up".to_string(),
+                status: "Down".to_string(),
+                changed_at,
+                ended_at: Some(changed_at + Duration::minutes(5)),
+            }],
+        }
+    }
+
+    #[test]
+    fn escapes_markup_in_values() {
+        assert_eq!(
+            escape(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &apos;Jerry&apos;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn feeds_never_carry_raw_markup_from_urls_or_regions() {
+        let base_url = "https://status.example.com";
+        for xml in [render_atom(&feed(), base_url), render_rss(&feed(), base_url)] {
+            assert!(!xml.contains("<2>"), "{}", xml);
+            assert!(!xml.contains("&b="), "{}", xml);
+            assert!(!xml.contains("\"west\""), "{}", xml);
+            assert!(xml.contains("a=1&amp;b=&lt;2&gt;"));
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{Data, Json, Query},
+    Error, Result,
+};
+use store::{
+    models::org::{EDITOR, VIEWER},
+    store::Store,
+};
+
+use crate::{
+    access::{authorize_website, require_website_role},
+    auth_middleware::AuthUser,
+    error::ApiError,
+    request_input::{
+        AddIncidentUpdateInput, CreateIncidentInput, IncidentIdInput, OrgQuery, PostmortemInput,
+        UpdateIncidentInput, WebsiteIncidentsQuery,
+    },
+    request_output::{
+        IncidentDetailsOutput, IncidentOutput, IncidentUpdateOutput, IncidentsOutput,
+        PostmortemOutput, WebsiteIncidentsOutput,
+    },
+};
+
+const DEFAULT_INCIDENT_PAGE: i64 = 20;
+
+/// Checks that the user has at least `required` on every website the
+/// incident is on.
+async fn require_incident_role(s: &Store, user: &AuthUser, incident_id: &str, required: &str) -> Result<()> {
+    let websites = s.get_incident_websites(incident_id.to_string()).await.map_err(ApiError::from)?;
+    for website in &websites {
+        require_website_role(s, user, website, required).await?;
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/incident",
+    tag = "incidents",
+    request_body = CreateIncidentInput,
+    responses((status = 200, body = IncidentOutput))
+)]
+#[handler]
+pub async fn create_incident(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<CreateIncidentInput>,
+) -> Result<Json<IncidentOutput>> {
+    for website in &data.websites {
+        require_website_role(s, &user, website, EDITOR).await?;
+    }
+
+    let incident = s
+        .create_incident(&user.actor(), data.title, data.websites, data.status, data.message)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(IncidentOutput { data: Some(incident), success: true }))
+}
+
+/// Incidents on websites of all the user's organizations, or of `org_id`.
+#[utoipa::path(
+    get,
+    path = "/api/incidents",
+    tag = "incidents",
+    params(OrgQuery),
+    responses((status = 200, body = IncidentsOutput))
+)]
+#[handler]
+pub async fn get_users_incidents(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Query(query): Query<OrgQuery>,
+) -> Result<Json<IncidentsOutput>> {
+    // Keys limited to one organization never list another's incidents
+    let org_id = match (user.org_id, query.org_id) {
+        (Some(key_org), Some(requested)) if key_org != requested => {
+            return Err(Error::from_status(StatusCode::FORBIDDEN));
+        }
+        (Some(key_org), _) => Some(key_org),
+        (None, requested) => requested,
+    };
+
+    let two_factor = s.is_totp_enabled(user.user_id.clone()).await.map_err(ApiError::from)?;
+    let incidents = s
+        .get_member_incidents(user.user_id, org_id, two_factor)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(IncidentsOutput { data: Some(incidents), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/incident/get",
+    tag = "incidents",
+    request_body = IncidentIdInput,
+    responses((status = 200, body = IncidentDetailsOutput))
+)]
+#[handler]
+pub async fn get_incident(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<IncidentIdInput>,
+) -> Result<Json<IncidentDetailsOutput>> {
+    require_incident_role(s, &user, &data.incident_id, VIEWER).await?;
+
+    let incident = s.get_incident(data.incident_id).await.map_err(ApiError::from)?;
+    Ok(Json(IncidentDetailsOutput { data: Some(incident), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/incident/update",
+    tag = "incidents",
+    request_body = UpdateIncidentInput,
+    responses((status = 200, body = IncidentOutput))
+)]
+#[handler]
+pub async fn update_incident(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<UpdateIncidentInput>,
+) -> Result<Json<IncidentOutput>> {
+    require_incident_role(s, &user, &data.incident_id, EDITOR).await?;
+    let incident = s.update_incident_title(&user.actor(), data.incident_id, data.title).await.map_err(ApiError::from)?;
+    Ok(Json(IncidentOutput { data: Some(incident), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/incident/add_update",
+    tag = "incidents",
+    request_body = AddIncidentUpdateInput,
+    responses((status = 200, body = IncidentUpdateOutput))
+)]
+#[handler]
+pub async fn add_incident_update(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<AddIncidentUpdateInput>,
+) -> Result<Json<IncidentUpdateOutput>> {
+    require_incident_role(s, &user, &data.incident_id, EDITOR).await?;
+    let update = s
+        .add_incident_update(&user.actor(), data.incident_id, data.status, data.message)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(IncidentUpdateOutput { data: Some(update), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/incident/delete",
+    tag = "incidents",
+    request_body = IncidentIdInput,
+    responses((status = 200, body = IncidentOutput))
+)]
+#[handler]
+pub async fn delete_incident(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<IncidentIdInput>,
+) -> Result<Json<IncidentOutput>> {
+    require_incident_role(s, &user, &data.incident_id, EDITOR).await?;
+    let n = s.delete_incident(&user.actor(), data.incident_id).await.map_err(ApiError::from)?;
+    Ok(Json(IncidentOutput { data: None, success: n > 0 }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/incident/postmortem",
+    tag = "incidents",
+    request_body = PostmortemInput,
+    responses((status = 200, body = PostmortemOutput))
+)]
+#[handler]
+pub async fn set_postmortem(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<PostmortemInput>,
+) -> Result<Json<PostmortemOutput>> {
+    require_incident_role(s, &user, &data.incident_id, EDITOR).await?;
+    let postmortem = s.set_postmortem(&user.actor(), data.incident_id, data.content).await.map_err(ApiError::from)?;
+    Ok(Json(PostmortemOutput { data: Some(postmortem), success: true }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/status/incidents",
+    tag = "incidents",
+    params(WebsiteIncidentsQuery),
+    responses((status = 200, body = WebsiteIncidentsOutput)),
+    security(())
+)]
+#[handler]
+pub async fn get_public_incidents(
+    Data(s): Data<&Arc<Store>>,
+    Query(data): Query<WebsiteIncidentsQuery>,
+) -> Result<Json<WebsiteIncidentsOutput>> {
+    authorize_website(s, None, &data.website, Some(&data.share_token)).await?;
+
+    let limit = data.limit.unwrap_or(DEFAULT_INCIDENT_PAGE);
+    let offset = data.offset.unwrap_or(0);
+
+    let incidents = s
+        .get_website_incidents(data.website, limit, offset)
+        .await
+        .map_err(ApiError::from)?;
+    Ok(Json(WebsiteIncidentsOutput { data: Some(incidents), success: true }))
+}
@@ -1,3 +1,16 @@
 pub mod user;
 pub mod website;
-pub mod app;
\ No newline at end of file
+pub mod app;
+pub mod subscriber;
+pub mod incident;
+pub mod feed;
+pub mod badge;
+pub mod slo;
+pub mod report;
+pub mod billing;
+pub mod sso;
+pub mod two_factor;
+pub mod api_key;
+pub mod org;
+pub mod audit;
+pub mod docs;
\ No newline at end of file
@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use notifier::{config::Config as MailConfig, mailer::Mailer};
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{Data, Json},
+    Error, Result,
+};
+use store::{
+    models::org::{ADMIN, OWNER, VIEWER},
+    store::Store,
+};
+
+use crate::{
+    access::require_org_role,
+    auth_middleware::AuthUser,
+    error::ApiError,
+    request_input::{CreateOrgInput, InviteMemberInput, OrgInput, RemoveMemberInput, RequireTwoFactorInput, RevokeInvitationInput, SetMemberRoleInput, TokenInput},
+    request_output::{InvitationsOutput, MembersOutput, MembershipsOutput, OrganizationOutput, SuccessOutput},
+};
+
+#[utoipa::path(
+    post,
+    path = "/api/org",
+    tag = "organizations",
+    request_body = CreateOrgInput,
+    responses((status = 200, body = OrganizationOutput))
+)]
+#[handler]
+pub async fn create_org(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<CreateOrgInput>,
+) -> Result<Json<OrganizationOutput>> {
+    let name = data.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiError::invalid("name can't be empty").into());
+    }
+
+    let org = s.create_organization(&user.actor(), name).await.map_err(ApiError::from)?;
+    Ok(Json(OrganizationOutput { data: Some(org), success: true }))
+}
+
+/// The user's organizations and their role in each.
+#[utoipa::path(
+    get,
+    path = "/api/orgs",
+    tag = "organizations",
+    responses((status = 200, body = MembershipsOutput))
+)]
+#[handler]
+pub async fn get_orgs(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<MembershipsOutput>> {
+    let orgs = s.get_users_organizations(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(MembershipsOutput { data: Some(orgs), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/org/members",
+    tag = "organizations",
+    request_body = OrgInput,
+    responses((status = 200, body = MembersOutput))
+)]
+#[handler]
+pub async fn get_org_members(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<OrgInput>,
+) -> Result<Json<MembersOutput>> {
+    require_org_role(s, &user, &data.org_id, VIEWER).await?;
+
+    let members = s.get_org_members(data.org_id).await.map_err(ApiError::from)?;
+    Ok(Json(MembersOutput { data: Some(members), success: true }))
+}
+
+/// Mails an invitation link. Admins can invite anyone but owners, whom only
+/// owners can invite.
+#[utoipa::path(
+    post,
+    path = "/api/org/invite",
+    tag = "organizations",
+    request_body = InviteMemberInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn invite_member(
+    Data(s): Data<&Arc<Store>>,
+    Data(mailer): Data<&Arc<Mailer>>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
+    user: AuthUser,
+    Json(data): Json<InviteMemberInput>,
+) -> Result<Json<SuccessOutput>> {
+    let role = require_org_role(s, &user, &data.org_id, ADMIN).await?;
+    if data.role == OWNER && role != OWNER {
+        return Err(Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    let email = data.email.trim().to_string();
+    if !email.contains('@') || email.contains(char::is_whitespace) {
+        return Err(ApiError::invalid("email isn't a valid address").into());
+    }
+
+    let (invitation, token) = s
+        .create_org_invitation(&user.actor(), data.org_id, email, data.role)
+        .await
+        .map_err(ApiError::from)?;
+
+    let body = format!(
+        "You have been invited to join a team on Nexus as {}:\n\n{}/accept-invite?token={}\n\nThe link expires in 7 days and only works for this email address. If you weren't expecting this, you can ignore this email.\n",
+        invitation.role, mail_config.public_url, token
+    );
+
+    match mailer.send(&invitation.email, "You're invited to a team on Nexus", body).await {
+        Ok(_) => Ok(Json(SuccessOutput { success: true })),
+        Err(e) => {
+            println!("Error: {}", e);
+            Err(ApiError::upstream("Couldn't send the invitation email").into())
+        }
+    }
+}
+
+/// Pending invitations.
+#[utoipa::path(
+    post,
+    path = "/api/org/invitations",
+    tag = "organizations",
+    request_body = OrgInput,
+    responses((status = 200, body = InvitationsOutput))
+)]
+#[handler]
+pub async fn get_org_invitations(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<OrgInput>,
+) -> Result<Json<InvitationsOutput>> {
+    require_org_role(s, &user, &data.org_id, ADMIN).await?;
+
+    let invitations = s.get_org_invitations(data.org_id).await.map_err(ApiError::from)?;
+    Ok(Json(InvitationsOutput { data: Some(invitations), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/org/invitation/revoke",
+    tag = "organizations",
+    request_body = RevokeInvitationInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn revoke_org_invitation(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<RevokeInvitationInput>,
+) -> Result<Json<SuccessOutput>> {
+    require_org_role(s, &user, &data.org_id, ADMIN).await?;
+
+    s.revoke_org_invitation(&user.actor(), data.org_id, data.invitation_id).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
+}
+
+/// Joins the organization the invitation is for. The user must have verified
+/// the address it was sent to.
+#[utoipa::path(
+    post,
+    path = "/api/org/invitation/accept",
+    tag = "organizations",
+    request_body = TokenInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn accept_org_invitation(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<TokenInput>,
+) -> Result<Json<SuccessOutput>> {
+    s.accept_org_invitation(&user.actor(), data.token).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
+}
+
+/// Changes a member's role. Only owners can make or unmake owners.
+#[utoipa::path(
+    post,
+    path = "/api/org/member/role",
+    tag = "organizations",
+    request_body = SetMemberRoleInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn set_member_role(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<SetMemberRoleInput>,
+) -> Result<Json<SuccessOutput>> {
+    let role = require_org_role(s, &user, &data.org_id, ADMIN).await?;
+
+    let current = s
+        .get_member_role(data.org_id.clone(), data.user_id.clone())
+        .await
+        .map_err(|_| Error::from_status(StatusCode::NOT_FOUND))?;
+
+    if (current == OWNER || data.role == OWNER) && role != OWNER {
+        return Err(Error::from_status(StatusCode::FORBIDDEN));
+    }
+
+    s.set_member_role(&user.actor(), data.org_id, data.user_id, data.role).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
+}
+
+/// Removes a member, or lets members leave. Only owners can remove owners,
+/// and the last owner can't leave.
+#[utoipa::path(
+    post,
+    path = "/api/org/member/remove",
+    tag = "organizations",
+    request_body = RemoveMemberInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn remove_member(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<RemoveMemberInput>,
+) -> Result<Json<SuccessOutput>> {
+    if data.user_id != user.user_id {
+        let role = require_org_role(s, &user, &data.org_id, ADMIN).await?;
+
+        let current = s
+            .get_member_role(data.org_id.clone(), data.user_id.clone())
+            .await
+            .map_err(|_| Error::from_status(StatusCode::NOT_FOUND))?;
+
+        if current == OWNER && role != OWNER {
+            return Err(Error::from_status(StatusCode::FORBIDDEN));
+        }
+    }
+
+    s.remove_member(&user.actor(), data.org_id, data.user_id).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
+}
+
+/// Makes 2FA mandatory for the organization's members. Owners turning it on
+/// need 2FA themselves, so they can't lock themselves out.
+#[utoipa::path(
+    post,
+    path = "/api/org/two_factor",
+    tag = "organizations",
+    request_body = RequireTwoFactorInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn set_org_require_two_factor(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<RequireTwoFactorInput>,
+) -> Result<Json<SuccessOutput>> {
+    require_org_role(s, &user, &data.org_id, OWNER).await?;
+
+    if data.required {
+        let enabled = s
+            .is_totp_enabled(user.user_id.clone())
+            .await
+            .map_err(|_| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        if !enabled {
+            return Err(Error::from_string(
+                "Turn on two-factor authentication before requiring it",
+                StatusCode::FORBIDDEN,
+            ));
+        }
+    }
+
+    s.set_org_require_two_factor(&user.actor(), data.org_id, data.required).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
+}
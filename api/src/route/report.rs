@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use notifier::report::{render_csv, render_pdf};
+use poem::{
+    handler,
+    http::{header, StatusCode},
+    web::{Data, Json, Query},
+    Response,
+    Result,
+};
+use store::{
+    models::{org::VIEWER, report::month_bounds},
+    store::Store,
+};
+
+use crate::{
+    access::require_website_role,
+    auth_middleware::AuthUser,
+    error::ApiError,
+    request_input::{CreateReportScheduleInput, MonthlyReportQuery, ReportScheduleIdInput},
+    request_output::{ReportScheduleOutput, ReportSchedulesOutput},
+};
+
+#[utoipa::path(
+    get,
+    path = "/api/report/monthly",
+    tag = "reports",
+    params(MonthlyReportQuery),
+    responses((status = 200, description = "The report as an attachment", content((String = "text/csv"), (String = "application/pdf"))))
+)]
+#[handler]
+pub async fn get_monthly_report(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Query(query): Query<MonthlyReportQuery>,
+) -> Result<Response> {
+    let format = query.format.unwrap_or_else(|| "csv".to_string());
+    if month_bounds(&query.month).is_none() || (format != "csv" && format != "pdf") {
+        return Err(ApiError::invalid("month must look like 2025-01 and format be csv or pdf").into());
+    }
+
+    // Answers 403 rather than an empty report for websites the user can't see
+    if let Some(website) = &query.website {
+        require_website_role(s, &user, website, VIEWER).await?;
+    }
+
+    let reports = s
+        .get_users_monthly_reports(user.user_id, user.org_id, query.website, query.month.clone())
+        .await
+        .map_err(ApiError::from)?;
+
+    let filename = format!("uptime-report-{}.{}", query.month, format);
+    let (content_type, body) = if format == "pdf" {
+        let title = format!("Availability report for {}", query.month);
+        ("application/pdf", render_pdf(&reports, &title))
+    } else {
+        ("text/csv; charset=utf-8", render_csv(&reports).into_bytes())
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(body))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/report/schedule",
+    tag = "reports",
+    request_body = CreateReportScheduleInput,
+    responses((status = 200, body = ReportScheduleOutput))
+)]
+#[handler]
+pub async fn create_report_schedule(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<CreateReportScheduleInput>,
+) -> Result<Json<ReportScheduleOutput>> {
+    if !data.email.contains('@') {
+        return Err(ApiError::invalid("email isn't a valid address").into());
+    }
+
+    if let Some(website) = &data.website {
+        require_website_role(s, &user, website, VIEWER).await?;
+    }
+
+    let schedule = s.create_report_schedule(&user.actor(), data.website, data.email).await.map_err(ApiError::from)?;
+    Ok(Json(ReportScheduleOutput { data: Some(schedule), success: true }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/report/schedules",
+    tag = "reports",
+    responses((status = 200, body = ReportSchedulesOutput))
+)]
+#[handler]
+pub async fn get_report_schedules(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<ReportSchedulesOutput>> {
+
+    let schedules = s.get_users_report_schedules(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(ReportSchedulesOutput { data: Some(schedules), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/report/schedule/delete",
+    tag = "reports",
+    request_body = ReportScheduleIdInput,
+    responses((status = 200, body = ReportScheduleOutput))
+)]
+#[handler]
+pub async fn delete_report_schedule(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<ReportScheduleIdInput>,
+) -> Result<Json<ReportScheduleOutput>> {
+    let n = s.delete_report_schedule(&user.actor(), data.schedule_id).await.map_err(ApiError::from)?;
+    Ok(Json(ReportScheduleOutput { data: None, success: n > 0 }))
+}
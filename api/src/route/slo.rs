@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use poem::{
+    handler,
+    web::{Data, Json},
+    Result,
+};
+use store::{
+    models::{
+        org::{EDITOR, VIEWER},
+        slo::{NewSlo, CALENDAR_MONTH, ROLLING},
+    },
+    store::Store,
+};
+
+use crate::{
+    access::require_website_role,
+    auth_middleware::AuthUser,
+    error::ApiError,
+    request_input::{CreateSloInput, SloIdInput},
+    request_output::{SloAlertsOutput, SloOutput, SloStatusOutput, SlosOutput},
+};
+
+#[utoipa::path(
+    post,
+    path = "/api/slo",
+    tag = "slos",
+    request_body = CreateSloInput,
+    responses((status = 200, body = SloOutput))
+)]
+#[handler]
+pub async fn create_slo(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<CreateSloInput>,
+) -> Result<Json<SloOutput>> {
+    require_website_role(s, &user, &data.website, EDITOR).await?;
+
+    let window_days = data.window_days.unwrap_or(30);
+    let burn_rate_threshold = data.burn_rate_threshold.unwrap_or(14.4);
+
+    let is_valid = data.target_percent > 0.0
+        && data.target_percent < 100.0
+        && (data.window_kind == ROLLING || data.window_kind == CALENDAR_MONTH)
+        && (1..=365).contains(&window_days)
+        && burn_rate_threshold > 0.0;
+
+    if !is_valid {
+        return Err(ApiError::invalid("target_percent, window_kind, window_days or burn_rate_threshold is out of range").into());
+    }
+
+    let new_slo = NewSlo {
+        name: data.name,
+        target_percent: data.target_percent,
+        window_kind: data.window_kind,
+        window_days,
+        burn_rate_threshold,
+    };
+
+    let slo = s.create_slo(&user.actor(), data.website, new_slo).await.map_err(ApiError::from)?;
+    Ok(Json(SloOutput { data: Some(slo), success: true }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/slos",
+    tag = "slos",
+    responses((status = 200, body = SlosOutput))
+)]
+#[handler]
+pub async fn get_users_slos(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<SlosOutput>> {
+    let slos = s.get_users_slos(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(SlosOutput { data: Some(slos), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/slo/status",
+    tag = "slos",
+    request_body = SloIdInput,
+    responses((status = 200, body = SloStatusOutput))
+)]
+#[handler]
+pub async fn get_slo_status(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<SloIdInput>,
+) -> Result<Json<SloStatusOutput>> {
+    let slo = s.get_slo(user.user_id.clone(), data.slo_id).await.map_err(ApiError::from)?;
+    // The SLO's creator may since have left the website's organization
+    require_website_role(s, &user, &slo.website_url, VIEWER).await?;
+
+    let status = s.get_slo_status(slo).await.map_err(ApiError::from)?;
+    Ok(Json(SloStatusOutput { data: Some(status), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/slo/alerts",
+    tag = "slos",
+    request_body = SloIdInput,
+    responses((status = 200, body = SloAlertsOutput))
+)]
+#[handler]
+pub async fn get_slo_alerts(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<SloIdInput>,
+) -> Result<Json<SloAlertsOutput>> {
+    let slo = s.get_slo(user.user_id.clone(), data.slo_id).await.map_err(ApiError::from)?;
+    require_website_role(s, &user, &slo.website_url, VIEWER).await?;
+
+    let alerts = s.get_slo_alerts(slo.id).await.map_err(ApiError::from)?;
+    Ok(Json(SloAlertsOutput { data: Some(alerts), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/slo/delete",
+    tag = "slos",
+    request_body = SloIdInput,
+    responses((status = 200, body = SloOutput))
+)]
+#[handler]
+pub async fn delete_slo(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<SloIdInput>,
+) -> Result<Json<SloOutput>> {
+    let n = s.delete_slo(&user.actor(), data.slo_id).await.map_err(ApiError::from)?;
+    Ok(Json(SloOutput { data: None, success: n > 0 }))
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use notifier::config::Config as MailConfig;
+use poem::{
+    handler,
+    http::{header, StatusCode},
+    web::{Data, Path, Query},
+    Error, Request, Response, Result,
+};
+use store::{error::StoreError, store::Store};
+
+use crate::{
+    error::ApiError,
+    request_input::SsoCallbackQuery,
+    route::user::{encode_mfa_token, issue_tokens, mfa_cookie, session_cookies},
+    sso::SsoProviders,
+};
+
+/// The login cookies only live for the round trip through the provider.
+const LOGIN_COOKIE_MAX_AGE: i64 = 10 * 60;
+
+fn redirect_uri(public_url: &str, provider: &str) -> String {
+    format!("{}/api/auth/sso/{}/callback", public_url, provider)
+}
+
+fn login_cookie(name: &str, value: &str, max_age: i64) -> String {
+    format!(
+        "{}={}; HttpOnly; SameSite=Lax; Path=/api/auth/sso; Max-Age={};",
+        name, value, max_age
+    )
+}
+
+/// Redirects to the provider's login page.
+#[utoipa::path(
+    get,
+    path = "/api/auth/sso/{provider}/start",
+    tag = "auth",
+    params(("provider" = String, Path, description = "A configured SSO provider")),
+    responses((status = 302, description = "Redirects to the identity provider")),
+    security(())
+)]
+#[handler]
+pub async fn sso_start(
+    Path(provider): Path<String>,
+    Data(sso): Data<&Arc<SsoProviders>>,
+    Data(client): Data<&reqwest::Client>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
+) -> Result<Response> {
+    let provider = sso
+        .get(&provider)
+        .ok_or_else(|| Error::from_status(StatusCode::NOT_FOUND))?;
+
+    let login = sso
+        .start_login(client, provider, &redirect_uri(&mail_config.public_url, &provider.name))
+        .await
+        .map_err(|e| {
+            println!("SSO start failed for {}: {}", provider.name, e);
+            Error::from_status(StatusCode::BAD_GATEWAY)
+        })?;
+
+    Ok(Response::builder()
+        .status(StatusCode::FOUND)
+        .header(header::LOCATION, login.url)
+        .header(header::SET_COOKIE, login_cookie("sso_state", &login.state, LOGIN_COOKIE_MAX_AGE))
+        .header(header::SET_COOKIE, login_cookie("sso_nonce", &login.nonce, LOGIN_COOKIE_MAX_AGE))
+        .header(header::SET_COOKIE, login_cookie("sso_verifier", &login.verifier, LOGIN_COOKIE_MAX_AGE))
+        .finish())
+}
+
+/// The provider redirects back here. Signs the linked user in and redirects to
+/// the dashboard with the session cookies set.
+#[utoipa::path(
+    get,
+    path = "/api/auth/sso/{provider}/callback",
+    tag = "auth",
+    params(("provider" = String, Path, description = "A configured SSO provider"), SsoCallbackQuery),
+    responses((status = 302, description = "Signs in and redirects to the dashboard, or to the 2FA step")),
+    security(())
+)]
+#[handler]
+pub async fn sso_callback(
+    req: &Request,
+    Path(provider): Path<String>,
+    Query(query): Query<SsoCallbackQuery>,
+    Data(s): Data<&Arc<Store>>,
+    Data(sso): Data<&Arc<SsoProviders>>,
+    Data(client): Data<&reqwest::Client>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
+) -> Result<Response> {
+    let provider = sso
+        .get(&provider)
+        .ok_or_else(|| Error::from_status(StatusCode::NOT_FOUND))?;
+
+    let cookie = |name: &str| req.cookie().get(name).map(|c| c.value_str().to_string());
+    let (Some(code), Some(state), Some(expected_state), Some(nonce), Some(verifier)) = (
+        query.code,
+        query.state,
+        cookie("sso_state"),
+        cookie("sso_nonce"),
+        cookie("sso_verifier"),
+    ) else {
+        return Err(Error::from_string("Login was cancelled or expired", StatusCode::BAD_REQUEST));
+    };
+
+    if state != expected_state {
+        return Err(Error::from_string("Login state does not match", StatusCode::BAD_REQUEST));
+    }
+
+    let external = sso
+        .finish_login(
+            client,
+            provider,
+            &redirect_uri(&mail_config.public_url, &provider.name),
+            &code,
+            &verifier,
+            &nonce,
+        )
+        .await
+        .map_err(|e| {
+            println!("SSO login failed for {}: {}", provider.name, e);
+            Error::from_status(StatusCode::UNAUTHORIZED)
+        })?;
+
+    let user_id = match s.sign_in_with_identity(external).await {
+        Ok(user_id) => user_id,
+        // The provider has no verified email to link or sign up with
+        Err(StoreError::Unauthorized) => {
+            return Err(ApiError::new(StatusCode::UNAUTHORIZED, "unverified_email", "No verified email to sign in with").into());
+        }
+        Err(e) => return Err(ApiError::from(e).into()),
+    };
+
+    let two_factor = s.is_totp_enabled(user_id.clone()).await.map_err(ApiError::from)?;
+
+    if two_factor {
+        // The dashboard asks for the code and finishes through /api/user/signin/2fa,
+        // which reads the token from the cookie
+        let mfa_token = encode_mfa_token(user_id).map_err(Error::from_status)?;
+        return Ok(Response::builder()
+            .status(StatusCode::FOUND)
+            .header(header::LOCATION, format!("{}/two-factor", mail_config.public_url))
+            .header(header::SET_COOKIE, mfa_cookie(&mfa_token))
+            .header(header::SET_COOKIE, login_cookie("sso_state", "", 0))
+            .header(header::SET_COOKIE, login_cookie("sso_nonce", "", 0))
+            .header(header::SET_COOKIE, login_cookie("sso_verifier", "", 0))
+            .finish());
+    }
+
+    let (access_token, refresh_token) = issue_tokens(s, user_id, req).await?;
+    let [access_cookie, refresh_cookie] = session_cookies(&access_token, &refresh_token);
+
+    Ok(Response::builder()
+        .status(StatusCode::FOUND)
+        .header(header::LOCATION, format!("{}/", mail_config.public_url))
+        .header(header::SET_COOKIE, access_cookie)
+        .header(header::SET_COOKIE, refresh_cookie)
+        .header(header::SET_COOKIE, login_cookie("sso_state", "", 0))
+        .header(header::SET_COOKIE, login_cookie("sso_nonce", "", 0))
+        .header(header::SET_COOKIE, login_cookie("sso_verifier", "", 0))
+        .finish())
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use notifier::{config::Config, mailer::Mailer, webhook::check_target};
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{Data, Json, Query},
+    Request, Response, Result,
+};
+use store::{
+    error::StoreError,
+    models::subscriber::{EMAIL_CHANNEL, WEBHOOK_CHANNEL},
+    store::Store,
+};
+
+use crate::{
+    access::is_shared,
+    auth_middleware::client_ip,
+    error::{link_page_error, ApiError},
+    request_input::{SubscribeInput, TokenInput},
+    request_output::SubscribeOutput,
+};
+
+async fn is_valid_target(channel: &str, target: &str) -> bool {
+    match channel {
+        EMAIL_CHANNEL => target.contains('@') && !target.contains(char::is_whitespace),
+        WEBHOOK_CHANNEL => check_target(target).await.is_ok(),
+        _ => false,
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/subscribe",
+    tag = "status_pages",
+    request_body = SubscribeInput,
+    responses((status = 200, body = SubscribeOutput)),
+    security(())
+)]
+#[handler]
+pub async fn subscribe(
+    Json(data): Json<SubscribeInput>,
+    Data(s): Data<&Arc<Store>>,
+    Data(mailer): Data<&Arc<Mailer>>,
+    Data(config): Data<&Arc<Config>>,
+    req: &Request,
+) -> Result<Json<SubscribeOutput>> {
+    // Status changes of unshared websites are as private as their dashboards
+    if !is_shared(s, &data.website, Some(&data.share_token)).await.map_err(ApiError::from)? {
+        return Err(ApiError::from(StoreError::NotFound).into());
+    }
+
+    if !is_valid_target(&data.channel, &data.target).await {
+        return Err(ApiError::invalid(
+            "target must be an email address or an http(s) url on a public address",
+        )
+        .into());
+    }
+
+    let subscriber = s
+        .add_subscriber(data.website, data.channel, data.target, client_ip(req))
+        .await
+        .map_err(ApiError::from)?;
+
+    if let Some(token) = &subscriber.confirm_token {
+        let body = format!(
+            "Please confirm that you want to receive status updates for {}:\n\n{}/api/subscribe/confirm?token={}\n\nIf you did not ask for this, you can ignore this email.\n",
+            subscriber.website_url, config.public_url, token
+        );
+
+        let subject = format!("Confirm your subscription to {}", subscriber.website_url);
+        if let Err(e) = mailer.send(&subscriber.target, &subject, body).await {
+            println!("Error: {}", e);
+            return Err(ApiError::upstream("Couldn't send the confirmation email").into());
+        }
+    }
+
+    Ok(Json(SubscribeOutput {
+        subscriber_id: Some(subscriber.id),
+        needs_confirmation: !subscriber.is_confirmed,
+        success: true,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/subscribe/confirm",
+    tag = "status_pages",
+    params(TokenInput),
+    responses((status = 200, description = "The subscription is confirmed", body = String, content_type = "text/plain")),
+    security(())
+)]
+#[handler]
+pub async fn confirm_subscription(
+    Query(data): Query<TokenInput>,
+    Data(s): Data<&Arc<Store>>,
+    req: &Request,
+) -> Response {
+    match s.confirm_subscriber(data.token, client_ip(req)).await {
+        Ok(subscriber) => Response::builder()
+            .status(StatusCode::OK)
+            .body(format!("Subscription to {} confirmed", subscriber.website_url)),
+        Err(e) => link_page_error(e, "Invalid or already used confirmation link"),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/unsubscribe",
+    tag = "status_pages",
+    params(TokenInput),
+    responses((status = 200, description = "The subscription is removed", body = String, content_type = "text/plain")),
+    security(())
+)]
+#[handler]
+pub async fn unsubscribe(
+    Query(data): Query<TokenInput>,
+    Data(s): Data<&Arc<Store>>,
+    req: &Request,
+) -> Response {
+    match s.remove_subscriber(data.token, client_ip(req)).await {
+        Ok(n) if n > 0 => Response::builder()
+            .status(StatusCode::OK)
+            .body("You have been unsubscribed"),
+        Ok(_) => link_page_error(StoreError::NotFound, "Invalid unsubscribe link"),
+        Err(e) => link_page_error(e, "Invalid unsubscribe link"),
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use poem::{
+    handler,
+    web::{Data, Json},
+    Result,
+};
+use store::{error::StoreError, store::Store};
+
+use crate::{
+    auth_middleware::AuthUser,
+    error::ApiError,
+    rate_limit::{too_many_requests, RateLimiter, MAX_FAILURES},
+    request_input::TotpCodeInput,
+    request_output::{RecoveryCodesOutput, TotpEnrollmentOutput, SuccessOutput, TwoFactorStatus, TwoFactorStatusOutput},
+    route::user::second_factor_lockout_key,
+};
+
+/// Checks a current code, counting wrong ones towards the account's lockout.
+async fn verify_code(s: &Store, limiter: &RateLimiter, user: &AuthUser, code: String) -> Result<bool> {
+    let lockout_key = second_factor_lockout_key(&user.user_id);
+    if let Some(wait) = limiter.locked_for(&lockout_key, MAX_FAILURES).await {
+        return Err(too_many_requests(wait));
+    }
+
+    if matches!(s.verify_second_factor(&user.actor(), code).await, Ok(true)) {
+        limiter.clear_failures(&lockout_key).await;
+        Ok(true)
+    } else {
+        limiter.record_failure(&lockout_key).await;
+        Ok(false)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/2fa",
+    tag = "two_factor",
+    responses((status = 200, body = TwoFactorStatusOutput))
+)]
+#[handler]
+pub async fn get_two_factor_status(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<TwoFactorStatusOutput>> {
+    let enabled = s.is_totp_enabled(user_id.clone()).await.map_err(ApiError::from)?;
+    let recovery_codes_left = s.get_unused_recovery_code_count(user_id).await.map_err(ApiError::from)?;
+
+    Ok(Json(TwoFactorStatusOutput {
+        data: Some(TwoFactorStatus { enabled, recovery_codes_left }),
+        success: true,
+    }))
+}
+
+/// Returns a new secret and its `otpauth://` URI for the authenticator app.
+/// 2FA stays off until a code from it is confirmed.
+#[utoipa::path(
+    post,
+    path = "/api/user/2fa/setup",
+    tag = "two_factor",
+    responses((status = 200, body = TotpEnrollmentOutput))
+)]
+#[handler]
+pub async fn setup_two_factor(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<TotpEnrollmentOutput>> {
+    let enrollment = s.begin_totp_enrollment(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(TotpEnrollmentOutput { data: Some(enrollment), success: true }))
+}
+
+/// Turns 2FA on and returns the recovery codes.
+#[utoipa::path(
+    post,
+    path = "/api/user/2fa/confirm",
+    tag = "two_factor",
+    request_body = TotpCodeInput,
+    responses((status = 200, body = RecoveryCodesOutput))
+)]
+#[handler]
+pub async fn confirm_two_factor(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<TotpCodeInput>,
+) -> Result<Json<RecoveryCodesOutput>> {
+    let codes = s.confirm_totp_enrollment(&user.actor(), data.code).await.map_err(ApiError::from)?;
+    Ok(Json(RecoveryCodesOutput { data: Some(codes), success: true }))
+}
+
+/// Replaces the recovery codes, given a current code.
+#[utoipa::path(
+    post,
+    path = "/api/user/2fa/recovery_codes",
+    tag = "two_factor",
+    request_body = TotpCodeInput,
+    responses((status = 200, body = RecoveryCodesOutput))
+)]
+#[handler]
+pub async fn regenerate_recovery_codes(
+    Data(s): Data<&Arc<Store>>,
+    Data(limiter): Data<&Arc<RateLimiter>>,
+    user: AuthUser,
+    Json(data): Json<TotpCodeInput>,
+) -> Result<Json<RecoveryCodesOutput>> {
+    if !verify_code(s, limiter, &user, data.code).await? {
+        return Err(ApiError::from(StoreError::Unauthorized).into());
+    }
+
+    let codes = s.regenerate_recovery_codes(&user.actor()).await.map_err(ApiError::from)?;
+    Ok(Json(RecoveryCodesOutput { data: Some(codes), success: true }))
+}
+
+/// Turns 2FA off, given a current code, so a stolen session alone can't.
+#[utoipa::path(
+    post,
+    path = "/api/user/2fa/disable",
+    tag = "two_factor",
+    request_body = TotpCodeInput,
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn disable_two_factor(
+    Data(s): Data<&Arc<Store>>,
+    Data(limiter): Data<&Arc<RateLimiter>>,
+    user: AuthUser,
+    Json(data): Json<TotpCodeInput>,
+) -> Result<Json<SuccessOutput>> {
+    if !verify_code(s, limiter, &user, data.code).await? {
+        return Err(ApiError::from(StoreError::Unauthorized).into());
+    }
+
+    s.disable_totp(&user.actor()).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
+}
@@ -1,37 +1,232 @@
 use std::{env, sync::{Arc}};
 
 use crate::{
-    request_input::{CreateUserInput, SignInUserInput, SignInUserInputWithGoogle, UpdateEmailInput, UpdatePasswordInput},
-    request_output::{CreateUserOutput, UpdateEmailOutput},
+    auth_middleware::{client_ip, session_claims, AuthUser},
+    config::Config,
+    error::{link_page_error, ApiError},
+    google::GoogleVerifier,
+    rate_limit::{too_many_requests, RateLimiter, MAX_ACCOUNT_FAILURES, MAX_FAILURES},
+    request_input::{ConfirmPasswordResetInput, CreateUserInput, PasswordResetInput, RefreshInput, RevokeSessionInput, SecondFactorInput, SignInUserInput, SignInUserInputWithGoogle, TokenInput, UpdateEmailInput, UpdatePasswordInput},
+    request_output::{CreateUserOutput, IdentitiesOutput, SessionsOutput, SignInOutput, SigninUserOutput, SuccessOutput, TwoFactorChallengeOutput, UpdateEmailOutput},
 };
-use jsonwebtoken::{encode, EncodingKey, Header};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use notifier::{config::Config as MailConfig, mailer::Mailer};
 use poem::{
     handler,
     http::{header, StatusCode},
-    web::{Data, Json},
-    Error, Response,
+    web::{Data, Json, Query},
+    Error, IntoResponse, Request, Response,
+    Result,
 };
 use serde::{Deserialize, Serialize};
-use store::store::Store;
+use store::{
+    error::StoreError,
+    models::{
+        audit::Actor,
+        user::{
+            email_token::{CHANGE_EMAIL, RESET_PASSWORD, VERIFY_EMAIL},
+            identity::ExternalIdentity,
+            session::REFRESH_TOKEN_TTL,
+        },
+    },
+    store::Store,
+};
+
+/// Access tokens are short lived; clients renew them with the refresh token.
+const ACCESS_TOKEN_TTL: chrono::Duration = chrono::Duration::minutes(15);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    /// Session the token was issued for, checked for revocation on every request
+    pub sid: String,
+}
+
+/// Proves the password step of a sign in for accounts with 2FA on. It can
+/// only be traded for a session together with a second factor.
+#[derive(Debug, Serialize, Deserialize)]
+struct MfaClaims {
+    sub: String,
+    exp: usize,
+    purpose: String,
+}
+
+const MFA_PURPOSE: &str = "mfa";
+const MFA_TOKEN_TTL: chrono::Duration = chrono::Duration::minutes(5);
+
+fn jwt_secret() -> Result<String, StatusCode> {
+    env::var("JWT_SECRET").map_err(|_| StatusCode::EXPECTATION_FAILED)
 }
 
+pub(crate) fn encode_mfa_token(user_id: String) -> Result<String, StatusCode> {
+    let claims = MfaClaims {
+        sub: user_id,
+        exp: (chrono::Utc::now() + MFA_TOKEN_TTL).timestamp() as usize,
+        purpose: MFA_PURPOSE.to_string(),
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret()?.as_ref()))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Carries the first step's token through a redirect, like SSO's, instead of
+/// the URL where it would end up in browser history and logs.
+pub(crate) fn mfa_cookie(mfa_token: &str) -> String {
+    format!(
+        "mfa_token={}; HttpOnly; SameSite=Strict; Path=/api/user/signin/2fa; Max-Age={};",
+        mfa_token,
+        MFA_TOKEN_TTL.num_seconds()
+    )
+}
+
+const CLEAR_MFA_COOKIE: &str = "mfa_token=; HttpOnly; SameSite=Strict; Path=/api/user/signin/2fa; Max-Age=0;";
+
+fn decode_mfa_token(token: &str) -> Result<String, StatusCode> {
+    let claims = decode::<MfaClaims>(
+        token,
+        &DecodingKey::from_secret(jwt_secret()?.as_ref()),
+        &Validation::default(),
+    )
+    .map_err(|_| StatusCode::UNAUTHORIZED)?
+    .claims;
+
+    if claims.purpose != MFA_PURPOSE {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(claims.sub)
+}
+
+fn encode_access_token(user_id: String, session_id: String) -> Result<String, StatusCode> {
+    let my_claims = Claims {
+        sub: user_id,
+        exp: (chrono::Utc::now() + ACCESS_TOKEN_TTL).timestamp() as usize,
+        sid: session_id,
+    };
+
+    encode(
+        &Header::default(),
+        &my_claims,
+        &EncodingKey::from_secret(jwt_secret()?.as_ref()),
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// `Set-Cookie` values for a new token pair.
+pub(crate) fn session_cookies(access_token: &str, refresh_token: &str) -> [String; 2] {
+    // For localhost cross-origin, use SameSite=lax or None with proper settings
+    [
+        format!(
+            "jwt={}; HttpOnly; SameSite=Lax; Path=/; Max-Age={};",
+            access_token,
+            ACCESS_TOKEN_TTL.num_seconds()
+        ),
+        format!(
+            "refresh_token={}; HttpOnly; SameSite=Lax; Path=/api/user; Max-Age={};",
+            refresh_token,
+            REFRESH_TOKEN_TTL.num_seconds()
+        ),
+    ]
+}
+
+/// Sets both tokens as cookies and returns them in the body for clients that
+/// send them as headers.
+fn session_response(access_token: String, refresh_token: String) -> Response {
+    let [access_cookie, refresh_cookie] = session_cookies(&access_token, &refresh_token);
+
+    Json(SigninUserOutput { jwt: access_token, refresh_token, success: true })
+        .with_header(header::SET_COOKIE, access_cookie)
+        .with_header(header::SET_COOKIE, refresh_cookie)
+        .into_response()
+}
+
+/// Starts a session for the user and returns its access and refresh tokens.
+pub(crate) async fn issue_tokens(s: &Store, user_id: String, req: &Request) -> Result<(String, String), Error> {
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    let (session, refresh_token) = s
+        .create_session(user_id.clone(), user_agent, client_ip(req))
+        .await
+        .map_err(|_| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    let access_token = encode_access_token(user_id, session.id).map_err(Error::from_status)?;
+    Ok((access_token, refresh_token))
+}
+
+async fn start_session(s: &Store, user_id: String, req: &Request) -> Result<Response, Error> {
+    let (access_token, refresh_token) = issue_tokens(s, user_id, req).await?;
+    Ok(session_response(access_token, refresh_token))
+}
+
+/// Starts a session once the first factor checked out, or asks for the second
+/// one when the account has 2FA on.
+async fn complete_sign_in(s: &Store, user_id: String, req: &Request) -> Result<Response, Error> {
+    let two_factor = s.is_totp_enabled(user_id.clone()).await.map_err(ApiError::from)?;
+
+    if !two_factor {
+        return start_session(s, user_id, req).await;
+    }
+
+    let mfa_token = encode_mfa_token(user_id).map_err(Error::from_status)?;
+    Ok(Json(TwoFactorChallengeOutput { two_factor_required: true, mfa_token, success: true }).into_response())
+}
+
+/// Mails a one-time verification link for the user's current address.
+async fn send_verification_email(
+    s: &Store,
+    mailer: &Mailer,
+    config: &MailConfig,
+    user_id: String,
+    address: &str,
+) -> Result<(), Error> {
+    let token = s
+        .create_email_token(user_id, VERIFY_EMAIL, None)
+        .await
+        .map_err(ApiError::from)?;
+
+    let body = format!(
+        "Please confirm your email address:\n\n{}/api/user/verify_email/confirm?token={}\n\nThe link expires in 24 hours. If you did not sign up, you can ignore this email.\n",
+        config.public_url, token
+    );
+
+    mailer
+        .send(address, "Confirm your email address", body)
+        .await
+        .map_err(|e| {
+            println!("Error: {}", e);
+            ApiError::upstream("Couldn't send the verification email").into()
+        })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/signup",
+    tag = "auth",
+    request_body = CreateUserInput,
+    responses((status = 200, body = CreateUserOutput)),
+    security(())
+)]
 #[handler]
 pub async fn create_user(
     Json(data): Json<CreateUserInput>,
     Data(s): Data<&Arc<Store>>,
+    Data(mailer): Data<&Arc<Mailer>>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
 ) -> Result<Json<CreateUserOutput>, Error> {
     let username = data.username;
     let user_password = data.password;
     let name = data.name;
 
     let result = s
-        .sign_up(username, user_password, name).await
-        .map_err(|_| Error::from_status(StatusCode::CONFLICT))?;
+        .sign_up(username.clone(), user_password, name).await
+        .map_err(ApiError::from)?;
+
+    // The account works meanwhile, the user can ask for another link later
+    let _ = send_verification_email(s, mailer, mail_config, result.clone(), &username).await;
 
     Ok(Json(CreateUserOutput {
         user_id: result,
@@ -39,151 +234,426 @@ pub async fn create_user(
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/user/signin",
+    tag = "auth",
+    request_body = SignInUserInput,
+    responses((status = 200, description = "A session, or a challenge when the account has 2FA on", body = SignInOutput)),
+    security(())
+)]
 #[handler]
 pub async fn sign_in_user(
+    req: &Request,
     Json(data): Json<SignInUserInput>,
     Data(s): Data<&Arc<Store>>,
+    Data(limiter): Data<&Arc<RateLimiter>>,
 ) -> Result<Response, Error> {
     let username = data.username;
     let user_password = data.password;
 
-    let result = s.sign_in(username, user_password).await;
+    // Failures lock the account for the IP they came from, and for everyone
+    // once there are a lot of them
+    let email = username.trim().to_lowercase();
+    let account_key = format!("signin:{}", email);
+    let lockout_key = format!("signin:{}:{}", email, client_ip(req).unwrap_or_default());
+    let locked = match limiter.locked_for(&lockout_key, MAX_FAILURES).await {
+        Some(wait) => Some(wait),
+        None => limiter.locked_for(&account_key, MAX_ACCOUNT_FAILURES).await,
+    };
+    if let Some(wait) = locked {
+        return Err(too_many_requests(wait));
+    }
 
-    match result {
+    match s.sign_in(username, user_password).await {
         Ok(user) => {
-            let my_claims = Claims {
-                sub: user.id,
-                exp: 1111111111111,
-            };
-            let token = encode(
-                &Header::default(),
-                &my_claims,
-                &EncodingKey::from_secret(env::var("JWT_SECRET").map_err(|_| Error::from_string("Invalid ENV Secret", StatusCode::EXPECTATION_FAILED))?.as_ref()),
-            )
-            .map_err(|_| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
-            
-            // For localhost cross-origin, use SameSite=lax or None with proper settings
-            let cookie = format!(
-                "jwt={}; HttpOnly; SameSite=Lax; Path=/; Max-Age={};",
-                token,
-                60 * 60 * 24 * 7
-            );
-            
-            let mut resp = Response::builder()
-                .status(StatusCode::OK)
-                .header(header::SET_COOKIE, cookie)
-                .header(header::CONTENT_TYPE, "application/json")
-                .body(format!("{{\"jwt\":\"{}\"}}", token));
-
-            Ok(resp)
+            limiter.clear_failures(&lockout_key).await;
+            complete_sign_in(s, user.id, req).await
+        }
+        Err(e @ StoreError::Unauthorized) => {
+            limiter.record_failure(&lockout_key).await;
+            limiter.record_failure(&account_key).await;
+            Err(ApiError::from(e).into())
         }
-        Err(_) => Err(Error::from_status(StatusCode::NOT_FOUND)),
+        Err(e) => Err(ApiError::from(e).into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/auth/google",
+    tag = "auth",
+    request_body = SignInUserInputWithGoogle,
+    responses((status = 200, description = "A session, or a challenge when the account has 2FA on", body = SignInOutput)),
+    security(())
+)]
 #[handler]
 pub async fn google_auth(
+    req: &Request,
     Json(data): Json<SignInUserInputWithGoogle>,
     Data(s): Data<&Arc<Store>>,
+    Data(config): Data<&Arc<Config>>,
+    Data(client): Data<&reqwest::Client>,
+    Data(google): Data<&Arc<GoogleVerifier>>,
 ) -> Result<Response, Error> {
-    let username = data.username;
-    let user_password = String::from("GOOGLE_AUTH");
-    let user_name = data.user_name;
+    if config.google_client_id.is_none() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "google_unavailable",
+            "Google sign in isn't set up on this server",
+        )
+        .into());
+    }
 
-    let result = s.sign_in_with_google(username.clone()).await;
-    let exp = (chrono::Utc::now() + chrono::Duration::days(7)).timestamp() as usize;
-    match result {
-        Ok(user) => {
-            let my_claims = Claims {
-                sub: user.id,
-                exp: exp.clone(),
-            };
-            let token = encode(
-                &Header::default(),
-                &my_claims,
-                &EncodingKey::from_secret(env::var("JWT_SECRET").map_err(|_| Error::from_string("Invalid ENV Secret", StatusCode::EXPECTATION_FAILED))?.as_ref()),
-            )
-            .map_err(|_| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
-            
-            let mut resp = Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "application/json")
-                .body(format!("{{\"jwt\":\"{}\"}}", token));
-
-            Ok(resp)
-        }
-        Err(_) => {
-            let new_user = s.sign_up(username, user_password, user_name).await;
-            match new_user {
-                Ok(u) => {
-                    let my_claims = Claims {
-                        sub: u,
-                        exp,
-                    };
-                    let token = encode(
-                        &Header::default(),
-                        &my_claims,
-                        &EncodingKey::from_secret(env::var("JWT_SECRET").map_err(|_| Error::from_string("Invalid ENV Secret", StatusCode::EXPECTATION_FAILED))?.as_ref()),
-                    )
-                    .map_err(|_| Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))?;
-                    
-                    let mut resp = Response::builder()
-                        .status(StatusCode::OK)
-                        .header(header::CONTENT_TYPE, "application/json")
-                        .body(format!("{{\"jwt\":\"{}\"}}", token));
-        
-                    Ok(resp)
-                },
-                Err(_) => Err(Error::from_status(StatusCode::INTERNAL_SERVER_ERROR))
-            }
-        },
+    let claims = google
+        .verify(client, config, &data.id_token)
+        .await
+        .map_err(|e| {
+            println!("Google sign in rejected: {}", e);
+            Error::from_status(StatusCode::UNAUTHORIZED)
+        })?;
+
+    let external = ExternalIdentity {
+        provider: "google".to_string(),
+        email_verified: claims.email_verified(),
+        subject: claims.sub,
+        email: Some(claims.email),
+        name: claims.name,
+    };
+
+    let user_id = s.sign_in_with_identity(external).await.map_err(ApiError::from)?;
+    complete_sign_in(s, user_id, req).await
+}
+
+/// Second sign in step for accounts with 2FA on: trades the token from the
+/// first step and a TOTP or recovery code for a session. After SSO the token
+/// comes in the `mfa_token` cookie instead of the body.
+#[utoipa::path(
+    post,
+    path = "/api/user/signin/2fa",
+    tag = "auth",
+    request_body = SecondFactorInput,
+    responses((status = 200, description = "A session", body = SigninUserOutput)),
+    security(())
+)]
+#[handler]
+pub async fn sign_in_second_factor(
+    req: &Request,
+    Json(data): Json<SecondFactorInput>,
+    Data(s): Data<&Arc<Store>>,
+    Data(limiter): Data<&Arc<RateLimiter>>,
+) -> Result<Response, Error> {
+    let mfa_token = data
+        .mfa_token
+        .or_else(|| req.cookie().get("mfa_token").map(|c| c.value_str().to_string()))
+        .ok_or_else(|| Error::from_status(StatusCode::UNAUTHORIZED))?;
+    let user_id = decode_mfa_token(&mfa_token).map_err(Error::from_status)?;
+
+    let lockout_key = second_factor_lockout_key(&user_id);
+    if let Some(wait) = limiter.locked_for(&lockout_key, MAX_FAILURES).await {
+        return Err(too_many_requests(wait));
+    }
+
+    let actor = Actor { user_id: user_id.clone(), ip: client_ip(req) };
+    if s.verify_second_factor(&actor, data.code).await.map_err(ApiError::from)? {
+        limiter.clear_failures(&lockout_key).await;
+        let res = start_session(s, user_id, req).await?;
+        Ok(res.with_header(header::SET_COOKIE, CLEAR_MFA_COOKIE).into_response())
+    } else {
+        limiter.record_failure(&lockout_key).await;
+        Err(ApiError::from(StoreError::Unauthorized).into())
     }
 }
 
+/// Wrong 2FA codes count against the account, wherever they are entered.
+pub(crate) fn second_factor_lockout_key(user_id: &str) -> String {
+    format!("mfa:{}", user_id)
+}
+
+/// Swaps the refresh token from the cookie or body for a new token pair.
+#[utoipa::path(
+    post,
+    path = "/api/user/refresh",
+    tag = "auth",
+    request_body(content = Option<RefreshInput>, description = "Can be left out when the `refresh_token` cookie is sent"),
+    responses((status = 200, description = "A new token pair", body = SigninUserOutput)),
+    security(())
+)]
+#[handler]
+pub async fn refresh_session(
+    req: &Request,
+    data: Option<Json<RefreshInput>>,
+    Data(s): Data<&Arc<Store>>,
+) -> Result<Response, Error> {
+    let refresh_token = data
+        .map(|Json(d)| d.refresh_token)
+        .or_else(|| req.cookie().get("refresh_token").map(|c| c.value_str().to_string()))
+        .ok_or_else(|| Error::from_status(StatusCode::UNAUTHORIZED))?;
+
+    let (session, new_refresh_token) = s
+        .rotate_refresh_token(refresh_token, client_ip(req))
+        .await
+        .map_err(ApiError::from)?;
+
+    let access_token = encode_access_token(session.user_id, session.id).map_err(Error::from_status)?;
+    Ok(session_response(access_token, new_refresh_token))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/sessions",
+    tag = "users",
+    responses((status = 200, body = SessionsOutput))
+)]
+#[handler]
+pub async fn get_sessions(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<SessionsOutput>> {
+
+    let sessions = s.get_users_sessions(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(SessionsOutput { data: Some(sessions), success: true }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/sessions/revoke",
+    tag = "users",
+    request_body = RevokeSessionInput,
+    responses((status = 200, body = SessionsOutput))
+)]
+#[handler]
+pub async fn revoke_session(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<RevokeSessionInput>,
+) -> Result<Json<SessionsOutput>> {
+    let n = s.revoke_session(&user.actor(), data.session_id).await.map_err(ApiError::from)?;
+    Ok(Json(SessionsOutput { data: None, success: n > 0 }))
+}
+
+/// External logins linked to the account.
+#[utoipa::path(
+    get,
+    path = "/api/user/identities",
+    tag = "users",
+    responses((status = 200, body = IdentitiesOutput))
+)]
+#[handler]
+pub async fn get_identities(
+    Data(s): Data<&Arc<Store>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<IdentitiesOutput>> {
+    let identities = s.get_users_identities(user_id).await.map_err(ApiError::from)?;
+    Ok(Json(IdentitiesOutput { data: Some(identities), success: true }))
+}
+
+/// Sends the verification link again.
+#[utoipa::path(
+    post,
+    path = "/api/user/verify_email/send",
+    tag = "users",
+    responses((status = 200, body = SuccessOutput))
+)]
+#[handler]
+pub async fn resend_verification_email(
+    Data(s): Data<&Arc<Store>>,
+    Data(mailer): Data<&Arc<Mailer>>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<SuccessOutput>> {
+    let address = s.get_users_email(user_id.clone()).await.map_err(ApiError::from)?;
+
+    if send_verification_email(s, mailer, mail_config, user_id, &address).await.is_err() {
+        return Err(ApiError::upstream("Couldn't send the verification email").into());
+    }
+    Ok(Json(SuccessOutput { success: true }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/verify_email/confirm",
+    tag = "auth",
+    params(TokenInput),
+    responses((status = 200, description = "The email address is verified", body = String, content_type = "text/plain")),
+    security(())
+)]
+#[handler]
+pub async fn confirm_email(req: &Request, Query(data): Query<TokenInput>, Data(s): Data<&Arc<Store>>) -> Response {
+    match s.verify_email(data.token, client_ip(req)).await {
+        Ok(_) => Response::builder()
+            .status(StatusCode::OK)
+            .body("Your email address is verified"),
+        Err(e) => link_page_error(e, "Invalid, expired or already used verification link"),
+    }
+}
+
+/// Mails a confirmation link to the new address. The account keeps its
+/// current email until the link is followed.
+#[utoipa::path(
+    post,
+    path = "/api/update_email",
+    tag = "users",
+    request_body = UpdateEmailInput,
+    responses((status = 200, body = UpdateEmailOutput))
+)]
 #[handler]
 pub async fn update_email(
     Json(data): Json<UpdateEmailInput>,
     Data(s): Data<&Arc<Store>>,
-) -> Json<UpdateEmailOutput> {
-    let input_user_id = data.user_id;
-    let new_email = data.new_email;
+    Data(mailer): Data<&Arc<Mailer>>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
+    AuthUser { user_id: input_user_id, .. }: AuthUser,
+) -> Result<Json<UpdateEmailOutput>> {
+    let new_email = data.new_email.trim().to_string();
+    if !new_email.contains('@') || new_email.contains(char::is_whitespace) {
+        return Err(ApiError::invalid("new_email isn't a valid address").into());
+    }
+
+    if s.find_user_by_email(new_email.clone()).await.map_err(ApiError::from)?.is_some() {
+        return Err(ApiError::from(StoreError::Conflict("email is already in use")).into());
+    }
+
+    let token = s
+        .create_email_token(input_user_id, CHANGE_EMAIL, Some(new_email.clone()))
+        .await
+        .map_err(ApiError::from)?;
+
+    let body = format!(
+        "Please confirm that you want to use this address for your account:\n\n{}/api/user/email_change/confirm?token={}\n\nThe link expires in 24 hours. If you did not ask for this, you can ignore this email.\n",
+        mail_config.public_url, token
+    );
+
+    match mailer.send(&new_email, "Confirm your new email address", body).await {
+        Ok(_) => Ok(Json(UpdateEmailOutput { success: true })),
+        Err(e) => {
+            println!("Error: {}", e);
+            Err(ApiError::upstream("Couldn't send the confirmation email").into())
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/email_change/confirm",
+    tag = "auth",
+    params(TokenInput),
+    responses((status = 200, description = "The account uses the new address", body = String, content_type = "text/plain")),
+    security(())
+)]
+#[handler]
+pub async fn confirm_email_change(
+    Query(data): Query<TokenInput>,
+    Data(s): Data<&Arc<Store>>,
+    req: &Request,
+) -> Response {
+    match s.confirm_email_change(data.token, client_ip(req)).await {
+        Ok(address) => Response::builder()
+            .status(StatusCode::OK)
+            .body(format!("Your account now uses {}", address)),
+        Err(e) => link_page_error(e, "Invalid, expired or already used confirmation link"),
+    }
+}
 
-    let result = s.update_email(input_user_id, new_email).await;
+/// Mails a reset link if the address has an account. Always answers success,
+/// so it can't be used to find out who has one.
+#[utoipa::path(
+    post,
+    path = "/api/user/password_reset",
+    tag = "auth",
+    request_body = PasswordResetInput,
+    responses((status = 200, body = SuccessOutput)),
+    security(())
+)]
+#[handler]
+pub async fn request_password_reset(
+    Json(data): Json<PasswordResetInput>,
+    Data(s): Data<&Arc<Store>>,
+    Data(mailer): Data<&Arc<Mailer>>,
+    Data(mail_config): Data<&Arc<MailConfig>>,
+) -> Json<SuccessOutput> {
+    if let Ok(Some((user_id, _))) = s.find_user_by_email(data.email.clone()).await {
+        if let Ok(token) = s.create_email_token(user_id, RESET_PASSWORD, None).await {
+            let body = format!(
+                "Someone asked to reset the password of your account. To choose a new password, open:\n\n{}/reset-password?token={}\n\nThe link expires in 1 hour. If you did not ask for this, you can ignore this email.\n",
+                mail_config.public_url, token
+            );
 
-    match result {
-        Ok(_) => {
-            Json(UpdateEmailOutput { success: true })
+            if let Err(e) = mailer.send(&data.email, "Reset your password", body).await {
+                println!("Error: {}", e);
+            }
         }
-        Err(_) => Json(UpdateEmailOutput { success: false })
     }
+
+    Json(SuccessOutput { success: true })
+}
+
+/// Sets the new password and signs out every session.
+#[utoipa::path(
+    post,
+    path = "/api/user/password_reset/confirm",
+    tag = "auth",
+    request_body = ConfirmPasswordResetInput,
+    responses((status = 200, body = SuccessOutput)),
+    security(())
+)]
+#[handler]
+pub async fn confirm_password_reset(
+    Json(data): Json<ConfirmPasswordResetInput>,
+    Data(s): Data<&Arc<Store>>,
+    req: &Request,
+) -> Result<Json<SuccessOutput>> {
+    s.reset_password(data.token, data.new_password, client_ip(req)).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/update_password",
+    tag = "users",
+    request_body = UpdatePasswordInput,
+    responses((status = 200, body = SuccessOutput))
+)]
 #[handler]
 pub async fn update_password(
+    req: &Request,
     Json(data): Json<UpdatePasswordInput>,
     Data(s): Data<&Arc<Store>>,
-) -> Json<UpdateEmailOutput> {
-    let input_user_id = data.user_id;
+    user: AuthUser,
+) -> Result<Json<SuccessOutput>> {
     let old_password = data.old_password;
     let new_password = data.new_password;
 
-    let result = s.update_password(input_user_id, old_password, new_password).await;
-
-    match result {
-        Ok(_) => {
-            Json(UpdateEmailOutput { success: true })
-        }
-        Err(_) => Json(UpdateEmailOutput { success: false })
-    }
+    // Keep this session; every other one is signed out
+    let claims = session_claims(req).await?;
+    s.update_password(&user.actor(), claims.sid, old_password, new_password).await.map_err(ApiError::from)?;
+    Ok(Json(SuccessOutput { success: true }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/user/logout",
+    tag = "auth",
+    responses((status = 200, description = "The session is revoked and its cookies cleared")),
+    security(())
+)]
 #[handler]
-pub async fn logout_user() ->  Response {
+pub async fn logout_user(req: &Request, Data(s): Data<&Arc<Store>>) -> Response {
+    // Revoke the session so copies of its tokens stop working too
+    if let Ok(claims) = session_claims(req).await {
+        let actor = Actor { user_id: claims.sub, ip: client_ip(req) };
+        let _ = s.revoke_session(&actor, claims.sid).await;
+    }
+
     Response::builder()
     .status(StatusCode::OK)
     .header(
         header::SET_COOKIE,
         "jwt=; HttpOnly; SameSite=Lax; Path=/; Max-Age=0",
     )
+    .header(
+        header::SET_COOKIE,
+        "refresh_token=; HttpOnly; SameSite=Lax; Path=/api/user; Max-Age=0",
+    )
     .body("Logged out")
-}
\ No newline at end of file
+}
@@ -1,233 +1,299 @@
 use std::sync::{Arc};
 
 use crate::{
-    auth_middleware::UserIdFromHeader,
-    request_input::{ CreateWebsiteInput, GetUptimePercentage, GetUptimePercentageByRegion, GetWebsiteAverageRespTime, GetWebsiteAverageRespTimeByRegion, GetWebsiteDetailsDailyInput, GetWebsiteDetailsHourlyInput, GetWebsiteDetailsLastHourInput, UsersWebsites },
-    request_output::{ CreateWebsiteOutput, GetUptimePercentageOutput, GetWebsiteAvgRespTimeOutput, GetWebsiteDetailsDailyOutput, GetWebsiteDetailsHourlyOutput, GetWebsiteDetailsLastHourOutput },
+    access::{authorize_website, require_org_role, require_website_role},
+    auth_middleware::{AuthUser, MaybeAuthUser},
+    error::ApiError,
+    request_input::{ CreateWebsiteInput, OrgQuery, ShareWebsiteInput, GetUptimePercentage, GetUptimePercentageByRegion, GetWebsiteAverageRespTime, GetWebsiteAverageRespTimeByRegion, GetWebsiteDetailsDailyInput, GetWebsiteDetailsHourlyInput, GetWebsiteDetailsLastHourInput, UsersWebsites },
+    request_output::{ CreateWebsiteOutput, ShareWebsiteOutput, GetUptimePercentageOutput, GetWebsiteAvgRespTimeOutput, GetWebsiteDetailsDailyOutput, GetWebsiteDetailsHourlyOutput, GetWebsiteDetailsLastHourOutput },
 };
 use poem::{
     handler,
-    web::{Data, Json},
+    http::StatusCode,
+    web::{Data, Json, Query},
+    Error, Result,
+};
+use store::{
+    error::StoreError,
+    models::{org::{ADMIN, EDITOR, VIEWER}, website::Status},
+    store::Store,
 };
-use store::{models::website::Status, store::Store};
 
+/// Adds a website to an organization the user is at least an editor of.
+#[utoipa::path(
+    post,
+    path = "/api/website",
+    tag = "websites",
+    request_body = CreateWebsiteInput,
+    responses((status = 200, body = CreateWebsiteOutput))
+)]
 #[handler]
 pub async fn create_website(
     Json(data): Json<CreateWebsiteInput>,
-    Data(s): Data<&Arc<Store>>
-) -> Json<CreateWebsiteOutput> {
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser
+) -> Result<Json<CreateWebsiteOutput>> {
     let url = data.url;
     let about = data.about;
-    let user_id= data.user_id;
-    
-    let created_website = s.create_website(user_id, url, about).await;
-    match created_website {
-        Ok(w) => Json(CreateWebsiteOutput {
-            website_id: w.id,
-            success: true,
-        }),
-        Err(e) => Json(CreateWebsiteOutput {
-            website_id: e.to_string(),
-            success: false,
-        }),
-    }
+
+    let org_id = match data.org_id.or_else(|| user.org_id.clone()) {
+        Some(org_id) => org_id,
+        None => s
+            .get_personal_org_id(user.user_id.clone())
+            .await
+            .map_err(ApiError::from)?,
+    };
+    require_org_role(s, &user, &org_id, EDITOR).await?;
+
+    // A plan quota error reads as e.g. "Quota exceeded: the Basic plan allows 5 monitors"
+    let w = s.create_website(&user.actor(), org_id, url, about).await.map_err(ApiError::from)?;
+    Ok(Json(CreateWebsiteOutput {
+        website_id: w.id,
+        success: true,
+    }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/get_status",
+    tag = "analytics",
+    request_body = GetWebsiteDetailsLastHourInput,
+    responses((status = 200, body = Status))
+)]
 #[handler]
 pub async fn get_website_recent_status(
     Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
     Json(data): Json<GetWebsiteDetailsLastHourInput>
-) -> Json<Status> {
-    
-    let website_result = s.get_website_recent_status(data.website, data.user_id).await;
-    match website_result {
-        Ok(s) => Json(s),
-        Err(_) => Json(Status{
+) -> Result<Json<Status>> {
+    require_website_role(s, &user, &data.website, VIEWER).await?;
+
+    match s.get_website_recent_status(data.website).await {
+        Ok(s) => Ok(Json(s)),
+        // Not checked yet
+        Err(StoreError::NotFound) => Ok(Json(Status{
             status: "Unknown".into()
-        }),
+        })),
+        Err(e) => Err(ApiError::from(e).into()),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/website/hourly",
+    tag = "analytics",
+    request_body = GetWebsiteDetailsHourlyInput,
+    responses((status = 200, body = GetWebsiteDetailsHourlyOutput))
+)]
 #[handler]
 pub async fn get_details_hourly(
     Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
     Json(data): Json<GetWebsiteDetailsHourlyInput>
-) -> Json<GetWebsiteDetailsHourlyOutput> {
-    
-    let website_result = s.get_website_details_hourly(data.website, data.user_id, data.hour).await;
-    match website_result {
-        Ok(w) => Json(GetWebsiteDetailsHourlyOutput {
-            data: Some(w),
-            success: true,
-        }),
-        Err(_) => Json(GetWebsiteDetailsHourlyOutput {
-            data: None,
-            success: false,
-        }),
-    }
+) -> Result<Json<GetWebsiteDetailsHourlyOutput>> {
+    require_website_role(s, &user, &data.website, VIEWER).await?;
+
+    let w = s.get_website_details_hourly(data.website, data.hour).await.map_err(ApiError::from)?;
+    Ok(Json(GetWebsiteDetailsHourlyOutput {
+        data: Some(w),
+        success: true,
+    }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/website/daily",
+    tag = "analytics",
+    request_body = GetWebsiteDetailsDailyInput,
+    responses((status = 200, body = GetWebsiteDetailsDailyOutput))
+)]
 #[handler]
 pub async fn get_details_daily(
     Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
     Json(data): Json<GetWebsiteDetailsDailyInput>
-) -> Json<GetWebsiteDetailsDailyOutput> {
-    
-    let website_result = s.get_website_details_daily(data.website, data.user_id, data.day).await;
-    match website_result {
-        Ok(w) => Json(GetWebsiteDetailsDailyOutput {
-            data: Some(w),
-            success: true,
-        }),
-        Err(_) => Json(GetWebsiteDetailsDailyOutput {
-            data: None,
-            success: false,
-        }),
-    }
+) -> Result<Json<GetWebsiteDetailsDailyOutput>> {
+    require_website_role(s, &user, &data.website, VIEWER).await?;
+
+    let w = s.get_website_details_daily(data.website, data.day).await.map_err(ApiError::from)?;
+    Ok(Json(GetWebsiteDetailsDailyOutput {
+        data: Some(w),
+        success: true,
+    }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/website/last_hour",
+    tag = "analytics",
+    request_body = GetWebsiteDetailsLastHourInput,
+    responses((status = 200, body = GetWebsiteDetailsLastHourOutput))
+)]
 #[handler]
 pub async fn get_details_last_hour(
     Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
     Json(data): Json<GetWebsiteDetailsLastHourInput>
-) -> Json<GetWebsiteDetailsLastHourOutput> {
-    
-    let website_result = s.get_website_details_last_hour(data.website, data.user_id).await;
-    match website_result {
-        Ok(w) => Json(GetWebsiteDetailsLastHourOutput {
-            data: Some(w),
-            success: true,
-        }),
-        Err(_) => Json(GetWebsiteDetailsLastHourOutput {
-            data: None,
-            success: false,
-        }),
-    }
+) -> Result<Json<GetWebsiteDetailsLastHourOutput>> {
+    require_website_role(s, &user, &data.website, VIEWER).await?;
+
+    let w = s.get_website_details_last_hour(data.website).await.map_err(ApiError::from)?;
+    Ok(Json(GetWebsiteDetailsLastHourOutput {
+        data: Some(w),
+        success: true,
+    }))
 }
 
+/// Websites of all the user's organizations, or of `org_id`.
+#[utoipa::path(
+    get,
+    path = "/api/user/get_all_websites",
+    tag = "websites",
+    params(OrgQuery),
+    responses((status = 200, body = UsersWebsites))
+)]
 #[handler]
 pub async fn get_users_websites(
     Data(s): Data<&Arc<Store>>,
-    UserIdFromHeader(user_id): UserIdFromHeader
-) -> Json<UsersWebsites> {
-    
-    let res = s.get_users_all_websites(user_id).await;
-
-    match res {
-        Ok(websites) => {
-            let users_websites = UsersWebsites { websites: Some(websites), success: true };
-            Json(users_websites)
-        }
-        Err(_) => {
-            let users_websites = UsersWebsites { websites: None, success: false };
-            Json(users_websites)
+    user: AuthUser,
+    Query(query): Query<OrgQuery>
+) -> Result<Json<UsersWebsites>> {
+    // Keys limited to one organization never list another's websites
+    let org_id = match (user.org_id, query.org_id) {
+        (Some(key_org), Some(requested)) if key_org != requested => {
+            return Err(Error::from_status(StatusCode::FORBIDDEN));
         }
-    }
+        (Some(key_org), _) => Some(key_org),
+        (None, requested) => requested,
+    };
+
+    // Organizations requiring 2FA hide their websites until it's on
+    let two_factor = s.is_totp_enabled(user.user_id.clone()).await.map_err(ApiError::from)?;
+    let websites = s.get_member_websites(user.user_id, org_id, two_factor).await.map_err(ApiError::from)?;
+
+    Ok(Json(UsersWebsites { websites: Some(websites), success: true }))
 }
 
+/// Turns the website's public dashboard link on or off. While it is on, the
+/// analytics endpoints also answer requests carrying its `share_token`. Only
+/// organization admins can publish a website.
+#[utoipa::path(
+    post,
+    path = "/api/website/share",
+    tag = "websites",
+    request_body = ShareWebsiteInput,
+    responses((status = 200, body = ShareWebsiteOutput))
+)]
 #[handler]
+pub async fn share_website(
+    Data(s): Data<&Arc<Store>>,
+    user: AuthUser,
+    Json(data): Json<ShareWebsiteInput>
+) -> Result<Json<ShareWebsiteOutput>> {
+    require_website_role(s, &user, &data.website, ADMIN).await?;
+
+    let token = s.set_website_sharing(&user.actor(), data.website, data.enabled).await.map_err(ApiError::from)?;
+    Ok(Json(ShareWebsiteOutput { share_token: token, success: true }))
+}
 
+#[utoipa::path(
+    post,
+    path = "/api/get_avg_resp",
+    tag = "analytics",
+    request_body = GetWebsiteAverageRespTime,
+    responses((status = 200, body = GetWebsiteAvgRespTimeOutput)),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
+#[handler]
 pub async fn get_avg_resp(
     Data(s): Data<&Arc<Store>>,
+    MaybeAuthUser(user): MaybeAuthUser,
     Json(data): Json<GetWebsiteAverageRespTime>
-) ->  Json<GetWebsiteAvgRespTimeOutput> {
-    
+) -> Result<Json<GetWebsiteAvgRespTimeOutput>> {
+    authorize_website(s, user.as_ref(), &data.website, data.share_token.as_deref()).await?;
+
     let input_website = data.website;
-    let res = s.get_average_resp_time(input_website).await;
-
-    match res {
-        Ok(avg) => {
-            Json(GetWebsiteAvgRespTimeOutput {
-                data: Some(avg),
-                success: true
-            })
-        },
-        Err(e) => {
-            println!("Error: {}", e);
-            Json(GetWebsiteAvgRespTimeOutput {
-                data: None,
-                success: false
-            }) 
-        }
-    }
+    let avg = s.get_average_resp_time(input_website).await.map_err(ApiError::from)?;
+
+    Ok(Json(GetWebsiteAvgRespTimeOutput {
+        data: Some(avg),
+        success: true
+    }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/get_avg_resp_region",
+    tag = "analytics",
+    request_body = GetWebsiteAverageRespTimeByRegion,
+    responses((status = 200, body = GetWebsiteAvgRespTimeOutput)),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[handler]
-
 pub async fn get_avg_resp_by_region(
     Data(s): Data<&Arc<Store>>,
+    MaybeAuthUser(user): MaybeAuthUser,
     Json(data): Json<GetWebsiteAverageRespTimeByRegion>
-) ->  Json<GetWebsiteAvgRespTimeOutput> {
-    
+) -> Result<Json<GetWebsiteAvgRespTimeOutput>> {
+    authorize_website(s, user.as_ref(), &data.website, data.share_token.as_deref()).await?;
+
     let input_website = data.website;
     let input_region = data.region;
-    let res = s.get_average_resp_time_by_region(input_website, input_region).await;
-
-    match res {
-        Ok(avg) => {
-            Json(GetWebsiteAvgRespTimeOutput {
-                data: Some(avg),
-                success: true
-            })
-        },
-        Err(e) => {
-            println!("Error: {}", e);
-            Json(GetWebsiteAvgRespTimeOutput {
-                data: None,
-                success: false
-            }) 
-        }
-    }
+    let avg = s.get_average_resp_time_by_region(input_website, input_region).await.map_err(ApiError::from)?;
+
+    Ok(Json(GetWebsiteAvgRespTimeOutput {
+        data: Some(avg),
+        success: true
+    }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/get_uptime_percentage",
+    tag = "analytics",
+    request_body = GetUptimePercentage,
+    responses((status = 200, body = GetUptimePercentageOutput)),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[handler]
 pub async fn get_uptime_percentage(
     Data(s): Data<&Arc<Store>>,
+    MaybeAuthUser(user): MaybeAuthUser,
     Json(data): Json<GetUptimePercentage>
-) ->  Json<GetUptimePercentageOutput> {
-    
+) -> Result<Json<GetUptimePercentageOutput>> {
+    authorize_website(s, user.as_ref(), &data.website, data.share_token.as_deref()).await?;
+
     let input_website = data.website;
-    let res = s.get_average_uptime_percentage(input_website).await;
-
-    match res {
-        Ok(up) => {
-            Json(GetUptimePercentageOutput {
-                data: Some(up),
-                success: true
-            })
-        },
-        Err(e) => {
-            println!("Error: {}", e);
-            Json(GetUptimePercentageOutput {
-                data: None,
-                success: false
-            }) 
-        }
-    }
+    let up = s.get_average_uptime_percentage(input_website).await.map_err(ApiError::from)?;
+
+    Ok(Json(GetUptimePercentageOutput {
+        data: Some(up),
+        success: true
+    }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/get_uptime_percentage_region",
+    tag = "analytics",
+    request_body = GetUptimePercentageByRegion,
+    responses((status = 200, body = GetUptimePercentageOutput)),
+    security((), ("bearer" = []), ("cookie" = []))
+)]
 #[handler]
 pub async fn get_uptime_percentage_by_region(
     Data(s): Data<&Arc<Store>>,
+    MaybeAuthUser(user): MaybeAuthUser,
     Json(data): Json<GetUptimePercentageByRegion>
-) ->  Json<GetUptimePercentageOutput> {    
+) -> Result<Json<GetUptimePercentageOutput>> {
+    authorize_website(s, user.as_ref(), &data.website, data.share_token.as_deref()).await?;
+
     let input_website = data.website;
     let input_region = data.region;
-    let res = s.get_average_uptime_percentage_by_region(input_website, input_region).await;
-
-    match res {
-        Ok(up) => {
-            Json(GetUptimePercentageOutput {
-                data: Some(up),
-                success: true
-            })
-        },
-        Err(e) => {
-            println!("Error: {}", e);
-            Json(GetUptimePercentageOutput {
-                data: None,
-                success: false
-            }) 
-        }
-    }
+    let up = s.get_average_uptime_percentage_by_region(input_website, input_region).await.map_err(ApiError::from)?;
+
+    Ok(Json(GetUptimePercentageOutput {
+        data: Some(up),
+        success: true
+    }))
 }
\ No newline at end of file
@@ -0,0 +1,362 @@
+use std::{collections::HashMap, env};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, Algorithm, Validation};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use store::models::user::identity::ExternalIdentity;
+use tokio::sync::RwLock;
+
+use crate::jwks::{JwksCache, JwksError};
+
+const GITHUB_AUTHORIZE_URL: &str = "https://github.com/login/oauth/authorize";
+const GITHUB_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+pub type SsoError = JwksError;
+
+pub enum ProviderKind {
+    /// Any OpenID Connect provider, configured from its discovery document
+    Oidc { issuer: String },
+    /// GitHub OAuth apps are not OIDC, users come from its REST API instead
+    Github,
+}
+
+pub struct Provider {
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: String,
+    pub kind: ProviderKind,
+}
+
+#[derive(Deserialize, Clone)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OidcClaims {
+    sub: String,
+    email: Option<String>,
+    /// Some providers send `"true"` instead of a bool
+    #[serde(default)]
+    email_verified: Value,
+    name: Option<String>,
+    nonce: Option<String>,
+}
+
+impl OidcClaims {
+    fn email_verified(&self) -> bool {
+        self.email_verified == Value::Bool(true) || self.email_verified == "true"
+    }
+}
+
+#[derive(Deserialize)]
+struct GithubUser {
+    id: i64,
+    login: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+/// Where to send the browser to log in, and the secrets the callback has to
+/// present again, which are kept in cookies in the meantime.
+pub struct LoginRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+}
+
+/// 32 random bytes, URL safe.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// PKCE S256 challenge for `verifier`.
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// The login providers configured through `SSO_PROVIDERS`, a comma separated
+/// list of names. Each name reads `SSO_<NAME>_CLIENT_ID`,
+/// `SSO_<NAME>_CLIENT_SECRET`, optionally `SSO_<NAME>_SCOPES`, and unless it
+/// is `github`, the `SSO_<NAME>_ISSUER` to discover the provider from.
+pub struct SsoProviders {
+    providers: HashMap<String, Provider>,
+    discovery: RwLock<HashMap<String, Discovery>>,
+    jwks: JwksCache,
+}
+
+impl Default for SsoProviders {
+    fn default() -> Self {
+        let names = env::var("SSO_PROVIDERS").unwrap_or_default();
+
+        let providers = names
+            .split(',')
+            .map(|n| n.trim().to_lowercase())
+            .filter(|n| !n.is_empty())
+            .map(|name| {
+                let var = |key: &str| env::var(format!("SSO_{}_{}", name.to_uppercase(), key));
+
+                let client_id = var("CLIENT_ID")
+                    .unwrap_or_else(|_| panic!("Please provide a client id for {}", name));
+                let client_secret = var("CLIENT_SECRET")
+                    .unwrap_or_else(|_| panic!("Please provide a client secret for {}", name));
+
+                let (kind, default_scopes) = if name == "github" {
+                    (ProviderKind::Github, "read:user user:email")
+                } else {
+                    let issuer = var("ISSUER")
+                        .unwrap_or_else(|_| panic!("Please provide an issuer for {}", name));
+                    (
+                        ProviderKind::Oidc { issuer: issuer.trim_end_matches('/').to_string() },
+                        "openid email profile",
+                    )
+                };
+
+                let provider = Provider {
+                    name: name.clone(),
+                    client_id,
+                    client_secret,
+                    scopes: var("SCOPES").unwrap_or_else(|_| default_scopes.to_string()),
+                    kind,
+                };
+                (name, provider)
+            })
+            .collect();
+
+        Self {
+            providers,
+            discovery: RwLock::new(HashMap::new()),
+            jwks: JwksCache::default(),
+        }
+    }
+}
+
+impl SsoProviders {
+    pub fn get(&self, name: &str) -> Option<&Provider> {
+        self.providers.get(name)
+    }
+
+    /// The issuer's discovery document, fetched once per process.
+    async fn discover(&self, client: &reqwest::Client, issuer: &str) -> Result<Discovery, SsoError> {
+        if let Some(discovery) = self.discovery.read().await.get(issuer) {
+            return Ok(discovery.clone());
+        }
+
+        let discovery = client
+            .get(format!("{}/.well-known/openid-configuration", issuer))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Discovery>()
+            .await?;
+
+        if discovery.issuer.trim_end_matches('/') != issuer {
+            return Err(format!("Discovery document is for {}", discovery.issuer).into());
+        }
+
+        self.discovery
+            .write()
+            .await
+            .insert(issuer.to_string(), discovery.clone());
+        Ok(discovery)
+    }
+
+    /// Starts an authorization code login with PKCE.
+    pub async fn start_login(
+        &self,
+        client: &reqwest::Client,
+        provider: &Provider,
+        redirect_uri: &str,
+    ) -> Result<LoginRequest, SsoError> {
+        let authorize_url = match &provider.kind {
+            ProviderKind::Oidc { issuer } => self.discover(client, issuer).await?.authorization_endpoint,
+            ProviderKind::Github => GITHUB_AUTHORIZE_URL.to_string(),
+        };
+
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = random_token();
+        let challenge = code_challenge(&verifier);
+
+        let url = reqwest::Url::parse_with_params(
+            &authorize_url,
+            &[
+                ("response_type", "code"),
+                ("client_id", provider.client_id.as_str()),
+                ("redirect_uri", redirect_uri),
+                ("scope", provider.scopes.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+
+        Ok(LoginRequest { url: url.to_string(), state, nonce, verifier })
+    }
+
+    /// Exchanges the callback's code and maps the provider's account to an
+    /// identity. The state must already have been checked by the caller.
+    pub async fn finish_login(
+        &self,
+        client: &reqwest::Client,
+        provider: &Provider,
+        redirect_uri: &str,
+        code: &str,
+        verifier: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, SsoError> {
+        match &provider.kind {
+            ProviderKind::Oidc { issuer } => {
+                let discovery = self.discover(client, issuer).await?;
+                let tokens = exchange_code(client, provider, &discovery.token_endpoint, redirect_uri, code, verifier).await?;
+                let id_token = tokens.id_token.ok_or("Provider returned no ID token")?;
+
+                let mut claims = self.verify_id_token(client, provider, &discovery, &id_token).await?;
+                if claims.nonce.as_deref() != Some(nonce) {
+                    return Err("ID token nonce does not match".into());
+                }
+
+                // Some providers keep the email out of the ID token
+                if claims.email.is_none() {
+                    if let Some(userinfo_url) = &discovery.userinfo_endpoint {
+                        let info = client
+                            .get(userinfo_url)
+                            .bearer_auth(&tokens.access_token)
+                            .send()
+                            .await?
+                            .error_for_status()?
+                            .json::<OidcClaims>()
+                            .await?;
+                        if info.sub == claims.sub {
+                            claims.email_verified = info.email_verified.clone();
+                            claims.email = info.email;
+                            claims.name = claims.name.or(info.name);
+                        }
+                    }
+                }
+
+                Ok(ExternalIdentity {
+                    provider: provider.name.clone(),
+                    email_verified: claims.email_verified(),
+                    subject: claims.sub,
+                    email: claims.email,
+                    name: claims.name,
+                })
+            }
+            ProviderKind::Github => {
+                let tokens = exchange_code(client, provider, GITHUB_TOKEN_URL, redirect_uri, code, verifier).await?;
+
+                let user = github_get::<GithubUser>(client, &tokens.access_token, "/user").await?;
+                let emails = github_get::<Vec<GithubEmail>>(client, &tokens.access_token, "/user/emails").await?;
+                let primary = emails.into_iter().find(|e| e.primary && e.verified);
+
+                Ok(ExternalIdentity {
+                    provider: provider.name.clone(),
+                    subject: user.id.to_string(),
+                    email_verified: primary.is_some(),
+                    email: primary.map(|e| e.email),
+                    name: user.name.or(Some(user.login)),
+                })
+            }
+        }
+    }
+
+    async fn verify_id_token(
+        &self,
+        client: &reqwest::Client,
+        provider: &Provider,
+        discovery: &Discovery,
+        id_token: &str,
+    ) -> Result<OidcClaims, SsoError> {
+        let header = decode_header(id_token)?;
+        // Only asymmetric keys from the JWKS, never a shared secret
+        if !matches!(
+            header.alg,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 | Algorithm::ES256 | Algorithm::ES384
+        ) {
+            return Err(format!("ID token algorithm {:?} is not allowed", header.alg).into());
+        }
+
+        let kid = header.kid.ok_or("ID token has no key id")?;
+        let key = self.jwks.decoding_key(client, &discovery.jwks_uri, &kid).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+
+        Ok(decode::<OidcClaims>(id_token, &key, &validation)?.claims)
+    }
+}
+
+async fn exchange_code(
+    client: &reqwest::Client,
+    provider: &Provider,
+    token_url: &str,
+    redirect_uri: &str,
+    code: &str,
+    verifier: &str,
+) -> Result<TokenResponse, SsoError> {
+    let tokens = client
+        .post(token_url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", provider.client_id.as_str()),
+            ("client_secret", provider.client_secret.as_str()),
+            ("code_verifier", verifier),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TokenResponse>()
+        .await?;
+
+    Ok(tokens)
+}
+
+async fn github_get<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    access_token: &str,
+    path: &str,
+) -> Result<T, SsoError> {
+    let res = client
+        .get(format!("{}{}", GITHUB_API_URL, path))
+        .bearer_auth(access_token)
+        .header(reqwest::header::USER_AGENT, "nexus-api")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<T>()
+        .await?;
+
+    Ok(res)
+}
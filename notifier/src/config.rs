@@ -0,0 +1,46 @@
+use std::env;
+
+pub struct Config {
+    /// Email is off when this isn't set, and sending fails
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_tls: bool,
+    pub mail_from: String,
+    pub public_url: String,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, &'static str> {
+        let smtp_host = env::var("SMTP_HOST").ok();
+
+        let smtp_port = env::var("SMTP_PORT")
+            .ok()
+            .and_then(|p| p.parse::<u16>().ok())
+            .unwrap_or(587);
+
+        // Plain connections are only meant for local SMTP sinks
+        let smtp_tls = env::var("SMTP_TLS")
+            .map(|v| v != "false")
+            .unwrap_or(true);
+
+        let mail_from = match env::var("MAIL_FROM") {
+            Ok(from) => from,
+            Err(_) if smtp_host.is_none() => String::new(),
+            Err(_) => return Err("Please provide mail from address"),
+        };
+
+        let public_url = env::var("PUBLIC_URL").map_err(|_| "Please provide public url")?;
+
+        Ok(Self {
+            smtp_host,
+            smtp_port,
+            smtp_username: env::var("SMTP_USERNAME").ok(),
+            smtp_password: env::var("SMTP_PASSWORD").ok(),
+            smtp_tls,
+            mail_from,
+            public_url: public_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
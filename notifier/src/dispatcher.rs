@@ -0,0 +1,373 @@
+use chrono::{Duration, NaiveDateTime};
+use std::collections::HashMap;
+use store::{
+    error::StoreError,
+    models::{
+        report::{previous_month, ReportSchedule},
+        slo::Slo,
+        subscriber::{Subscriber, EMAIL_CHANNEL, WEBHOOK_CHANNEL},
+        website::StatusTransition,
+    },
+    store::Store,
+};
+
+use crate::{
+    mailer::{MailAttachment, Mailer},
+    report::{render_csv, render_pdf},
+    webhook::{send_webhook, StatusChangePayload},
+};
+
+pub struct Dispatcher {
+    pub store: Store,
+    pub mailer: Mailer,
+    pub client: reqwest::Client,
+    pub public_url: String,
+}
+
+/// Deliveries that keep failing are given up on for status changes older
+/// than this.
+const MAX_RETRY_HOURS: i64 = 24;
+
+/// Up and Down are the states subscribers hear about. Anything else, like
+/// Unknown when a check couldn't run, says nothing about the website.
+fn is_known(status: &str) -> bool {
+    status == "Up" || status == "Down"
+}
+
+/// Keeps the changes between Up and Down per region, looking through Unknown:
+/// Up → Unknown → Up stays quiet, Up → Unknown → Down is reported as Up → Down.
+fn notable_transitions(transitions: Vec<StatusTransition>) -> Vec<StatusTransition> {
+    let mut last_known: HashMap<String, String> = HashMap::new();
+    let mut notable = Vec::new();
+
+    for mut transition in transitions {
+        let previous = last_known.get(&transition.region).cloned().or_else(|| {
+            transition
+                .previous_status
+                .clone()
+                .filter(|status| is_known(status))
+        });
+
+        if !is_known(&transition.status) {
+            if let Some(previous) = previous {
+                last_known.insert(transition.region.clone(), previous);
+            }
+            continue;
+        }
+
+        last_known.insert(transition.region.clone(), transition.status.clone());
+
+        if let Some(previous) = previous.filter(|p| *p != transition.status) {
+            transition.previous_status = Some(previous);
+            notable.push(transition);
+        }
+    }
+
+    notable
+}
+
+/// How far a subscriber got when delivering `pending[failed]` failed. Changes
+/// at the same instant only count once all of them arrived.
+fn delivered_until(pending: &[&StatusTransition], failed: usize, resume_at: NaiveDateTime) -> NaiveDateTime {
+    pending[..failed]
+        .iter()
+        .rev()
+        .map(|t| t.changed_at)
+        .find(|at| *at < pending[failed].changed_at)
+        .unwrap_or(resume_at)
+}
+
+impl Dispatcher {
+    pub async fn run_once(&self) -> Result<(), StoreError> {
+        let websites = self.store.get_all_websites().await?;
+
+        for (url, _, _, _) in websites {
+            if let Err(e) = self.dispatch_website(url.clone()).await {
+                println!("Failed to dispatch notifications for {}: {}", url, e);
+            }
+        }
+
+        let slos = self.store.get_all_slos().await?;
+
+        for slo in slos {
+            if let Err(e) = self.check_slo(&slo).await {
+                println!("Failed to check SLO {}: {}", slo.id, e);
+            }
+        }
+
+        let month = previous_month();
+        let schedules = self.store.get_due_report_schedules(month.clone()).await?;
+
+        for schedule in schedules {
+            if let Err(e) = self.send_monthly_report(&schedule, &month).await {
+                println!("Failed to send report {}: {}", schedule.id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send_monthly_report(&self, schedule: &ReportSchedule, month: &str) -> Result<(), StoreError> {
+        let reports = self
+            .store
+            .get_users_monthly_reports(
+                schedule.user_id.clone(),
+                None,
+                schedule.website_url.clone(),
+                month.to_string(),
+            )
+            .await?;
+
+        let title = format!("Availability report for {}", month);
+        let attachments = vec![
+            MailAttachment {
+                filename: format!("uptime-report-{}.csv", month),
+                content_type: "text/csv".to_string(),
+                body: render_csv(&reports).into_bytes(),
+            },
+            MailAttachment {
+                filename: format!("uptime-report-{}.pdf", month),
+                content_type: "application/pdf".to_string(),
+                body: render_pdf(&reports, &title),
+            },
+        ];
+        let body = format!("Attached is your {}, as CSV and PDF.\n", title.to_lowercase());
+
+        // Leave the schedule due so the next loop retries a failed delivery
+        if let Err(e) = self
+            .mailer
+            .send_with_attachments(&schedule.email, &title, body, attachments)
+            .await
+        {
+            println!("Failed to email report {}: {}", schedule.id, e);
+            return Ok(());
+        }
+
+        self.store
+            .mark_report_schedule_sent(schedule.id.clone(), month.to_string())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Opens an alert when the 1h burn rate crosses the SLO's threshold and resolves
+    /// it once the burn rate drops back, emailing the owner both times.
+    async fn check_slo(&self, slo: &Slo) -> Result<(), StoreError> {
+        let burn_rate = self
+            .store
+            .get_slo_burn_rate(slo, Duration::hours(1))
+            .await?
+            .unwrap_or(0.0);
+        let open_alert = self.store.get_open_slo_alert(slo.id.clone()).await?;
+
+        let message = match open_alert {
+            None if burn_rate > slo.burn_rate_threshold => {
+                self.store.open_slo_alert(slo.id.clone(), burn_rate).await?;
+                format!(
+                    "SLO \"{}\" for {} is burning its error budget {:.1}x faster than allowed (threshold {:.1}x).",
+                    slo.name, slo.website_url, burn_rate, slo.burn_rate_threshold
+                )
+            }
+            Some(alert) if burn_rate <= slo.burn_rate_threshold => {
+                self.store.resolve_slo_alert(alert.id).await?;
+                format!(
+                    "SLO \"{}\" for {} is back below its burn-rate threshold ({:.1}x).",
+                    slo.name, slo.website_url, burn_rate
+                )
+            }
+            _ => return Ok(()),
+        };
+
+        if let Some(owner) = self.store.get_user(slo.user_id.clone()).await? {
+            let subject = format!("SLO alert: {}", slo.name);
+            if let Err(e) = self.mailer.send(&owner.email, &subject, message).await {
+                println!("Failed to send SLO alert for {}: {}", slo.id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn dispatch_website(&self, url: String) -> Result<(), StoreError> {
+        let latest = match self.store.get_latest_tick_time(url.clone()).await? {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        // First time we see this website: start from its latest tick instead of
+        // replaying the whole history to subscribers
+        let since = match self.store.get_notification_cursor(url.clone()).await? {
+            Some(c) => c,
+            None => return self.store.set_notification_cursor(url, latest).await,
+        };
+
+        let subscribers = self.store.get_confirmed_subscribers(url.clone()).await?;
+
+        // Subscribers behind after failed deliveries pick up where they got to
+        let oldest_retry = latest - Duration::hours(MAX_RETRY_HOURS);
+        let resume_at = |subscriber: &Subscriber| {
+            subscriber
+                .notified_until
+                .map_or(since, |at| at.max(oldest_retry))
+        };
+        let from = subscribers.iter().map(resume_at).fold(since, NaiveDateTime::min);
+
+        if latest <= from {
+            return Ok(());
+        }
+
+        let transitions = notable_transitions(
+            self.store
+                .get_status_transitions(url.clone(), from, latest)
+                .await?,
+        );
+
+        for subscriber in &subscribers {
+            let resume = resume_at(subscriber);
+            let pending: Vec<&StatusTransition> = transitions
+                .iter()
+                .filter(|t| t.changed_at > resume)
+                .collect();
+
+            let mut sent = 0;
+            for transition in &pending {
+                if let Err(e) = self.notify(&url, transition, subscriber).await {
+                    println!("Failed to notify subscriber {}: {}", subscriber.id, e);
+                    break;
+                }
+                sent += 1;
+            }
+
+            let notified_until = (sent < pending.len()).then(|| delivered_until(&pending, sent, resume));
+            if notified_until != subscriber.notified_until {
+                self.store
+                    .set_subscriber_notified_until(subscriber.id.clone(), notified_until)
+                    .await?;
+            }
+        }
+
+        self.store.set_notification_cursor(url, latest.max(since)).await
+    }
+
+    async fn notify(
+        &self,
+        url: &str,
+        transition: &StatusTransition,
+        subscriber: &Subscriber,
+    ) -> Result<(), String> {
+        let unsubscribe_url = format!(
+            "{}/api/unsubscribe?token={}",
+            self.public_url, subscriber.unsubscribe_token
+        );
+        let previous_status = transition.previous_status.clone().unwrap_or_default();
+
+        match subscriber.channel.as_str() {
+            EMAIL_CHANNEL => {
+                let subject = if transition.status == "Up" {
+                    format!("{} is back up", url)
+                } else {
+                    format!("{} is down", url)
+                };
+                let body = format!(
+                    "{} changed from {} to {} in region {} at {} UTC.\n\nTo stop receiving these emails, open {}\n",
+                    url,
+                    previous_status,
+                    transition.status,
+                    transition.region,
+                    transition.changed_at.format("%Y-%m-%d %H:%M:%S"),
+                    unsubscribe_url
+                );
+
+                self.mailer
+                    .send(&subscriber.target, &subject, body)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            WEBHOOK_CHANNEL => {
+                let payload = StatusChangePayload {
+                    website: url.to_string(),
+                    region: transition.region.clone(),
+                    previous_status,
+                    status: transition.status.clone(),
+                    changed_at: transition.changed_at,
+                    unsubscribe_url,
+                };
+
+                send_webhook(&self.client, &subscriber.target, &payload)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            other => Err(format!("Unknown channel {}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(region: &str, previous: Option<&str>, status: &str) -> StatusTransition {
+        StatusTransition {
+            region: region.to_string(),
+            previous_status: previous.map(str::to_string),
+            status: status.to_string(),
+            changed_at: chrono::DateTime::from_timestamp(0, 0).unwrap().naive_utc(),
+        }
+    }
+
+    fn summary(transitions: Vec<StatusTransition>) -> Vec<(String, String, String)> {
+        notable_transitions(transitions)
+            .into_iter()
+            .map(|t| (t.region, t.previous_status.unwrap(), t.status))
+            .collect()
+    }
+
+    #[test]
+    fn unknown_is_not_reported_as_down() {
+        let transitions = vec![
+            transition("eu", Some("Up"), "Unknown"),
+            transition("eu", Some("Unknown"), "Up"),
+        ];
+
+        assert!(summary(transitions).is_empty());
+    }
+
+    #[test]
+    fn looks_through_unknown_per_region() {
+        let transitions = vec![
+            transition("eu", Some("Up"), "Unknown"),
+            transition("us", Some("Down"), "Up"),
+            transition("eu", Some("Unknown"), "Down"),
+        ];
+
+        assert_eq!(
+            summary(transitions),
+            vec![
+                ("us".to_string(), "Down".to_string(), "Up".to_string()),
+                ("eu".to_string(), "Up".to_string(), "Down".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_deliveries_resume_after_the_last_complete_instant() {
+        let at = |secs| chrono::DateTime::from_timestamp(secs, 0).unwrap().naive_utc();
+        let changed_at = |secs, region| StatusTransition {
+            changed_at: at(secs),
+            ..transition(region, Some("Up"), "Down")
+        };
+        let transitions = [
+            changed_at(10, "eu"),
+            changed_at(20, "eu"),
+            changed_at(20, "us"),
+            changed_at(30, "eu"),
+        ];
+        let pending: Vec<&StatusTransition> = transitions.iter().collect();
+
+        assert_eq!(delivered_until(&pending, 0, at(5)), at(5));
+        assert_eq!(delivered_until(&pending, 1, at(5)), at(10));
+        // "eu" at 20 arrived but "us" at 20 didn't, so both are sent again
+        assert_eq!(delivered_until(&pending, 2, at(5)), at(10));
+        assert_eq!(delivered_until(&pending, 3, at(5)), at(20));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod config;
+pub mod dispatcher;
+pub mod mailer;
+pub mod report;
+pub mod webhook;
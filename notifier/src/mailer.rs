@@ -0,0 +1,183 @@
+use lettre::{
+    message::{header::ContentType, Attachment, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::config::Config;
+
+pub type MailError = Box<dyn std::error::Error + Send + Sync>;
+
+pub struct MailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+pub struct Mailer {
+    /// None when SMTP isn't configured
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: String,
+}
+
+impl Mailer {
+    pub fn new(config: &Config) -> Result<Self, MailError> {
+        let Some(smtp_host) = &config.smtp_host else {
+            return Ok(Self {
+                transport: None,
+                from: config.mail_from.clone(),
+            });
+        };
+
+        let mut builder = if config.smtp_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(smtp_host)
+        };
+
+        builder = builder.port(config.smtp_port);
+
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            transport: Some(builder.build()),
+            from: config.mail_from.clone(),
+        })
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    fn transport(&self) -> Result<&AsyncSmtpTransport<Tokio1Executor>, MailError> {
+        self.transport
+            .as_ref()
+            .ok_or_else(|| "Email is not configured, set SMTP_HOST".into())
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), MailError> {
+        let email = Message::builder()
+            .from(self.from.parse()?)
+            .to(to.parse()?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)?;
+
+        self.transport()?.send(email).await?;
+
+        Ok(())
+    }
+
+    pub async fn send_with_attachments(
+        &self,
+        to: &str,
+        subject: &str,
+        body: String,
+        attachments: Vec<MailAttachment>,
+    ) -> Result<(), MailError> {
+        let mut multipart = MultiPart::mixed().singlepart(SinglePart::plain(body));
+        for a in attachments {
+            multipart = multipart
+                .singlepart(Attachment::new(a.filename).body(a.body, ContentType::parse(&a.content_type)?));
+        }
+
+        let email = Message::builder()
+            .from(self.from.parse()?)
+            .to(to.parse()?)
+            .subject(subject)
+            .multipart(multipart)?;
+
+        self.transport()?.send(email).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    fn config(smtp_host: Option<String>, smtp_port: u16) -> Config {
+        Config {
+            smtp_host,
+            smtp_port,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_tls: false,
+            mail_from: "Uptime <alerts@example.com>".to_string(),
+            public_url: "https://status.example.com".to_string(),
+        }
+    }
+
+    // Speaks just enough SMTP to take one message and returns its DATA
+    async fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+
+        let mut data = String::new();
+        let mut in_data = false;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    write.write_all(b"250 Queued\r\n").await.unwrap();
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+
+            let command = line.to_uppercase();
+            if command.starts_with("DATA") {
+                in_data = true;
+                write.write_all(b"354 Go ahead\r\n").await.unwrap();
+            } else if command.starts_with("QUIT") {
+                write.write_all(b"221 Bye\r\n").await.unwrap();
+                break;
+            } else {
+                write.write_all(b"250 OK\r\n").await.unwrap();
+            }
+        }
+
+        data
+    }
+
+    #[tokio::test]
+    async fn sends_through_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = tokio::spawn(smtp_sink(listener));
+
+        let mailer = Mailer::new(&config(Some("127.0.0.1".to_string()), port)).unwrap();
+        mailer
+            .send("user@example.com", "example.com is down", "It stopped answering.".to_string())
+            .await
+            .unwrap();
+        drop(mailer);
+
+        let data = sink.await.unwrap();
+        assert!(data.contains("To: user@example.com"));
+        assert!(data.contains("Subject: example.com is down"));
+        assert!(data.contains("It stopped answering."));
+    }
+
+    #[tokio::test]
+    async fn fails_without_smtp() {
+        let mailer = Mailer::new(&config(None, 587)).unwrap();
+
+        assert!(!mailer.is_configured());
+        assert!(mailer
+            .send("user@example.com", "Subject", "Body".to_string())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,34 @@
+use dotenvy::dotenv;
+use notifier::{config::Config, dispatcher::Dispatcher, mailer::Mailer, webhook::webhook_client};
+use std::time::Duration;
+use store::store::Store;
+use tokio::time::sleep;
+
+async fn main_loop() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+
+    dotenv().ok();
+    let config = Config::from_env()?;
+    if config.smtp_host.is_none() {
+        return Err("Please provide smtp host".into());
+    }
+
+    let dispatcher = Dispatcher {
+        store: Store::new().await,
+        mailer: Mailer::new(&config)?,
+        client: webhook_client()?,
+        public_url: config.public_url.clone(),
+    };
+
+    loop {
+        if let Err(e) = dispatcher.run_once().await {
+            println!("{}", e);
+        }
+
+        sleep(Duration::from_secs(10)).await;
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    main_loop().await
+}
@@ -0,0 +1,142 @@
+use store::models::report::MonthlyReport;
+
+const CSV_HEADER: &str = "website,month,total_checks,uptime_percent,outage_count,downtime_minutes,mttr_minutes,avg_response_ms,p50_response_ms,p95_response_ms,p99_response_ms";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn opt(value: Option<f64>, precision: usize) -> String {
+    value
+        .map(|v| format!("{:.*}", precision, v))
+        .unwrap_or_default()
+}
+
+pub fn render_csv(reports: &[MonthlyReport]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+
+    for r in reports {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{}\n",
+            csv_field(&r.website_url),
+            r.month,
+            r.total_checks,
+            opt(r.uptime_percent, 3),
+            r.outage_count,
+            r.downtime_minutes,
+            opt(r.mttr_minutes, 1),
+            opt(r.avg_response_ms, 0),
+            opt(r.p50_response_ms, 0),
+            opt(r.p95_response_ms, 0),
+            opt(r.p99_response_ms, 0),
+        ));
+    }
+
+    csv
+}
+
+fn report_lines(reports: &[MonthlyReport], title: &str) -> Vec<String> {
+    let mut lines = vec![title.to_string(), String::new()];
+
+    for r in reports {
+        let na = |v: Option<f64>, precision: usize, unit: &str| {
+            v.map(|v| format!("{:.*}{}", precision, v, unit))
+                .unwrap_or_else(|| "n/a".to_string())
+        };
+
+        lines.push(r.website_url.clone());
+        lines.push(format!("  Uptime:            {}", na(r.uptime_percent, 3, "%")));
+        lines.push(format!("  Checks:            {}", r.total_checks));
+        lines.push(format!("  Outages:           {}", r.outage_count));
+        lines.push(format!("  Total downtime:    {} min", r.downtime_minutes));
+        lines.push(format!("  MTTR:              {}", na(r.mttr_minutes, 1, " min")));
+        lines.push(format!(
+            "  Response time:     avg {} / p50 {} / p95 {} / p99 {}",
+            na(r.avg_response_ms, 0, "ms"),
+            na(r.p50_response_ms, 0, "ms"),
+            na(r.p95_response_ms, 0, "ms"),
+            na(r.p99_response_ms, 0, "ms"),
+        ));
+        lines.push(String::new());
+    }
+
+    lines
+}
+
+fn pdf_text(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_ascii() && !c.is_ascii_control())
+        .collect::<String>()
+        .replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+}
+
+/// Renders the report as a plain A4 PDF with one line per metric, using the
+/// built-in Courier font so no font needs to be embedded.
+pub fn render_pdf(reports: &[MonthlyReport], title: &str) -> Vec<u8> {
+    const LINES_PER_PAGE: usize = 60;
+
+    let lines = report_lines(reports, title);
+    let pages: Vec<&[String]> = lines.chunks(LINES_PER_PAGE).collect();
+
+    // Objects: 1 catalog, 2 page tree, 3 font, then a page and a content stream per page
+    let mut objects: Vec<String> = Vec::new();
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 4 + i * 2).collect();
+
+    objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+    objects.push(format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        page_ids
+            .iter()
+            .map(|id| format!("{} 0 R", id))
+            .collect::<Vec<_>>()
+            .join(" "),
+        pages.len()
+    ));
+    objects.push("<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>".to_string());
+
+    for (i, page) in pages.iter().enumerate() {
+        let mut stream = String::from("BT\n/F1 10 Tf\n12 TL\n40 800 Td\n");
+        for line in page.iter() {
+            stream.push_str(&format!("({}) Tj T*\n", pdf_text(line)));
+        }
+        stream.push_str("ET");
+
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            page_ids[i] + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}\nendstream",
+            stream.len(),
+            stream
+        ));
+    }
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+
+    let xref_offset = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_offset
+    ));
+
+    pdf.into_bytes()
+}
@@ -0,0 +1,254 @@
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+    Url,
+};
+use serde::Serialize;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+pub type WebhookError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Serialize)]
+pub struct StatusChangePayload {
+    pub website: String,
+    pub region: String,
+    pub previous_status: String,
+    pub status: String,
+    pub changed_at: chrono::NaiveDateTime,
+    pub unsubscribe_url: String,
+}
+
+/// Whether `ip` is on the public internet, rather than loopback, private,
+/// link-local (where cloud metadata lives, at 169.254.169.254) or reserved.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_unspecified()
+                || v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || v4.is_documentation()
+                || a == 0
+                // Carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+
+            let segments = v6.segments();
+            // NAT64 and IPv4-compatible addresses carry an IPv4 address in
+            // their last 32 bits, which is what they end up reaching
+            let is_nat64 = segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0];
+            let is_ipv4_compatible = segments[..6] == [0; 6];
+            if (is_nat64 || is_ipv4_compatible) && !v6.is_unspecified() && !v6.is_loopback() {
+                let [.., a, b, c, d] = v6.octets();
+                return is_public_ip(IpAddr::V4([a, b, c, d].into()));
+            }
+
+            let first = segments[0];
+            !(v6.is_unspecified()
+                || v6.is_loopback()
+                || v6.is_multicast()
+                // Unique local
+                || (first & 0xfe00) == 0xfc00
+                // Link-local
+                || (first & 0xffc0) == 0xfe80
+                // Documentation
+                || (first == 0x2001 && segments[1] == 0x0db8)
+                // 6to4 relays to whatever IPv4 address follows the prefix
+                || first == 0x2002)
+        }
+    }
+}
+
+/// Fails unless `target` is an http(s) URL whose host only resolves to public
+/// addresses, so subscribers can't point deliveries at internal services.
+pub async fn check_target(target: &str) -> Result<(), WebhookError> {
+    let url = Url::parse(target)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("Webhook URLs must be http or https".into());
+    }
+
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<IpAddr> = match url.host() {
+        Some(url::Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(url::Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        Some(url::Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await?
+            .map(|a| a.ip())
+            .collect(),
+        None => return Err("Webhook URL has no host".into()),
+    };
+
+    if addrs.is_empty() || !addrs.into_iter().all(is_public_ip) {
+        return Err("Webhook URL doesn't resolve to a public address".into());
+    }
+
+    Ok(())
+}
+
+/// Drops non-public addresses when connecting, so a host that passed
+/// `check_target` can't rebind to an internal address afterwards.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|a| is_public_ip(a.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The client for deliveries. It only connects to public addresses and
+/// doesn't follow redirects, which could point anywhere.
+pub fn webhook_client() -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+}
+
+pub async fn send_webhook(
+    client: &reqwest::Client,
+    target: &str,
+    payload: &StatusChangePayload,
+) -> Result<(), WebhookError> {
+    check_target(target).await?;
+    post_webhook(client, target, payload).await
+}
+
+async fn post_webhook(
+    client: &reqwest::Client,
+    target: &str,
+    payload: &StatusChangePayload,
+) -> Result<(), WebhookError> {
+    client
+        .post(target)
+        .timeout(Duration::from_secs(10))
+        .json(payload)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    fn payload() -> StatusChangePayload {
+        StatusChangePayload {
+            website: "https://example.com".to_string(),
+            region: "eu".to_string(),
+            previous_status: "Up".to_string(),
+            status: "Down".to_string(),
+            changed_at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap().naive_utc(),
+            unsubscribe_url: "https://status.example.com/api/unsubscribe?token=t".to_string(),
+        }
+    }
+
+    // Accepts one request, answers 200 and returns its body
+    async fn receive_one(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await.unwrap();
+        reader
+            .into_inner()
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+
+        String::from_utf8(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn posts_the_payload_as_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let receiver = tokio::spawn(receive_one(listener));
+
+        post_webhook(&reqwest::Client::new(), &url, &payload()).await.unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&receiver.await.unwrap()).unwrap();
+        assert_eq!(body["website"], "https://example.com");
+        assert_eq!(body["previous_status"], "Up");
+        assert_eq!(body["status"], "Down");
+    }
+
+    #[tokio::test]
+    async fn rejects_internal_targets() {
+        for target in [
+            "http://127.0.0.1/hook",
+            "http://localhost:8080/hook",
+            "http://10.1.2.3/hook",
+            "http://192.168.0.10/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://[64:ff9b::a9fe:a9fe]/hook",
+            "http://[::7f00:1]/hook",
+            "http://[::10.0.0.1]/hook",
+            "http://[2001:db8::1]/hook",
+            "http://[2002:7f00:1::1]/hook",
+            "ftp://example.com/hook",
+        ] {
+            assert!(check_target(target).await.is_err(), "{} was allowed", target);
+        }
+
+        assert!(check_target("https://93.184.216.34/hook").await.is_ok());
+        assert!(check_target("https://[64:ff9b::5db8:d822]/hook").await.is_ok());
+        assert!(check_target("https://[2606:2800:220:1::1]/hook").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn client_refuses_hosts_resolving_to_internal_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://localhost:{}/hook", listener.local_addr().unwrap().port());
+
+        let client = webhook_client().unwrap();
+        assert!(post_webhook(&client, &url, &payload()).await.is_err());
+    }
+}
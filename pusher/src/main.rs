@@ -1,8 +1,43 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use dotenvy::dotenv;
 use redisstreams::redis::{Redis, WebsiteEvent};
-use store::store::Store;
+use store::{models::retention::PurgeTarget, store::Store};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const PURGE_BATCH_SIZE: i64 = 5_000;
+
+/// Deletes data past its plan's retention in small batches, pausing between
+/// batches so the tables stay available to the workers.
+async fn purge_loop() {
+    let s = Store::new().await;
+
+    loop {
+        for target in PurgeTarget::ALL {
+            let mut total = 0;
+            loop {
+                match s.purge_expired(target, PURGE_BATCH_SIZE).await {
+                    Ok(deleted) => {
+                        total += deleted;
+                        if (deleted as i64) < PURGE_BATCH_SIZE {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        println!("Purge of {:?} failed: {}", target, e);
+                        break;
+                    }
+                }
+                sleep(Duration::from_millis(200)).await;
+            }
+            if total > 0 {
+                println!("Purged {} expired rows from {:?}", total, target);
+            }
+        }
+        sleep(PURGE_INTERVAL).await;
+    }
+}
 
 async fn main_loop() -> Result<(), Box<dyn std::error::Error>> {
 
@@ -10,20 +45,43 @@ async fn main_loop() -> Result<(), Box<dyn std::error::Error>> {
     let mut r = Redis::default().await?;
     let s = Store::new().await;
 
+    // When each website was last queued, so none is checked more often than its plan allows
+    let mut last_queued: HashMap<String, Instant> = HashMap::new();
+
     loop {
-        let websites = s.get_all_websites().await?;
+        let websites = s.get_scheduled_websites().await?;
+        let now = Instant::now();
+
+        let mut website_events: Vec<WebsiteEvent> = Vec::new();
+        for w in websites {
+            let interval = Duration::from_secs(w.min_check_interval_secs.max(0) as u64);
+            if last_queued.get(&w.url).is_some_and(|at| now.duration_since(*at) < interval) {
+                continue;
+            }
 
-        let website_events: Vec<WebsiteEvent> = websites.into_iter().map(|w| {
-            let (url, id, users_id, is_snipp_added) = w;
-            WebsiteEvent { url, id, users_id, is_snipp_added }
-        }).collect();
+            last_queued.insert(w.url.clone(), now);
+            website_events.push(WebsiteEvent {
+                url: w.url,
+                id: w.id,
+                users_id: w.user_id,
+                is_snipp_added: w.is_snippet_added,
+                regions: w.allowed_regions,
+            });
+        }
 
         r.x_add_bulk(&website_events).await;
+
+        if let Err(e) = s.refresh_rollups().await {
+            println!("Rollup failed: {}", e);
+        }
         sleep(Duration::from_secs(10)).await;
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    main_loop().await
+    tokio::select! {
+        res = main_loop() => res,
+        _ = purge_loop() => Ok(()),
+    }
 }
\ No newline at end of file
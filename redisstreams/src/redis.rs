@@ -5,7 +5,9 @@ pub struct WebsiteEvent {
     pub url: String,
     pub id: String,
     pub users_id: String,
-    pub is_snipp_added: bool
+    pub is_snipp_added: bool,
+    /// Regions allowed to check the website, `None` for all of them
+    pub regions: Option<Vec<String>>,
 }
 pub struct Redis {
     pub conn: MultiplexedConnection,
@@ -21,10 +23,15 @@ impl Redis {
     }
 
     async fn x_add(&mut self, website: &WebsiteEvent) {
+        let mut fields = vec![("url", website.url.clone()), ("id", website.id.clone())];
+        if let Some(regions) = &website.regions {
+            fields.push(("regions", regions.join(",")));
+        }
+
         let _: Result<String, RedisError> = self.conn.xadd(
             "betteruptime:website",
             "*",
-            &[("url", website.url.clone()), ("id", website.id.clone())],
+            &fields,
         ).await;
     }
 
@@ -43,7 +50,7 @@ impl Redis {
             .conn
             .xread_options(&["betteruptime:website"], &[">"], &opts).await;
 
-        return res;
+        res
     }
 
     async fn x_ack(&mut self, consumer_group: &String, event_id: String) {
@@ -54,7 +61,7 @@ impl Redis {
     
     pub async fn x_ack_bulk(&mut self, consumer_group: &String, event_ids: &[String]) -> () {
         for event_id in event_ids {
-            self.x_ack(&consumer_group, event_id.clone()).await;
+            self.x_ack(consumer_group, event_id.clone()).await;
         }
     }
 }
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS "website_tick_website_url_region_created_at_idx";
+DROP TABLE IF EXISTS "notification_cursor";
+DROP TABLE IF EXISTS "subscribers";
@@ -0,0 +1,33 @@
+-- 1. Create table: subscribers
+CREATE TABLE "subscribers" (
+    "id" TEXT NOT NULL,
+    "website_url" TEXT NOT NULL,
+    "channel" TEXT NOT NULL,
+    "target" TEXT NOT NULL,
+    "confirm_token" TEXT UNIQUE,
+    "unsubscribe_token" TEXT UNIQUE NOT NULL,
+    "is_confirmed" BOOLEAN NOT NULL DEFAULT FALSE,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "Subscribers_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "subscribers_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "subscribers_channel_check"
+        CHECK ("channel" IN ('email', 'webhook')),
+    CONSTRAINT "subscribers_website_channel_target_key"
+        UNIQUE ("website_url", "channel", "target")
+);
+
+-- 2. Create table: notification_cursor
+CREATE TABLE "notification_cursor" (
+    "website_url" TEXT NOT NULL,
+    "last_tick_at" TIMESTAMP(3) NOT NULL,
+    CONSTRAINT "NotificationCursor_pkey" PRIMARY KEY ("website_url"),
+    CONSTRAINT "notification_cursor_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- 3. Index used to walk consecutive ticks per website and region
+CREATE INDEX "website_tick_website_url_region_created_at_idx"
+    ON "website_tick" ("website_url", "region", "createdAt");
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS "postmortems";
+DROP TABLE IF EXISTS "incident_updates";
+DROP TABLE IF EXISTS "incident_websites";
+DROP TABLE IF EXISTS "incidents";
@@ -0,0 +1,59 @@
+-- 1. Create table: incidents
+CREATE TABLE "incidents" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "title" TEXT NOT NULL,
+    "status" TEXT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "resolved_at" TIMESTAMP(3),
+    CONSTRAINT "Incidents_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "incidents_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "incidents_status_check"
+        CHECK ("status" IN ('investigating', 'identified', 'monitoring', 'resolved'))
+);
+
+-- 2. Create table: incident_websites
+CREATE TABLE "incident_websites" (
+    "incident_id" TEXT NOT NULL,
+    "website_url" TEXT NOT NULL,
+    CONSTRAINT "IncidentWebsites_pkey" PRIMARY KEY ("incident_id", "website_url"),
+    CONSTRAINT "incident_websites_incident_id_fkey"
+        FOREIGN KEY ("incident_id") REFERENCES "incidents"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "incident_websites_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- 3. Create table: incident_updates
+CREATE TABLE "incident_updates" (
+    "id" TEXT NOT NULL,
+    "incident_id" TEXT NOT NULL,
+    "status" TEXT NOT NULL,
+    "message" TEXT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "IncidentUpdates_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "incident_updates_incident_id_fkey"
+        FOREIGN KEY ("incident_id") REFERENCES "incidents"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "incident_updates_status_check"
+        CHECK ("status" IN ('investigating', 'identified', 'monitoring', 'resolved'))
+);
+
+-- 4. Create table: postmortems
+CREATE TABLE "postmortems" (
+    "incident_id" TEXT NOT NULL,
+    "content" TEXT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "Postmortems_pkey" PRIMARY KEY ("incident_id"),
+    CONSTRAINT "postmortems_incident_id_fkey"
+        FOREIGN KEY ("incident_id") REFERENCES "incidents"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "incident_websites_website_url_idx" ON "incident_websites" ("website_url");
+CREATE INDEX "incident_updates_incident_id_created_at_idx" ON "incident_updates" ("incident_id", "created_at");
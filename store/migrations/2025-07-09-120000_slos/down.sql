@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS "slo_alerts";
+DROP TABLE IF EXISTS "slos";
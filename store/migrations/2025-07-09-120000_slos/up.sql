@@ -0,0 +1,40 @@
+-- 1. Create table: slos
+CREATE TABLE "slos" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "website_url" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "target_percent" DOUBLE PRECISION NOT NULL,
+    "window_kind" TEXT NOT NULL,
+    "window_days" INTEGER NOT NULL DEFAULT 30,
+    "burn_rate_threshold" DOUBLE PRECISION NOT NULL DEFAULT 14.4,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "Slos_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "slos_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "slos_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "slos_target_percent_check"
+        CHECK ("target_percent" > 0 AND "target_percent" < 100),
+    CONSTRAINT "slos_window_kind_check"
+        CHECK ("window_kind" IN ('rolling', 'calendar_month')),
+    CONSTRAINT "slos_window_days_check"
+        CHECK ("window_days" BETWEEN 1 AND 365)
+);
+
+-- 2. Create table: slo_alerts
+CREATE TABLE "slo_alerts" (
+    "id" TEXT NOT NULL,
+    "slo_id" TEXT NOT NULL,
+    "burn_rate" DOUBLE PRECISION NOT NULL,
+    "triggered_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "resolved_at" TIMESTAMP(3),
+    CONSTRAINT "SloAlerts_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "slo_alerts_slo_id_fkey"
+        FOREIGN KEY ("slo_id") REFERENCES "slos"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "slo_alerts_slo_id_triggered_at_idx" ON "slo_alerts" ("slo_id", "triggered_at");
@@ -0,0 +1,16 @@
+-- 1. Create table: report_schedules
+CREATE TABLE "report_schedules" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "website_url" TEXT,
+    "email" TEXT NOT NULL,
+    "last_sent_month" TEXT,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "ReportSchedules_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "report_schedules_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "report_schedules_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS "website_tick_created_at_idx";
+DROP TABLE IF EXISTS "rollup_watermark";
+DROP TABLE IF EXISTS "website_tick_daily";
+DROP TABLE IF EXISTS "website_tick_hourly";
+DROP TABLE IF EXISTS "website_tick_minutely";
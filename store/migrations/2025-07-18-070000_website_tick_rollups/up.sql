@@ -0,0 +1,75 @@
+-- 1. Create table: website_tick_minutely
+CREATE TABLE "website_tick_minutely" (
+    "website_url" TEXT NOT NULL,
+    "region" TEXT NOT NULL,
+    "bucket" TIMESTAMP(3) NOT NULL,
+    "up_count" BIGINT NOT NULL,
+    "down_count" BIGINT NOT NULL,
+    "unknown_count" BIGINT NOT NULL,
+    "sum_response_ms" BIGINT NOT NULL,
+    -- Response time statistics below only cover "Up" ticks
+    "up_sum_response_ms" BIGINT NOT NULL,
+    "min_response_ms" INTEGER,
+    "max_response_ms" INTEGER,
+    "p50_response_ms" DOUBLE PRECISION,
+    "p95_response_ms" DOUBLE PRECISION,
+    "p99_response_ms" DOUBLE PRECISION,
+    CONSTRAINT "WebsiteTickMinutely_pkey" PRIMARY KEY ("website_url", "region", "bucket"),
+    CONSTRAINT "website_tick_minutely_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- 2. Create table: website_tick_hourly
+CREATE TABLE "website_tick_hourly" (
+    "website_url" TEXT NOT NULL,
+    "region" TEXT NOT NULL,
+    "bucket" TIMESTAMP(3) NOT NULL,
+    "up_count" BIGINT NOT NULL,
+    "down_count" BIGINT NOT NULL,
+    "unknown_count" BIGINT NOT NULL,
+    "sum_response_ms" BIGINT NOT NULL,
+    -- Response time statistics below only cover "Up" ticks
+    "up_sum_response_ms" BIGINT NOT NULL,
+    "min_response_ms" INTEGER,
+    "max_response_ms" INTEGER,
+    "p50_response_ms" DOUBLE PRECISION,
+    "p95_response_ms" DOUBLE PRECISION,
+    "p99_response_ms" DOUBLE PRECISION,
+    CONSTRAINT "WebsiteTickHourly_pkey" PRIMARY KEY ("website_url", "region", "bucket"),
+    CONSTRAINT "website_tick_hourly_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- 3. Create table: website_tick_daily
+CREATE TABLE "website_tick_daily" (
+    "website_url" TEXT NOT NULL,
+    "region" TEXT NOT NULL,
+    "bucket" TIMESTAMP(3) NOT NULL,
+    "up_count" BIGINT NOT NULL,
+    "down_count" BIGINT NOT NULL,
+    "unknown_count" BIGINT NOT NULL,
+    "sum_response_ms" BIGINT NOT NULL,
+    -- Response time statistics below only cover "Up" ticks
+    "up_sum_response_ms" BIGINT NOT NULL,
+    "min_response_ms" INTEGER,
+    "max_response_ms" INTEGER,
+    "p50_response_ms" DOUBLE PRECISION,
+    "p95_response_ms" DOUBLE PRECISION,
+    "p99_response_ms" DOUBLE PRECISION,
+    CONSTRAINT "WebsiteTickDaily_pkey" PRIMARY KEY ("website_url", "region", "bucket"),
+    CONSTRAINT "website_tick_daily_website_url_fkey"
+        FOREIGN KEY ("website_url") REFERENCES "websites"("url")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- 4. Create table: rollup_watermark
+CREATE TABLE "rollup_watermark" (
+    "granularity" TEXT NOT NULL,
+    "rolled_up_to" TIMESTAMP(3) NOT NULL,
+    CONSTRAINT "RollupWatermark_pkey" PRIMARY KEY ("granularity")
+);
+
+-- 5. Rollups scan raw ticks by time range
+CREATE INDEX "website_tick_created_at_idx" ON "website_tick" ("createdAt");
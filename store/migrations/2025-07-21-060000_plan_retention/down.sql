@@ -0,0 +1,9 @@
+DROP INDEX IF EXISTS "website_tick_daily_bucket_idx";
+DROP INDEX IF EXISTS "website_tick_hourly_bucket_idx";
+DROP INDEX IF EXISTS "website_tick_minutely_bucket_idx";
+DROP INDEX IF EXISTS "page_visits_visited_at_idx";
+
+ALTER TABLE "plan"
+    DROP COLUMN "rollup_retention_days",
+    DROP COLUMN "visit_retention_days",
+    DROP COLUMN "tick_retention_days";
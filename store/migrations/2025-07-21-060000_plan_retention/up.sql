@@ -0,0 +1,11 @@
+-- 1. Retention per plan, in days
+ALTER TABLE "plan"
+    ADD COLUMN "tick_retention_days" INTEGER NOT NULL DEFAULT 30,
+    ADD COLUMN "visit_retention_days" INTEGER NOT NULL DEFAULT 90,
+    ADD COLUMN "rollup_retention_days" INTEGER NOT NULL DEFAULT 400;
+
+-- 2. Purges scan these by age
+CREATE INDEX "page_visits_visited_at_idx" ON "page_visits" ("visited_at");
+CREATE INDEX "website_tick_minutely_bucket_idx" ON "website_tick_minutely" ("bucket");
+CREATE INDEX "website_tick_hourly_bucket_idx" ON "website_tick_hourly" ("bucket");
+CREATE INDEX "website_tick_daily_bucket_idx" ON "website_tick_daily" ("bucket");
@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS "page_visits_website_visited_at_idx";
+
+ALTER TABLE "plan"
+    DROP COLUMN "monthly_event_quota",
+    DROP COLUMN "allowed_regions",
+    DROP COLUMN "min_check_interval_secs",
+    DROP COLUMN "max_monitors";
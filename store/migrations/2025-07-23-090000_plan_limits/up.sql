@@ -0,0 +1,9 @@
+-- 1. Plan capabilities; a NULL region list allows every region
+ALTER TABLE "plan"
+    ADD COLUMN "max_monitors" INTEGER NOT NULL DEFAULT 5,
+    ADD COLUMN "min_check_interval_secs" INTEGER NOT NULL DEFAULT 60,
+    ADD COLUMN "allowed_regions" TEXT[],
+    ADD COLUMN "monthly_event_quota" BIGINT NOT NULL DEFAULT 10000;
+
+-- 2. Monthly event counts per website
+CREATE INDEX "page_visits_website_visited_at_idx" ON "page_visits" ("website", "visited_at");
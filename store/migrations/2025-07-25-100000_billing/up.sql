@@ -0,0 +1,32 @@
+-- 1. Price of each paid plan at the payment provider
+ALTER TABLE "plan" ADD COLUMN "provider_price_id" TEXT UNIQUE;
+
+-- 2. Create table: subscriptions
+CREATE TABLE "subscriptions" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT UNIQUE NOT NULL,
+    "provider_customer_id" TEXT NOT NULL,
+    "provider_subscription_id" TEXT UNIQUE NOT NULL,
+    "plan_name" TEXT NOT NULL,
+    "status" TEXT NOT NULL,
+    "current_period_end" TIMESTAMP(3),
+    -- Creation time of the last provider event applied, older events are ignored
+    "last_event_at" TIMESTAMP(3) NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "Subscriptions_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "subscriptions_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "subscriptions_plan_name_fkey"
+        FOREIGN KEY ("plan_name") REFERENCES "plan"("name")
+        ON DELETE RESTRICT ON UPDATE CASCADE
+);
+
+-- 3. Create table: billing_events
+-- Provider webhooks are delivered at least once; processed event ids are kept
+CREATE TABLE "billing_events" (
+    "id" TEXT NOT NULL,
+    "event_type" TEXT NOT NULL,
+    "received_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "BillingEvents_pkey" PRIMARY KEY ("id")
+);
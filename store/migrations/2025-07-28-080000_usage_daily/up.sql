@@ -0,0 +1,11 @@
+-- 1. Create table: usage_daily
+CREATE TABLE "usage_daily" (
+    "user_id" TEXT NOT NULL,
+    "day" DATE NOT NULL,
+    "events" BIGINT NOT NULL DEFAULT 0,
+    "checks" BIGINT NOT NULL DEFAULT 0,
+    CONSTRAINT "UsageDaily_pkey" PRIMARY KEY ("user_id", "day"),
+    CONSTRAINT "usage_daily_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
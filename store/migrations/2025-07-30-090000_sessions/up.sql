@@ -0,0 +1,22 @@
+-- 1. Create table: sessions
+-- Refresh tokens are stored as SHA-256 hex digests, never in the clear
+CREATE TABLE "sessions" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "refresh_token_hash" TEXT UNIQUE NOT NULL,
+    -- The token rotated out last, presenting it again means it was stolen
+    "previous_refresh_token_hash" TEXT,
+    "user_agent" TEXT,
+    "ip" TEXT,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "last_used_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "expires_at" TIMESTAMP(3) NOT NULL,
+    "revoked_at" TIMESTAMP(3),
+    CONSTRAINT "Sessions_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "sessions_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "sessions_user_id_idx" ON "sessions" ("user_id");
+CREATE INDEX "sessions_previous_refresh_token_hash_idx" ON "sessions" ("previous_refresh_token_hash");
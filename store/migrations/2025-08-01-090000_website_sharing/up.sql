@@ -0,0 +1,2 @@
+-- 1. Public dashboard links; NULL until the owner turns sharing on
+ALTER TABLE "websites" ADD COLUMN "share_token" TEXT UNIQUE;
@@ -0,0 +1,4 @@
+UPDATE "users" SET "password" = 'GOOGLE_AUTH' WHERE "password" IS NULL;
+ALTER TABLE "users" ALTER COLUMN "password" SET NOT NULL;
+
+DROP TABLE IF EXISTS "identities";
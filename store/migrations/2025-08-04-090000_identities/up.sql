@@ -0,0 +1,24 @@
+-- 1. Create table: identities
+-- One row per external login (provider + its subject id) linked to a user
+CREATE TABLE "identities" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "provider" TEXT NOT NULL,
+    "subject" TEXT NOT NULL,
+    "email" TEXT,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "last_login_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "Identities_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "identities_provider_subject_key" UNIQUE ("provider", "subject"),
+    CONSTRAINT "identities_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "identities_user_id_idx" ON "identities" ("user_id");
+
+-- 2. Accounts that only sign in through a provider have no password
+ALTER TABLE "users" ALTER COLUMN "password" DROP NOT NULL;
+
+-- 3. Drop the sentinel password Google sign-ups were stored with
+UPDATE "users" SET "password" = NULL WHERE "password" = 'GOOGLE_AUTH';
@@ -0,0 +1,27 @@
+-- 1. Whether the user proved they own their email address
+ALTER TABLE "users" ADD COLUMN "email_verified" BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Providers only link verified addresses
+UPDATE "users" SET "email_verified" = TRUE
+WHERE "id" IN (SELECT "user_id" FROM "identities");
+
+-- 2. Create table: email_tokens
+-- One-time links for verifying, changing the email and resetting the password,
+-- stored as SHA-256 hex digests
+CREATE TABLE "email_tokens" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "purpose" TEXT NOT NULL,
+    "token_hash" TEXT UNIQUE NOT NULL,
+    -- The address an email change switches to once confirmed
+    "new_email" TEXT,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "expires_at" TIMESTAMP(3) NOT NULL,
+    "used_at" TIMESTAMP(3),
+    CONSTRAINT "EmailTokens_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "email_tokens_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "email_tokens_user_id_purpose_idx" ON "email_tokens" ("user_id", "purpose");
@@ -0,0 +1,23 @@
+-- 1. TOTP secret (base32); it only counts once "totp_enabled_at" is set by
+-- confirming a first code
+ALTER TABLE "users"
+    ADD COLUMN "totp_secret" TEXT,
+    ADD COLUMN "totp_enabled_at" TIMESTAMP(3),
+    -- Last accepted 30s step, so a code can't be replayed
+    ADD COLUMN "totp_last_step" BIGINT;
+
+-- 2. Create table: recovery_codes
+-- Single-use codes for when the authenticator is lost, stored as SHA-256 hex digests
+CREATE TABLE "recovery_codes" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "code_hash" TEXT UNIQUE NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "used_at" TIMESTAMP(3),
+    CONSTRAINT "RecoveryCodes_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "recovery_codes_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "recovery_codes_user_id_idx" ON "recovery_codes" ("user_id");
@@ -0,0 +1,21 @@
+-- 1. Create table: api_keys
+-- Keys are stored as SHA-256 hex digests; "prefix" is the start of the key,
+-- kept in the clear so users can tell their keys apart
+CREATE TABLE "api_keys" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "prefix" TEXT NOT NULL,
+    "key_hash" TEXT UNIQUE NOT NULL,
+    "scopes" TEXT[] NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "expires_at" TIMESTAMP(3),
+    "last_used_at" TIMESTAMP(3),
+    "revoked_at" TIMESTAMP(3),
+    CONSTRAINT "ApiKeys_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "api_keys_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "api_keys_user_id_idx" ON "api_keys" ("user_id");
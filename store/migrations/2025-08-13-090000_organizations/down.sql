@@ -0,0 +1,6 @@
+ALTER TABLE "api_keys" DROP COLUMN IF EXISTS "org_id";
+ALTER TABLE "websites" DROP COLUMN IF EXISTS "org_id";
+
+DROP TABLE IF EXISTS "org_invitations";
+DROP TABLE IF EXISTS "org_members";
+DROP TABLE IF EXISTS "organizations";
@@ -0,0 +1,80 @@
+-- 1. Create table: organizations
+-- Every user gets a "personal" one; websites belong to organizations
+CREATE TABLE "organizations" (
+    "id" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "personal" BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Members without 2FA lose access to the organization's websites
+    "require_two_factor" BOOLEAN NOT NULL DEFAULT FALSE,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "Organizations_pkey" PRIMARY KEY ("id")
+);
+
+-- 2. Create table: org_members
+-- "role" is one of owner, admin, editor, viewer
+CREATE TABLE "org_members" (
+    "org_id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "role" TEXT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "OrgMembers_pkey" PRIMARY KEY ("org_id", "user_id"),
+    CONSTRAINT "org_members_org_id_fkey"
+        FOREIGN KEY ("org_id") REFERENCES "organizations"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "org_members_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "org_members_user_id_idx" ON "org_members" ("user_id");
+
+-- 3. Create table: org_invitations
+-- Invite links are stored as SHA-256 hex digests and can only be accepted by
+-- the invited, verified address
+CREATE TABLE "org_invitations" (
+    "id" TEXT NOT NULL,
+    "org_id" TEXT NOT NULL,
+    "email" TEXT NOT NULL,
+    "role" TEXT NOT NULL,
+    "token_hash" TEXT UNIQUE NOT NULL,
+    "invited_by" TEXT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "expires_at" TIMESTAMP(3) NOT NULL,
+    "accepted_at" TIMESTAMP(3),
+    CONSTRAINT "OrgInvitations_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "org_invitations_org_id_fkey"
+        FOREIGN KEY ("org_id") REFERENCES "organizations"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "org_invitations_invited_by_fkey"
+        FOREIGN KEY ("invited_by") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX "org_invitations_org_id_idx" ON "org_invitations" ("org_id");
+
+-- 4. Move every existing user into a personal organization, which reuses
+-- their id so their websites can be pointed at it directly
+INSERT INTO "organizations" ("id", "name", "personal")
+SELECT "id", "name", TRUE FROM "users";
+
+INSERT INTO "org_members" ("org_id", "user_id", "role")
+SELECT "id", "id", 'owner' FROM "users";
+
+-- 5. Websites belong to an organization; "user_id" stays as who created them
+-- and whose plan they count against
+ALTER TABLE "websites" ADD COLUMN "org_id" TEXT;
+UPDATE "websites" SET "org_id" = "user_id";
+ALTER TABLE "websites"
+    ALTER COLUMN "org_id" SET NOT NULL,
+    ADD CONSTRAINT "websites_org_id_fkey"
+        FOREIGN KEY ("org_id") REFERENCES "organizations"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE;
+
+CREATE INDEX "websites_org_id_idx" ON "websites" ("org_id");
+
+-- 6. API keys can be limited to one organization's websites
+ALTER TABLE "api_keys"
+    ADD COLUMN "org_id" TEXT,
+    ADD CONSTRAINT "api_keys_org_id_fkey"
+        FOREIGN KEY ("org_id") REFERENCES "organizations"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE;
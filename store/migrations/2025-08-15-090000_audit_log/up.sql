@@ -0,0 +1,31 @@
+-- 1. Create table: audit_log
+-- Who changed what, kept for compliance. Actors and organizations are plain
+-- ids rather than foreign keys so entries outlive what they refer to
+CREATE TABLE "audit_log" (
+    "id" TEXT NOT NULL,
+    -- NULL for changes made without an account, like unsubscribe links
+    "actor_id" TEXT,
+    "ip" TEXT,
+    "org_id" TEXT,
+    "action" TEXT NOT NULL,
+    "target_type" TEXT NOT NULL,
+    "target_id" TEXT NOT NULL,
+    "before" JSONB,
+    "after" JSONB,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "AuditLog_pkey" PRIMARY KEY ("id")
+);
+
+CREATE INDEX "audit_log_org_id_created_at_idx" ON "audit_log" ("org_id", "created_at");
+CREATE INDEX "audit_log_actor_id_created_at_idx" ON "audit_log" ("actor_id", "created_at");
+
+-- 2. Entries can be added but never changed or removed
+CREATE FUNCTION "audit_log_append_only"() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "audit_log_append_only"
+    BEFORE UPDATE OR DELETE ON "audit_log"
+    FOR EACH ROW EXECUTE FUNCTION "audit_log_append_only"();
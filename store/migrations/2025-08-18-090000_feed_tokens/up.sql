@@ -0,0 +1,3 @@
+-- 1. Secret for the user's combined status feed; the feed is off while it is NULL
+ALTER TABLE "users"
+    ADD COLUMN "feed_token" TEXT UNIQUE;
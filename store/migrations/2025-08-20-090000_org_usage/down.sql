@@ -0,0 +1,19 @@
+-- Team organizations' usage has no single user to go back to and is dropped
+ALTER TABLE "usage_daily" ADD COLUMN "user_id" TEXT;
+
+UPDATE "usage_daily" u SET "user_id" = m."user_id"
+FROM "org_members" m
+JOIN "organizations" o ON o."id" = m."org_id"
+WHERE m."org_id" = u."org_id" AND o."personal";
+
+DELETE FROM "usage_daily" WHERE "user_id" IS NULL;
+
+ALTER TABLE "usage_daily"
+    DROP CONSTRAINT "UsageDaily_pkey",
+    DROP CONSTRAINT "usage_daily_org_id_fkey",
+    DROP COLUMN "org_id",
+    ALTER COLUMN "user_id" SET NOT NULL,
+    ADD CONSTRAINT "UsageDaily_pkey" PRIMARY KEY ("user_id", "day"),
+    ADD CONSTRAINT "usage_daily_user_id_fkey"
+        FOREIGN KEY ("user_id") REFERENCES "users"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE;
@@ -0,0 +1,20 @@
+-- 1. Usage is counted per organization, like the websites producing it.
+-- Existing counts move to their user's personal organization
+ALTER TABLE "usage_daily" ADD COLUMN "org_id" TEXT;
+
+UPDATE "usage_daily" u SET "org_id" = m."org_id"
+FROM "org_members" m
+JOIN "organizations" o ON o."id" = m."org_id"
+WHERE m."user_id" = u."user_id" AND o."personal";
+
+DELETE FROM "usage_daily" WHERE "org_id" IS NULL;
+
+ALTER TABLE "usage_daily"
+    DROP CONSTRAINT "UsageDaily_pkey",
+    DROP CONSTRAINT "usage_daily_user_id_fkey",
+    DROP COLUMN "user_id",
+    ALTER COLUMN "org_id" SET NOT NULL,
+    ADD CONSTRAINT "UsageDaily_pkey" PRIMARY KEY ("org_id", "day"),
+    ADD CONSTRAINT "usage_daily_org_id_fkey"
+        FOREIGN KEY ("org_id") REFERENCES "organizations"("id")
+        ON DELETE CASCADE ON UPDATE CASCADE;
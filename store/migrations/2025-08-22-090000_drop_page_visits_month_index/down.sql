@@ -0,0 +1 @@
+CREATE INDEX "page_visits_website_visited_at_idx" ON "page_visits" ("website", "visited_at");
@@ -0,0 +1,4 @@
+-- 1. Events are metered in usage_daily, so nothing counts page_visits by
+-- website and month any more. Dashboard queries only look at recent visits,
+-- which "page_visits_visited_at_idx" narrows down
+DROP INDEX IF EXISTS "page_visits_website_visited_at_idx";
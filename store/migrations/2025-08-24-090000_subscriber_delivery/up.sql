@@ -0,0 +1,5 @@
+-- 1. How far status changes have reached a subscriber whose delivery failed,
+--    so they are retried; NULL while the subscriber is caught up with the
+--    website's notification cursor
+ALTER TABLE "subscribers"
+    ADD COLUMN "notified_until" TIMESTAMP;
@@ -0,0 +1,70 @@
+use std::fmt;
+
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel_async::pooled_connection::{bb8::RunError, PoolError};
+
+use crate::models::plan::QuotaError;
+
+/// Why a `Store` method failed.
+#[derive(Debug)]
+pub enum StoreError {
+    /// No such row, or one the caller can't see
+    NotFound,
+    /// Clashes with existing data, like a taken email, or would break a rule
+    /// such as an organization keeping an owner
+    Conflict(&'static str),
+    /// A wrong password, token or code
+    Unauthorized,
+    /// Input the store refuses, like an unknown role
+    Invalid(&'static str),
+    /// Would exceed a plan limit
+    Quota(QuotaError),
+    /// No connection became free before the pool timed out
+    PoolExhausted,
+    /// The database failed or couldn't be reached
+    Database(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NotFound => write!(f, "Not found"),
+            StoreError::Conflict(reason) => write!(f, "Conflict: {}", reason),
+            StoreError::Unauthorized => write!(f, "Unauthorized"),
+            StoreError::Invalid(reason) => write!(f, "Invalid input: {}", reason),
+            StoreError::Quota(e) => write!(f, "{}", e),
+            StoreError::PoolExhausted => write!(f, "Timed out waiting for a database connection"),
+            StoreError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<DieselError> for StoreError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => StoreError::NotFound,
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => StoreError::Conflict("already exists"),
+            // What the row points at is gone or was never there
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => StoreError::NotFound,
+            e => StoreError::Database(Box::new(e)),
+        }
+    }
+}
+
+impl From<RunError> for StoreError {
+    fn from(e: RunError) -> Self {
+        match e {
+            RunError::TimedOut => StoreError::PoolExhausted,
+            RunError::User(PoolError::QueryError(e)) => e.into(),
+            RunError::User(e) => StoreError::Database(Box::new(e)),
+        }
+    }
+}
+
+impl From<QuotaError> for StoreError {
+    fn from(e: QuotaError) -> Self {
+        StoreError::Quota(e)
+    }
+}
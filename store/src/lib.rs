@@ -1,4 +1,8 @@
 pub mod config;
+pub mod error;
 pub mod models;
 pub mod schema;
-pub mod store;
\ No newline at end of file
+pub mod store;
+
+#[cfg(test)]
+mod test_db;
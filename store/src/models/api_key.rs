@@ -0,0 +1,269 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    audit::{Actor, Change},
+    user::session::{hash_token, new_refresh_token},
+};
+
+pub const MONITORS_READ: &str = "monitors:read";
+pub const MONITORS_WRITE: &str = "monitors:write";
+pub const ANALYTICS_READ: &str = "analytics:read";
+
+pub const API_KEY_SCOPES: [&str; 3] = [MONITORS_READ, MONITORS_WRITE, ANALYTICS_READ];
+
+/// Every key starts with this, so they're recognisable in headers and in
+/// secret scanners.
+pub const API_KEY_MARKER: &str = "nx_";
+
+/// Characters of the key kept in the clear, marker included.
+const PREFIX_LEN: usize = 11;
+
+/// `last_used_at` is written at most this often per key, so busy keys don't
+/// turn every request into a write.
+const LAST_USED_RESOLUTION: Duration = Duration::minutes(1);
+
+const API_KEY: &str = "api_key";
+const API_KEY_CREATE: &str = "api_key.create";
+const API_KEY_REVOKE: &str = "api_key.revoke";
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::api_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiKey {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    /// Limits the key to this organization's websites
+    pub org_id: Option<String>,
+}
+
+/// Who an API key acts for.
+pub struct ApiKeyPrincipal {
+    pub user_id: String,
+    pub scopes: Vec<String>,
+    pub org_id: Option<String>,
+}
+
+impl Store {
+    /// Creates a key and returns it with the key itself, which is only ever
+    /// available here. Fails with `Invalid` on unknown or missing scopes.
+    pub async fn create_api_key(
+        &self,
+        actor: &Actor,
+        input_name: String,
+        input_scopes: Vec<String>,
+        expires_in: Option<Duration>,
+        input_org_id: Option<String>,
+    ) -> Result<(ApiKey, String), StoreError> {
+        use crate::schema::api_keys;
+
+        if input_scopes.is_empty() || !input_scopes.iter().all(|s| API_KEY_SCOPES.contains(&s.as_str())) {
+            return Err(StoreError::Invalid("unknown or missing scopes"));
+        }
+
+        let mut conn = self.pool.get().await?;
+
+        let key = format!("{}{}", API_KEY_MARKER, new_refresh_token());
+        let now = Utc::now().naive_utc();
+
+        let mut scopes = input_scopes;
+        scopes.sort();
+        scopes.dedup();
+
+        let new_key = ApiKey {
+            id: Uuid::new_v4().to_string(),
+            user_id: actor.user_id.clone(),
+            name: input_name,
+            prefix: key[..PREFIX_LEN].to_string(),
+            key_hash: hash_token(&key),
+            scopes,
+            created_at: now,
+            expires_at: expires_in.map(|d| now + d),
+            last_used_at: None,
+            revoked_at: None,
+            org_id: input_org_id,
+        };
+
+        let api_key = conn
+            .transaction::<_, StoreError, _>(|conn| {
+                async move {
+                    let api_key = diesel::insert_into(api_keys::table)
+                        .values(new_key)
+                        .returning(ApiKey::as_returning())
+                        .get_result(conn)
+                        .await?;
+
+                    let mut change = Change::new(API_KEY_CREATE, API_KEY, &api_key.id).after(&api_key);
+                    if let Some(o) = &api_key.org_id {
+                        change = change.org(o);
+                    }
+                    change.record(conn, actor).await?;
+
+                    Ok(api_key)
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok((api_key, key))
+    }
+
+    /// Looks up an unrevoked, unexpired key and records its use, to the
+    /// minute.
+    pub async fn authenticate_api_key(&self, input_key: String) -> Result<ApiKeyPrincipal, StoreError> {
+        use crate::schema::api_keys::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let now = Utc::now().naive_utc();
+
+        let (key_id, key_user_id, key_scopes, key_org_id, key_last_used_at) = api_keys
+            .filter(key_hash.eq(hash_token(&input_key)))
+            .filter(revoked_at.is_null())
+            .filter(expires_at.is_null().or(expires_at.gt(now)))
+            .select((id, user_id, scopes, org_id, last_used_at))
+            .first::<(String, String, Vec<String>, Option<String>, Option<NaiveDateTime>)>(&mut conn)
+            .await?;
+
+        let stale = now - LAST_USED_RESOLUTION;
+        if key_last_used_at.is_none_or(|t| t < stale) {
+            // Concurrent requests race for the same write; the filter lets one win
+            diesel::update(
+                api_keys
+                    .filter(id.eq(&key_id))
+                    .filter(last_used_at.is_null().or(last_used_at.lt(stale))),
+            )
+            .set(last_used_at.eq(Some(now)))
+            .execute(&mut conn)
+            .await?;
+        }
+
+        Ok(ApiKeyPrincipal { user_id: key_user_id, scopes: key_scopes, org_id: key_org_id })
+    }
+
+    pub async fn get_users_api_keys(&self, input_user_id: String) -> Result<Vec<ApiKey>, StoreError> {
+        use crate::schema::api_keys::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = api_keys
+            .filter(user_id.eq(input_user_id))
+            .order(created_at.desc())
+            .select(ApiKey::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// Revokes one of the user's keys. Fails with `NotFound` if it isn't
+    /// theirs or is already revoked.
+    pub async fn revoke_api_key(&self, actor: &Actor, input_key_id: String) -> Result<(), StoreError> {
+        use crate::schema::api_keys::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let revoked = diesel::update(
+                    api_keys
+                        .filter(id.eq(input_key_id))
+                        .filter(user_id.eq(&actor.user_id))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(Some(Utc::now().naive_utc())))
+                .returning(ApiKey::as_returning())
+                .get_result(conn)
+                .await?;
+
+                let mut change = Change::new(API_KEY_REVOKE, API_KEY, &revoked.id).after(&revoked);
+                if let Some(o) = &revoked.org_id {
+                    change = change.org(o);
+                }
+                change.record(conn, actor).await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::test_store;
+
+    #[tokio::test]
+    async fn keys_carry_their_scopes_and_organization() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let actor = Actor { user_id: user_id.clone(), ip: None };
+        let team = s.create_organization(&actor, "Team".to_string()).await.unwrap();
+
+        for scopes in [vec![], vec!["monitors:delete".to_string()]] {
+            let res = s.create_api_key(&actor, "CI".to_string(), scopes, None, None).await;
+            assert!(matches!(res, Err(StoreError::Invalid(_))));
+        }
+
+        let scopes = vec![MONITORS_WRITE.to_string(), MONITORS_READ.to_string(), MONITORS_READ.to_string()];
+        let (api_key, key) = s
+            .create_api_key(&actor, "CI".to_string(), scopes, None, Some(team.id.clone()))
+            .await
+            .unwrap();
+        assert!(key.starts_with(API_KEY_MARKER));
+        assert_eq!(api_key.prefix, key[..PREFIX_LEN]);
+
+        let principal = s.authenticate_api_key(key).await.unwrap();
+        assert_eq!(principal.user_id, user_id);
+        assert_eq!(principal.scopes, vec![MONITORS_READ, MONITORS_WRITE]);
+        assert_eq!(principal.org_id, Some(team.id));
+
+        let (_, account_key) = s
+            .create_api_key(&actor, "Script".to_string(), vec![ANALYTICS_READ.to_string()], None, None)
+            .await
+            .unwrap();
+        assert_eq!(s.authenticate_api_key(account_key).await.unwrap().org_id, None);
+    }
+
+    #[tokio::test]
+    async fn revoked_and_expired_keys_stop_working() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let actor = Actor { user_id, ip: None };
+        let other = Actor {
+            user_id: s.sign_up("b@example.com".to_string(), "hunter22".to_string(), "B".to_string()).await.unwrap(),
+            ip: None,
+        };
+        let scopes = vec![MONITORS_READ.to_string()];
+
+        let (api_key, key) = s.create_api_key(&actor, "CI".to_string(), scopes.clone(), None, None).await.unwrap();
+        assert!(s.authenticate_api_key(key.clone()).await.is_ok());
+
+        // Only the key's owner can revoke it
+        assert!(matches!(s.revoke_api_key(&other, api_key.id.clone()).await, Err(StoreError::NotFound)));
+        s.revoke_api_key(&actor, api_key.id.clone()).await.unwrap();
+        assert!(matches!(s.authenticate_api_key(key).await, Err(StoreError::NotFound)));
+        assert!(matches!(s.revoke_api_key(&actor, api_key.id).await, Err(StoreError::NotFound)));
+
+        let (_, expired) = s
+            .create_api_key(&actor, "Old".to_string(), scopes, Some(Duration::seconds(-1)), None)
+            .await
+            .unwrap();
+        assert!(matches!(s.authenticate_api_key(expired).await, Err(StoreError::NotFound)));
+    }
+}
@@ -1,5 +1,5 @@
-use crate::{models::user::{User, UserOutput}, schema::page_visits, store::Store};
-use diesel::{prelude::*, result::Error};
+use crate::{error::StoreError, models::user::{User, UserOutput}, schema::page_visits, store::Store};
+use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 
 #[derive(Queryable, Insertable, Selectable)]
@@ -14,10 +14,9 @@ pub struct PageVisit {
 }
 
 impl Store {
-    pub async fn get_user(&self, input_user_id: String) -> Result<Option<UserOutput>, Error> {
+    pub async fn get_user(&self, input_user_id: String) -> Result<Option<UserOutput>, StoreError> {
         use crate::schema::users::dsl::*;
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
         let user = users.filter(id.eq(input_user_id)).select(User::as_select()).get_result(&mut conn).await;
 
         match user {
@@ -31,9 +30,8 @@ impl Store {
         }
     }
 
-    pub async fn store_tracks(&self, page_visit_data: PageVisit) -> Result<PageVisit, Error> {
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+    pub async fn store_tracks(&self, page_visit_data: PageVisit) -> Result<PageVisit, StoreError> {
+        let mut conn = self.pool.get().await?;
         let created_page_visit = diesel::insert_into(page_visits::table)
             .values(page_visit_data)
             .returning(PageVisit::as_returning())
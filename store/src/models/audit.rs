@@ -0,0 +1,168 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Most entries returned by one `get_audit_log` call.
+pub const MAX_AUDIT_PAGE: i64 = 200;
+
+/// Who is making a change, recorded with it in the audit log.
+#[derive(Clone)]
+pub struct Actor {
+    pub user_id: String,
+    pub ip: Option<String>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::audit_log)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuditEntry {
+    pub id: String,
+    pub actor_id: Option<String>,
+    pub ip: Option<String>,
+    pub org_id: Option<String>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub created_at: NaiveDateTime,
+}
+
+/// A change to record, with the state of the target before and after it.
+/// Secrets never go in; the model structs already skip them when serialized.
+pub(crate) struct Change {
+    pub org_id: Option<String>,
+    pub action: &'static str,
+    pub target_type: &'static str,
+    pub target_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl Change {
+    pub fn new(action: &'static str, target_type: &'static str, target_id: impl Into<String>) -> Self {
+        Change {
+            org_id: None,
+            action,
+            target_type,
+            target_id: target_id.into(),
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn org(mut self, org_id: impl Into<String>) -> Self {
+        self.org_id = Some(org_id.into());
+        self
+    }
+
+    pub fn before(mut self, before: &impl Serialize) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self
+    }
+
+    pub fn after(mut self, after: &impl Serialize) -> Self {
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+
+    /// Appends the change to the audit log, on the caller's connection so it
+    /// commits or rolls back together with the change itself.
+    pub async fn record<C>(self, conn: &mut C, actor: &Actor) -> Result<(), StoreError>
+    where
+        C: AsyncConnection<Backend = diesel::pg::Pg>,
+    {
+        self.insert(conn, Some(actor.user_id.clone()), actor.ip.clone()).await
+    }
+
+    /// Like `record`, for changes made without an account.
+    pub async fn record_anonymous<C>(self, conn: &mut C, ip: Option<String>) -> Result<(), StoreError>
+    where
+        C: AsyncConnection<Backend = diesel::pg::Pg>,
+    {
+        self.insert(conn, None, ip).await
+    }
+
+    async fn insert<C>(self, conn: &mut C, actor_id: Option<String>, ip: Option<String>) -> Result<(), StoreError>
+    where
+        C: AsyncConnection<Backend = diesel::pg::Pg>,
+    {
+        diesel::insert_into(crate::schema::audit_log::table)
+            .values(AuditEntry {
+                id: Uuid::new_v4().to_string(),
+                actor_id,
+                ip,
+                org_id: self.org_id,
+                action: self.action.to_string(),
+                target_type: self.target_type.to_string(),
+                target_id: self.target_id,
+                before: self.before,
+                after: self.after,
+                created_at: Utc::now().naive_utc(),
+            })
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Narrows `get_audit_log`. Exactly one of `org_id` and `actor_id` should
+/// scope the query; the rest are optional filters.
+#[derive(Default)]
+pub struct AuditFilter {
+    pub org_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl Store {
+    /// Matching entries, newest first, `limit` at a time starting at `offset`.
+    pub async fn get_audit_log(&self, filter: AuditFilter, limit: i64, offset: i64) -> Result<Vec<AuditEntry>, StoreError> {
+        use crate::schema::audit_log::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let mut query = audit_log.select(AuditEntry::as_select()).into_boxed();
+
+        if let Some(o) = filter.org_id {
+            query = query.filter(org_id.eq(o));
+        }
+        if let Some(a) = filter.actor_id {
+            query = query.filter(actor_id.eq(a));
+        }
+        if let Some(a) = filter.action {
+            query = query.filter(action.eq(a));
+        }
+        if let Some(t) = filter.target_type {
+            query = query.filter(target_type.eq(t));
+        }
+        if let Some(t) = filter.target_id {
+            query = query.filter(target_id.eq(t));
+        }
+        if let Some(t) = filter.since {
+            query = query.filter(created_at.ge(t));
+        }
+        if let Some(t) = filter.until {
+            query = query.filter(created_at.lt(t));
+        }
+
+        let res = query
+            .order((created_at.desc(), id.desc()))
+            .limit(limit.clamp(1, MAX_AUDIT_PAGE))
+            .offset(offset.max(0))
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,384 @@
+use crate::{
+    error::StoreError,
+    models::{
+        audit::{Actor, Change},
+        org::OWNER,
+        plan::{refresh_org_plan, Plan},
+    },
+    store::Store,
+};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Plan users fall back to when a subscription ends.
+pub const DEFAULT_PLAN: &str = "Basic";
+
+pub const ACTIVE: &str = "active";
+pub const TRIALING: &str = "trialing";
+pub const PAST_DUE: &str = "past_due";
+
+const SUBSCRIPTION: &str = "subscription";
+const SUBSCRIPTION_START: &str = "subscription.start";
+const SUBSCRIPTION_CHANGE: &str = "subscription.change";
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::subscriptions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Subscription {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub provider_customer_id: String,
+    pub provider_subscription_id: String,
+    pub plan_name: String,
+    pub status: String,
+    pub current_period_end: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub last_event_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+/// A subscription state reported by the payment provider.
+pub struct SubscriptionChange {
+    pub provider_subscription_id: String,
+    pub status: String,
+    pub price_id: Option<String>,
+    pub current_period_end: Option<NaiveDateTime>,
+    pub event_at: NaiveDateTime,
+}
+
+/// Past-due subscriptions keep their plan while the provider retries payment;
+/// anything else that is not active ends it.
+fn keeps_plan(status: &str) -> bool {
+    status == ACTIVE || status == TRIALING || status == PAST_DUE
+}
+
+impl Subscription {
+    /// Whether the subscription still holds its plan.
+    pub fn is_current(&self) -> bool {
+        keeps_plan(&self.status)
+    }
+}
+
+/// Provider events have no account behind them; they are logged as the
+/// subscriber's, whose plan they change.
+fn subscriber(user_id: &str) -> Actor {
+    Actor { user_id: user_id.to_string(), ip: None }
+}
+
+/// Moves the user to `plan`, and the websites of organizations they own to
+/// whichever plan each now runs on.
+async fn set_users_plan(conn: &mut AsyncPgConnection, input_user_id: &str, plan: &str) -> Result<(), StoreError> {
+    use crate::schema::{org_members, users};
+
+    diesel::update(users::table.filter(users::id.eq(input_user_id)))
+        .set(users::plan_name.eq(plan))
+        .execute(conn)
+        .await?;
+
+    let owned = org_members::table
+        .filter(org_members::user_id.eq(input_user_id))
+        .filter(org_members::role.eq(OWNER))
+        .select(org_members::org_id)
+        .load::<String>(conn)
+        .await?;
+
+    for org_id in owned {
+        refresh_org_plan(conn, &org_id).await?;
+    }
+
+    Ok(())
+}
+
+impl Store {
+    pub async fn get_plan_by_price_id(&self, input_price_id: String) -> Result<Plan, StoreError> {
+        use crate::schema::plan::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = plan
+            .filter(provider_price_id.eq(input_price_id))
+            .select(Plan::as_select())
+            .first(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_users_subscription(&self, input_user_id: String) -> Result<Option<Subscription>, StoreError> {
+        use crate::schema::subscriptions::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = subscriptions
+            .filter(user_id.eq(input_user_id))
+            .select(Subscription::as_select())
+            .first(&mut conn)
+            .await
+            .optional()?;
+
+        Ok(res)
+    }
+
+    pub async fn is_billing_event_processed(&self, input_event_id: String) -> Result<bool, StoreError> {
+        use crate::schema::billing_events::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let count = billing_events
+            .filter(id.eq(input_event_id))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    pub async fn mark_billing_event_processed(
+        &self,
+        input_event_id: String,
+        input_event_type: String,
+    ) -> Result<(), StoreError> {
+        use crate::schema::billing_events::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        diesel::insert_into(billing_events)
+            .values((
+                id.eq(input_event_id),
+                event_type.eq(input_event_type),
+                received_at.eq(Utc::now().naive_utc()),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records a completed checkout and moves the user to the purchased plan.
+    /// Like status changes, a checkout older than the last event applied is
+    /// ignored, so one delivered late can't revive a deleted subscription.
+    pub async fn start_subscription(
+        &self,
+        input_user_id: String,
+        input_customer_id: String,
+        input_subscription_id: String,
+        input_plan_name: String,
+        event_at: NaiveDateTime,
+    ) -> Result<Subscription, StoreError> {
+        use crate::schema::subscriptions;
+
+        let mut conn = self.pool.get().await?;
+
+        let new_subscription = Subscription {
+            id: Uuid::new_v4().to_string(),
+            user_id: input_user_id.clone(),
+            provider_customer_id: input_customer_id,
+            provider_subscription_id: input_subscription_id,
+            plan_name: input_plan_name.clone(),
+            status: ACTIVE.to_string(),
+            current_period_end: None,
+            last_event_at: event_at,
+            created_at: Utc::now().naive_utc(),
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let current = subscriptions::table
+                    .filter(subscriptions::user_id.eq(&input_user_id))
+                    .select(Subscription::as_select())
+                    .for_update()
+                    .first(conn)
+                    .await
+                    .optional()?;
+
+                if let Some(current) = &current {
+                    if event_at < current.last_event_at {
+                        return Ok(current.clone());
+                    }
+                }
+
+                let subscription = diesel::insert_into(subscriptions::table)
+                    .values(&new_subscription)
+                    .on_conflict(subscriptions::user_id)
+                    .do_update()
+                    .set((
+                        subscriptions::provider_customer_id.eq(&new_subscription.provider_customer_id),
+                        subscriptions::provider_subscription_id.eq(&new_subscription.provider_subscription_id),
+                        subscriptions::plan_name.eq(&new_subscription.plan_name),
+                        subscriptions::status.eq(ACTIVE),
+                        subscriptions::last_event_at.eq(event_at),
+                    ))
+                    .returning(Subscription::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                set_users_plan(conn, &input_user_id, &input_plan_name).await?;
+
+                let mut change = Change::new(SUBSCRIPTION_START, SUBSCRIPTION, &subscription.id).after(&subscription);
+                if let Some(current) = &current {
+                    change = change.before(current);
+                }
+                change.record(conn, &subscriber(&input_user_id)).await?;
+
+                Ok(subscription)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Applies a provider status change. Changes older than the last one applied
+    /// are ignored since webhooks may arrive out of order. Changes to a
+    /// subscription whose checkout hasn't arrived yet are a `Conflict`, so the
+    /// provider retries them rather than having them dropped.
+    pub async fn apply_subscription_change(&self, change: SubscriptionChange) -> Result<Subscription, StoreError> {
+        use crate::schema::{plan, subscriptions};
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let Some(current) = subscriptions::table
+                    .filter(subscriptions::provider_subscription_id.eq(&change.provider_subscription_id))
+                    .select(Subscription::as_select())
+                    .for_update()
+                    .first(conn)
+                    .await
+                    .optional()?
+                else {
+                    return Err(StoreError::Conflict("no checkout has been recorded for this subscription yet"));
+                };
+
+                if change.event_at < current.last_event_at {
+                    return Ok(current);
+                }
+
+                let new_plan = if !keeps_plan(&change.status) {
+                    DEFAULT_PLAN.to_string()
+                } else if let Some(price_id) = &change.price_id {
+                    plan::table
+                        .filter(plan::provider_price_id.eq(price_id))
+                        .select(plan::name)
+                        .first::<String>(conn)
+                        .await
+                        .optional()?
+                        .unwrap_or(current.plan_name.clone())
+                } else {
+                    current.plan_name.clone()
+                };
+
+                let updated = diesel::update(subscriptions::table.filter(subscriptions::id.eq(&current.id)))
+                    .set((
+                        subscriptions::status.eq(&change.status),
+                        subscriptions::plan_name.eq(&new_plan),
+                        subscriptions::current_period_end.eq(change.current_period_end.or(current.current_period_end)),
+                        subscriptions::last_event_at.eq(change.event_at),
+                    ))
+                    .returning(Subscription::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                set_users_plan(conn, &current.user_id, &new_plan).await?;
+
+                Change::new(SUBSCRIPTION_CHANGE, SUBSCRIPTION, &updated.id)
+                    .before(&current)
+                    .after(&updated)
+                    .record(conn, &subscriber(&current.user_id))
+                    .await?;
+
+                Ok(updated)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{models::audit::AuditFilter, test_db::test_store};
+
+    fn change(status: &str, event_at: NaiveDateTime) -> SubscriptionChange {
+        SubscriptionChange {
+            provider_subscription_id: "sub_1".to_string(),
+            status: status.to_string(),
+            price_id: None,
+            current_period_end: None,
+            event_at,
+        }
+    }
+
+    async fn users_plan(s: &Store, user_id: &str) -> String {
+        use crate::schema::users;
+
+        let mut conn = s.pool.get().await.unwrap();
+        users::table
+            .filter(users::id.eq(user_id))
+            .select(users::plan_name)
+            .first(&mut conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn late_checkouts_dont_revive_deleted_subscriptions() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let checkout_at = Utc::now().naive_utc() - Duration::minutes(5);
+
+        let start = |at| {
+            s.start_subscription(user_id.clone(), "cus_1".to_string(), "sub_1".to_string(), "Pro".to_string(), at)
+        };
+        start(checkout_at).await.unwrap();
+        s.apply_subscription_change(change("canceled", checkout_at + Duration::minutes(1))).await.unwrap();
+        assert_eq!(users_plan(&s, &user_id).await, DEFAULT_PLAN);
+
+        // The checkout's webhook delivered again after the deletion
+        let subscription = start(checkout_at).await.unwrap();
+        assert_eq!(subscription.status, "canceled");
+        assert_eq!(users_plan(&s, &user_id).await, DEFAULT_PLAN);
+
+        // A later checkout starts a new subscription
+        start(checkout_at + Duration::minutes(2)).await.unwrap();
+        assert_eq!(users_plan(&s, &user_id).await, "Pro");
+
+        // Applied events are logged, the ignored late one isn't
+        let filter = AuditFilter { actor_id: Some(user_id), ..Default::default() };
+        let actions = s
+            .get_audit_log(filter, 10, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.action)
+            .collect::<Vec<_>>();
+        assert_eq!(actions, vec![SUBSCRIPTION_START, SUBSCRIPTION_CHANGE, SUBSCRIPTION_START]);
+    }
+
+    #[tokio::test]
+    async fn changes_before_the_checkout_are_retried() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let checkout_at = Utc::now().naive_utc() - Duration::minutes(5);
+
+        let early = s.apply_subscription_change(change(PAST_DUE, checkout_at + Duration::minutes(1))).await;
+        assert!(matches!(early, Err(StoreError::Conflict(_))));
+
+        s.start_subscription(user_id.clone(), "cus_1".to_string(), "sub_1".to_string(), "Pro".to_string(), checkout_at)
+            .await
+            .unwrap();
+        let retried = s.apply_subscription_change(change(PAST_DUE, checkout_at + Duration::minutes(1))).await.unwrap();
+        assert_eq!(retried.status, PAST_DUE);
+        assert!(retried.is_current());
+        assert_eq!(users_plan(&s, &user_id).await, "Pro");
+    }
+}
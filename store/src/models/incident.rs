@@ -0,0 +1,662 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::audit::{Actor, Change};
+
+pub const INCIDENT_STATUSES: [&str; 4] = ["investigating", "identified", "monitoring", "resolved"];
+pub const RESOLVED: &str = "resolved";
+pub const MAX_INCIDENT_PAGE: i64 = 100;
+
+const INCIDENT: &str = "incident";
+const INCIDENT_CREATE: &str = "incident.create";
+const INCIDENT_RENAME: &str = "incident.rename";
+const INCIDENT_UPDATE: &str = "incident.update";
+const INCIDENT_DELETE: &str = "incident.delete";
+const INCIDENT_POSTMORTEM: &str = "incident.postmortem";
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::incidents)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Incident {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    pub title: String,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::incident_websites)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct IncidentWebsite {
+    pub incident_id: String,
+    pub website_url: String,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::incident_updates)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct IncidentUpdate {
+    pub id: String,
+    pub incident_id: String,
+    pub status: String,
+    pub message: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::postmortems)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Postmortem {
+    pub incident_id: String,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct IncidentDetails {
+    pub incident: Incident,
+    pub websites: Vec<String>,
+    pub updates: Vec<IncidentUpdate>,
+    pub postmortem: Option<Postmortem>,
+}
+
+/// The organization an incident's websites belong to, for the audit log.
+async fn incident_org<C>(conn: &mut C, input_incident_id: &str) -> Result<String, StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::{incident_websites, websites};
+
+    let res = incident_websites::table
+        .inner_join(websites::table.on(websites::url.eq(incident_websites::website_url)))
+        .filter(incident_websites::incident_id.eq(input_incident_id))
+        .select(websites::org_id)
+        .first::<String>(conn)
+        .await?;
+
+    Ok(res)
+}
+
+impl Store {
+    /// Opens an incident on one or more websites of the same organization
+    /// together with its first timeline update. Callers check the actor's
+    /// role on each website.
+    pub async fn create_incident(
+        &self,
+        actor: &Actor,
+        input_title: String,
+        input_websites: Vec<String>,
+        input_status: String,
+        input_message: String,
+    ) -> Result<Incident, StoreError> {
+        use crate::schema::{incident_updates, incident_websites, incidents, websites};
+
+        let mut conn = self.pool.get().await?;
+
+        if input_websites.is_empty() {
+            return Err(StoreError::Invalid("an incident needs at least one website"));
+        }
+
+        if !INCIDENT_STATUSES.contains(&input_status.as_str()) {
+            return Err(StoreError::Invalid("unknown incident status"));
+        }
+
+        let mut unique = input_websites.clone();
+        unique.sort();
+        unique.dedup();
+        if unique.len() != input_websites.len() {
+            return Err(StoreError::Invalid("a website is listed more than once"));
+        }
+
+        let found = websites::table
+            .filter(websites::url.eq_any(&input_websites))
+            .select(websites::org_id)
+            .load::<String>(&mut conn)
+            .await?;
+
+        if found.len() != input_websites.len() {
+            return Err(StoreError::NotFound);
+        }
+
+        let incident_org_id = found[0].clone();
+        if found.iter().any(|o| *o != incident_org_id) {
+            return Err(StoreError::Invalid("an incident's websites must belong to one organization"));
+        }
+
+        let now = Utc::now().naive_utc();
+        let new_incident = Incident {
+            id: Uuid::new_v4().to_string(),
+            user_id: actor.user_id.clone(),
+            title: input_title,
+            status: input_status.clone(),
+            created_at: now,
+            updated_at: now,
+            resolved_at: if input_status == RESOLVED { Some(now) } else { None },
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let incident = diesel::insert_into(incidents::table)
+                    .values(new_incident)
+                    .returning(Incident::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                let links: Vec<IncidentWebsite> = input_websites
+                    .into_iter()
+                    .map(|w| IncidentWebsite {
+                        incident_id: incident.id.clone(),
+                        website_url: w,
+                    })
+                    .collect();
+
+                diesel::insert_into(incident_websites::table)
+                    .values(links)
+                    .execute(conn)
+                    .await?;
+
+                diesel::insert_into(incident_updates::table)
+                    .values(IncidentUpdate {
+                        id: Uuid::new_v4().to_string(),
+                        incident_id: incident.id.clone(),
+                        status: input_status,
+                        message: input_message,
+                        created_at: now,
+                    })
+                    .execute(conn)
+                    .await?;
+
+                Change::new(INCIDENT_CREATE, INCIDENT, &incident.id)
+                    .org(incident_org_id)
+                    .after(&incident)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(incident)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Incidents on websites of all the user's organizations, or of `org_id`,
+    /// newest first. Organizations requiring 2FA are left out until it's on,
+    /// like their websites.
+    pub async fn get_member_incidents(
+        &self,
+        input_user_id: String,
+        input_org_id: Option<String>,
+        has_two_factor: bool,
+    ) -> Result<Vec<Incident>, StoreError> {
+        use crate::schema::{incident_websites, incidents, org_members, organizations, websites};
+
+        let mut conn = self.pool.get().await?;
+
+        let mut visible = incident_websites::table
+            .inner_join(websites::table.on(websites::url.eq(incident_websites::website_url)))
+            .inner_join(organizations::table.on(organizations::id.eq(websites::org_id)))
+            .inner_join(org_members::table.on(org_members::org_id.eq(websites::org_id)))
+            .filter(org_members::user_id.eq(input_user_id))
+            .select(incident_websites::incident_id)
+            .into_boxed();
+
+        if let Some(o) = input_org_id {
+            visible = visible.filter(websites::org_id.eq(o));
+        }
+
+        if !has_two_factor {
+            visible = visible.filter(organizations::require_two_factor.eq(false));
+        }
+
+        let res = incidents::table
+            .filter(incidents::id.eq_any(visible))
+            .order(incidents::created_at.desc())
+            .select(Incident::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// The websites an incident is on, for checking the caller's role on them.
+    pub async fn get_incident_websites(&self, input_incident_id: String) -> Result<Vec<String>, StoreError> {
+        use crate::schema::incident_websites::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = incident_websites
+            .filter(incident_id.eq(input_incident_id))
+            .select(website_url)
+            .load::<String>(&mut conn)
+            .await?;
+
+        if res.is_empty() {
+            return Err(StoreError::NotFound);
+        }
+
+        Ok(res)
+    }
+
+    pub async fn get_incident(&self, input_incident_id: String) -> Result<IncidentDetails, StoreError> {
+        use crate::schema::incidents::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let incident = incidents
+            .filter(id.eq(input_incident_id))
+            .select(Incident::as_select())
+            .first(&mut conn)
+            .await?;
+
+        drop(conn);
+        self.get_incident_details(incident).await
+    }
+
+    pub async fn update_incident_title(
+        &self,
+        actor: &Actor,
+        input_incident_id: String,
+        input_title: String,
+    ) -> Result<Incident, StoreError> {
+        use crate::schema::incidents::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let previous = incidents
+                    .filter(id.eq(&input_incident_id))
+                    .select(title)
+                    .first::<String>(conn)
+                    .await?;
+
+                let res = diesel::update(incidents.filter(id.eq(&input_incident_id)))
+                    .set((title.eq(input_title), updated_at.eq(Utc::now().naive_utc())))
+                    .returning(Incident::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                Change::new(INCIDENT_RENAME, INCIDENT, &res.id)
+                    .org(incident_org(conn, &res.id).await?)
+                    .before(&serde_json::json!({ "title": previous }))
+                    .after(&serde_json::json!({ "title": res.title }))
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(res)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Appends a timeline update and moves the incident to the update's status.
+    pub async fn add_incident_update(
+        &self,
+        actor: &Actor,
+        input_incident_id: String,
+        input_status: String,
+        input_message: String,
+    ) -> Result<IncidentUpdate, StoreError> {
+        use crate::schema::{incident_updates, incidents};
+
+        if !INCIDENT_STATUSES.contains(&input_status.as_str()) {
+            return Err(StoreError::Invalid("unknown incident status"));
+        }
+
+        let mut conn = self.pool.get().await?;
+
+        let now = Utc::now().naive_utc();
+        let resolved = if input_status == RESOLVED { Some(now) } else { None };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let incident = diesel::update(incidents::table.filter(incidents::id.eq(&input_incident_id)))
+                .set((
+                    incidents::status.eq(&input_status),
+                    incidents::updated_at.eq(now),
+                    incidents::resolved_at.eq(resolved),
+                ))
+                .returning(Incident::as_returning())
+                .get_result(conn)
+                .await?;
+
+                let update = diesel::insert_into(incident_updates::table)
+                    .values(IncidentUpdate {
+                        id: Uuid::new_v4().to_string(),
+                        incident_id: incident.id,
+                        status: input_status,
+                        message: input_message,
+                        created_at: now,
+                    })
+                    .returning(IncidentUpdate::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                Change::new(INCIDENT_UPDATE, INCIDENT, &update.incident_id)
+                    .org(incident_org(conn, &update.incident_id).await?)
+                    .after(&update)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(update)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn delete_incident(
+        &self,
+        actor: &Actor,
+        input_incident_id: String,
+    ) -> Result<usize, StoreError> {
+        use crate::schema::incidents::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                // Looked up first, the links go with the incident
+                let org = match incident_org(conn, &input_incident_id).await {
+                    Ok(org) => org,
+                    Err(StoreError::NotFound) => return Ok(0),
+                    Err(e) => return Err(e),
+                };
+
+                let deleted = diesel::delete(incidents.filter(id.eq(input_incident_id)))
+                    .returning(Incident::as_returning())
+                    .get_result(conn)
+                    .await
+                    .optional()?;
+
+                let Some(incident) = deleted else {
+                    return Ok(0);
+                };
+
+                Change::new(INCIDENT_DELETE, INCIDENT, &incident.id)
+                    .org(org)
+                    .before(&incident)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(1)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Creates or replaces the postmortem. Only resolved incidents can have one.
+    pub async fn set_postmortem(
+        &self,
+        actor: &Actor,
+        input_incident_id: String,
+        input_content: String,
+    ) -> Result<Postmortem, StoreError> {
+        use crate::schema::{incidents, postmortems};
+
+        let mut conn = self.pool.get().await?;
+
+        let status = incidents::table
+            .filter(incidents::id.eq(&input_incident_id))
+            .select(incidents::status)
+            .first::<String>(&mut conn)
+            .await?;
+
+        if status != RESOLVED {
+            return Err(StoreError::Conflict("only resolved incidents can have a postmortem"));
+        }
+
+        let now = Utc::now().naive_utc();
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let previous = postmortems::table
+                    .filter(postmortems::incident_id.eq(&input_incident_id))
+                    .select(Postmortem::as_select())
+                    .first(conn)
+                    .await
+                    .optional()?;
+
+                let res = diesel::insert_into(postmortems::table)
+                    .values(Postmortem {
+                        incident_id: input_incident_id,
+                        content: input_content.clone(),
+                        created_at: now,
+                        updated_at: now,
+                    })
+                    .on_conflict(postmortems::incident_id)
+                    .do_update()
+                    .set((
+                        postmortems::content.eq(input_content),
+                        postmortems::updated_at.eq(now),
+                    ))
+                    .returning(Postmortem::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                let mut change = Change::new(INCIDENT_POSTMORTEM, INCIDENT, &res.incident_id)
+                    .org(incident_org(conn, &res.incident_id).await?)
+                    .after(&res);
+                if let Some(previous) = &previous {
+                    change = change.before(previous);
+                }
+                change.record(conn, actor).await?;
+
+                Ok(res)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Public view of the incidents that affected a website, newest first,
+    /// `limit` at a time starting at `offset`. Other websites they were on
+    /// may not be shared, so only this one is listed.
+    pub async fn get_website_incidents(
+        &self,
+        input_website_url: String,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<IncidentDetails>, StoreError> {
+        use crate::schema::{incident_websites, incidents};
+
+        let mut conn = self.pool.get().await?;
+
+        let found = incidents::table
+            .inner_join(incident_websites::table)
+            .filter(incident_websites::website_url.eq(&input_website_url))
+            .order((incidents::created_at.desc(), incidents::id.desc()))
+            .limit(limit.clamp(1, MAX_INCIDENT_PAGE))
+            .offset(offset.max(0))
+            .select(Incident::as_select())
+            .load(&mut conn)
+            .await?;
+
+        drop(conn);
+        let mut res = self.get_incidents_details(found).await?;
+        for details in &mut res {
+            details.websites = vec![input_website_url.clone()];
+        }
+
+        Ok(res)
+    }
+
+    async fn get_incident_details(&self, incident: Incident) -> Result<IncidentDetails, StoreError> {
+        let mut res = self.get_incidents_details(vec![incident]).await?;
+        res.pop().ok_or(StoreError::NotFound)
+    }
+
+    /// Loads websites, updates and postmortems for all of `found` at once,
+    /// keeping its order.
+    async fn get_incidents_details(&self, found: Vec<Incident>) -> Result<Vec<IncidentDetails>, StoreError> {
+        use crate::schema::{incident_updates, incident_websites, postmortems};
+
+        if found.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<&str> = found.iter().map(|i| i.id.as_str()).collect();
+        let mut conn = self.pool.get().await?;
+
+        let mut websites: HashMap<String, Vec<String>> = HashMap::new();
+        for link in incident_websites::table
+            .filter(incident_websites::incident_id.eq_any(&ids))
+            .select(IncidentWebsite::as_select())
+            .load(&mut conn)
+            .await?
+        {
+            websites.entry(link.incident_id).or_default().push(link.website_url);
+        }
+
+        let mut updates: HashMap<String, Vec<IncidentUpdate>> = HashMap::new();
+        for update in incident_updates::table
+            .filter(incident_updates::incident_id.eq_any(&ids))
+            .order(incident_updates::created_at.desc())
+            .select(IncidentUpdate::as_select())
+            .load(&mut conn)
+            .await?
+        {
+            updates.entry(update.incident_id.clone()).or_default().push(update);
+        }
+
+        let mut postmortems: HashMap<String, Postmortem> = postmortems::table
+            .filter(postmortems::incident_id.eq_any(&ids))
+            .select(Postmortem::as_select())
+            .load(&mut conn)
+            .await?
+            .into_iter()
+            .map(|p| (p.incident_id.clone(), p))
+            .collect();
+
+        let res = found
+            .into_iter()
+            .map(|incident| IncidentDetails {
+                websites: websites.remove(&incident.id).unwrap_or_default(),
+                updates: updates.remove(&incident.id).unwrap_or_default(),
+                postmortem: postmortems.remove(&incident.id),
+                incident,
+            })
+            .collect();
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::org::{OrgMember, EDITOR},
+        test_db::test_store,
+    };
+
+    async fn user(s: &Store, email: &str) -> (Actor, String) {
+        let user_id = s.sign_up(email.to_string(), "hunter22".to_string(), email.to_string()).await.unwrap();
+        let org_id = s.get_personal_org_id(user_id.clone()).await.unwrap();
+        (Actor { user_id, ip: None }, org_id)
+    }
+
+    #[tokio::test]
+    async fn org_members_see_each_others_incidents() {
+        let Some(s) = test_store().await else { return };
+        let (owner, _) = user(&s, "owner@example.com").await;
+        let (editor, _) = user(&s, "editor@example.com").await;
+        let (outsider, _) = user(&s, "outsider@example.com").await;
+
+        let team = s.create_organization(&owner, "Team".to_string()).await.unwrap();
+        let mut conn = s.pool.get().await.unwrap();
+        diesel::insert_into(crate::schema::org_members::table)
+            .values(OrgMember {
+                org_id: team.id.clone(),
+                user_id: editor.user_id.clone(),
+                role: EDITOR.to_string(),
+                created_at: Utc::now().naive_utc(),
+            })
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        s.create_website(&owner, team.id.clone(), "https://team.example.com".to_string(), String::new())
+            .await
+            .unwrap();
+
+        let incident = s
+            .create_incident(
+                &editor,
+                "Down".to_string(),
+                vec!["https://team.example.com".to_string()],
+                "investigating".to_string(),
+                "Looking".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let seen = s.get_member_incidents(owner.user_id.clone(), None, false).await.unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].id, incident.id);
+        assert!(s.get_member_incidents(outsider.user_id, None, false).await.unwrap().is_empty());
+        assert!(s.get_member_incidents(owner.user_id, Some("other".to_string()), false).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn incidents_stay_within_one_organization() {
+        let Some(s) = test_store().await else { return };
+        let (owner, personal) = user(&s, "owner@example.com").await;
+        let team = s.create_organization(&owner, "Team".to_string()).await.unwrap();
+        s.create_website(&owner, personal, "https://a.example.com".to_string(), String::new()).await.unwrap();
+        s.create_website(&owner, team.id, "https://b.example.com".to_string(), String::new()).await.unwrap();
+
+        let res = s
+            .create_incident(
+                &owner,
+                "Down".to_string(),
+                vec!["https://a.example.com".to_string(), "https://b.example.com".to_string()],
+                "investigating".to_string(),
+                "Looking".to_string(),
+            )
+            .await;
+        assert!(matches!(res, Err(StoreError::Invalid(_))));
+    }
+
+    #[tokio::test]
+    async fn public_incidents_only_list_the_requested_website() {
+        let Some(s) = test_store().await else { return };
+        let (owner, personal) = user(&s, "owner@example.com").await;
+        for url in ["https://shared.example.com", "https://private.example.com"] {
+            s.create_website(&owner, personal.clone(), url.to_string(), String::new()).await.unwrap();
+        }
+
+        let incident = s
+            .create_incident(
+                &owner,
+                "Down".to_string(),
+                vec!["https://shared.example.com".to_string(), "https://private.example.com".to_string()],
+                "investigating".to_string(),
+                "Looking".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let public = s.get_website_incidents("https://shared.example.com".to_string(), 10, 0).await.unwrap();
+        assert_eq!(public.len(), 1);
+        assert_eq!(public[0].websites, vec!["https://shared.example.com".to_string()]);
+
+        let full = s.get_incident(incident.id).await.unwrap();
+        assert_eq!(full.websites.len(), 2);
+    }
+}
@@ -1,3 +1,15 @@
 pub mod user;
 pub mod website;
-pub mod app;
\ No newline at end of file
+pub mod app;
+pub mod subscriber;
+pub mod incident;
+pub mod slo;
+pub mod report;
+pub mod rollup;
+pub mod retention;
+pub mod plan;
+pub mod billing;
+pub mod usage;
+pub mod api_key;
+pub mod org;
+pub mod audit;
\ No newline at end of file
@@ -0,0 +1,739 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    audit::{Actor, Change},
+    plan::refresh_org_plan,
+    user::session::{hash_token, new_refresh_token},
+};
+
+pub const OWNER: &str = "owner";
+pub const ADMIN: &str = "admin";
+pub const EDITOR: &str = "editor";
+pub const VIEWER: &str = "viewer";
+
+pub const ROLES: [&str; 4] = [OWNER, ADMIN, EDITOR, VIEWER];
+
+const INVITATION_TTL: Duration = Duration::days(7);
+
+const ORG: &str = "org";
+const INVITATION: &str = "org_invitation";
+const MEMBER: &str = "org_member";
+const ORG_CREATE: &str = "org.create";
+const ORG_INVITE: &str = "org.invite";
+const ORG_INVITATION_REVOKE: &str = "org.invitation_revoke";
+const ORG_INVITATION_ACCEPT: &str = "org.invitation_accept";
+const ORG_MEMBER_ROLE: &str = "org.member_role";
+const ORG_MEMBER_REMOVE: &str = "org.member_remove";
+const ORG_REQUIRE_TWO_FACTOR: &str = "org.require_two_factor";
+
+/// Roles only ever grant what the roles below them do, plus more.
+pub fn role_rank(role: &str) -> u8 {
+    match role {
+        OWNER => 4,
+        ADMIN => 3,
+        EDITOR => 2,
+        VIEWER => 1,
+        _ => 0,
+    }
+}
+
+/// Whether `role` grants at least what `required` does.
+pub fn role_allows(role: &str, required: &str) -> bool {
+    role_rank(role) >= role_rank(required)
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::organizations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub personal: bool,
+    pub require_two_factor: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::org_members)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrgMember {
+    pub org_id: String,
+    pub user_id: String,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::org_invitations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrgInvitation {
+    pub id: String,
+    pub org_id: String,
+    pub email: String,
+    pub role: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub invited_by: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub accepted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MemberDetails {
+    pub user_id: String,
+    pub email: String,
+    pub name: String,
+    pub role: String,
+    pub joined_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Membership {
+    pub organization: Organization,
+    pub role: String,
+}
+
+/// What a member may do with a website, from the organization owning it.
+pub struct WebsiteAccess {
+    pub org_id: String,
+    pub role: String,
+    /// Set when the organization requires 2FA and the member hasn't turned it on
+    pub missing_two_factor: bool,
+}
+
+/// Creates `input_user_id`'s personal organization inside the caller's
+/// transaction, so no user is ever left without one.
+pub(crate) async fn create_personal_org<C>(conn: &mut C, input_user_id: &str, input_name: &str) -> Result<String, StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::{org_members, organizations};
+
+    let now = Utc::now().naive_utc();
+    let new_org_id = Uuid::new_v4().to_string();
+
+    diesel::insert_into(organizations::table)
+        .values(Organization {
+            id: new_org_id.clone(),
+            name: input_name.to_string(),
+            personal: true,
+            require_two_factor: false,
+            created_at: now,
+        })
+        .execute(conn)
+        .await?;
+
+    diesel::insert_into(org_members::table)
+        .values(OrgMember {
+            org_id: new_org_id.clone(),
+            user_id: input_user_id.to_string(),
+            role: OWNER.to_string(),
+            created_at: now,
+        })
+        .execute(conn)
+        .await?;
+
+    Ok(new_org_id)
+}
+
+impl Store {
+    /// Creates a team organization with the user as its owner.
+    pub async fn create_organization(&self, actor: &Actor, input_name: String) -> Result<Organization, StoreError> {
+        use crate::schema::{org_members, organizations};
+
+        let mut conn = self.pool.get().await?;
+
+        let now = Utc::now().naive_utc();
+        let org = Organization {
+            id: Uuid::new_v4().to_string(),
+            name: input_name,
+            personal: false,
+            require_two_factor: false,
+            created_at: now,
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let org = diesel::insert_into(organizations::table)
+                    .values(org)
+                    .returning(Organization::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                diesel::insert_into(org_members::table)
+                    .values(OrgMember {
+                        org_id: org.id.clone(),
+                        user_id: actor.user_id.clone(),
+                        role: OWNER.to_string(),
+                        created_at: now,
+                    })
+                    .execute(conn)
+                    .await?;
+
+                Change::new(ORG_CREATE, ORG, &org.id)
+                    .org(&org.id)
+                    .after(&org)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(org)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// The organizations the user belongs to and their role in each,
+    /// personal one first.
+    pub async fn get_users_organizations(&self, input_user_id: String) -> Result<Vec<Membership>, StoreError> {
+        use crate::schema::{org_members, organizations};
+
+        let mut conn = self.pool.get().await?;
+
+        let res = organizations::table
+            .inner_join(org_members::table)
+            .filter(org_members::user_id.eq(input_user_id))
+            .order((organizations::personal.desc(), organizations::created_at.asc()))
+            .select((Organization::as_select(), org_members::role))
+            .load::<(Organization, String)>(&mut conn)
+            .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|(organization, role)| Membership { organization, role })
+            .collect())
+    }
+
+    pub async fn get_personal_org_id(&self, input_user_id: String) -> Result<String, StoreError> {
+        use crate::schema::{org_members, organizations};
+
+        let mut conn = self.pool.get().await?;
+
+        let res = organizations::table
+            .inner_join(org_members::table)
+            .filter(org_members::user_id.eq(input_user_id))
+            .filter(org_members::role.eq(OWNER))
+            .filter(organizations::personal.eq(true))
+            .select(organizations::id)
+            .first::<String>(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// The user's role in the organization. Fails with `NotFound` for
+    /// non-members.
+    pub async fn get_member_role(&self, input_org_id: String, input_user_id: String) -> Result<String, StoreError> {
+        use crate::schema::org_members::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = org_members
+            .filter(org_id.eq(input_org_id))
+            .filter(user_id.eq(input_user_id))
+            .select(role)
+            .first::<String>(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// The user's access to `input_url` through the organization owning it.
+    /// Fails with `NotFound` for unknown websites and non-members alike.
+    pub async fn get_website_access(&self, input_url: String, input_user_id: String) -> Result<WebsiteAccess, StoreError> {
+        use crate::schema::{org_members, organizations, users, websites};
+
+        let mut conn = self.pool.get().await?;
+
+        let (website_org_id, role, require_two_factor) = websites::table
+            .inner_join(organizations::table)
+            .inner_join(org_members::table.on(org_members::org_id.eq(websites::org_id)))
+            .filter(websites::url.eq(input_url))
+            .filter(org_members::user_id.eq(&input_user_id))
+            .select((websites::org_id, org_members::role, organizations::require_two_factor))
+            .first::<(String, String, bool)>(&mut conn)
+            .await?;
+
+        let missing_two_factor = require_two_factor
+            && users::table
+                .filter(users::id.eq(&input_user_id))
+                .select(users::totp_enabled_at)
+                .first::<Option<NaiveDateTime>>(&mut conn)
+                .await?
+                .is_none();
+
+        Ok(WebsiteAccess { org_id: website_org_id, role, missing_two_factor })
+    }
+
+    /// Whether the organization requires 2FA and the user hasn't turned it on.
+    pub async fn is_missing_org_two_factor(&self, input_org_id: String, input_user_id: String) -> Result<bool, StoreError> {
+        use crate::schema::{organizations, users};
+
+        let mut conn = self.pool.get().await?;
+
+        let required = organizations::table
+            .filter(organizations::id.eq(input_org_id))
+            .select(organizations::require_two_factor)
+            .first::<bool>(&mut conn)
+            .await?;
+
+        if !required {
+            return Ok(false);
+        }
+
+        let enabled_at = users::table
+            .filter(users::id.eq(input_user_id))
+            .select(users::totp_enabled_at)
+            .first::<Option<NaiveDateTime>>(&mut conn)
+            .await?;
+
+        Ok(enabled_at.is_none())
+    }
+
+    pub async fn get_org_members(&self, input_org_id: String) -> Result<Vec<MemberDetails>, StoreError> {
+        use crate::schema::{org_members, users};
+
+        let mut conn = self.pool.get().await?;
+
+        let res = org_members::table
+            .inner_join(users::table)
+            .filter(org_members::org_id.eq(input_org_id))
+            .order(org_members::created_at.asc())
+            .select((users::id, users::email, users::name, org_members::role, org_members::created_at))
+            .load::<MemberDetails>(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// Invites `input_email` to the organization, replacing any pending
+    /// invitation for it. Returns the invitation and its token, which only
+    /// goes into the emailed link. Personal organizations can't be shared.
+    pub async fn create_org_invitation(
+        &self,
+        actor: &Actor,
+        input_org_id: String,
+        input_email: String,
+        input_role: String,
+    ) -> Result<(OrgInvitation, String), StoreError> {
+        use crate::schema::{org_invitations, organizations};
+
+        if !ROLES.contains(&input_role.as_str()) {
+            return Err(StoreError::Invalid("unknown role"));
+        }
+
+        let mut conn = self.pool.get().await?;
+
+        let token = new_refresh_token();
+        let now = Utc::now().naive_utc();
+        let invitation = OrgInvitation {
+            id: Uuid::new_v4().to_string(),
+            org_id: input_org_id.clone(),
+            email: input_email.trim().to_lowercase(),
+            role: input_role,
+            token_hash: hash_token(&token),
+            invited_by: actor.user_id.clone(),
+            created_at: now,
+            expires_at: now + INVITATION_TTL,
+            accepted_at: None,
+        };
+
+        let invitation = conn
+            .transaction::<_, StoreError, _>(|conn| {
+                async move {
+                    let personal = organizations::table
+                        .filter(organizations::id.eq(&input_org_id))
+                        .select(organizations::personal)
+                        .first::<bool>(conn)
+                        .await?;
+
+                    if personal {
+                        return Err(StoreError::Invalid("personal organizations can't have other members"));
+                    }
+
+                    diesel::delete(
+                        org_invitations::table
+                            .filter(org_invitations::org_id.eq(&input_org_id))
+                            .filter(org_invitations::email.eq(&invitation.email))
+                            .filter(org_invitations::accepted_at.is_null()),
+                    )
+                    .execute(conn)
+                    .await?;
+
+                    let invitation = diesel::insert_into(org_invitations::table)
+                        .values(invitation)
+                        .returning(OrgInvitation::as_returning())
+                        .get_result(conn)
+                        .await?;
+
+                    Change::new(ORG_INVITE, INVITATION, &invitation.id)
+                        .org(&input_org_id)
+                        .after(&invitation)
+                        .record(conn, actor)
+                        .await?;
+
+                    Ok(invitation)
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok((invitation, token))
+    }
+
+    pub async fn get_org_invitations(&self, input_org_id: String) -> Result<Vec<OrgInvitation>, StoreError> {
+        use crate::schema::org_invitations::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = org_invitations
+            .filter(org_id.eq(input_org_id))
+            .filter(accepted_at.is_null())
+            .filter(expires_at.gt(Utc::now().naive_utc()))
+            .order(created_at.desc())
+            .select(OrgInvitation::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn revoke_org_invitation(
+        &self,
+        actor: &Actor,
+        input_org_id: String,
+        input_invitation_id: String,
+    ) -> Result<(), StoreError> {
+        use crate::schema::org_invitations::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let revoked = diesel::delete(
+                    org_invitations
+                        .filter(id.eq(input_invitation_id))
+                        .filter(org_id.eq(&input_org_id))
+                        .filter(accepted_at.is_null()),
+                )
+                .returning(OrgInvitation::as_returning())
+                .get_result(conn)
+                .await?;
+
+                Change::new(ORG_INVITATION_REVOKE, INVITATION, &revoked.id)
+                    .org(&input_org_id)
+                    .before(&revoked)
+                    .record(conn, actor)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Adds the user to the organization they were invited to. The invitation
+    /// must be unused, unexpired and addressed to the user's verified email.
+    /// Returns the organization's id.
+    pub async fn accept_org_invitation(&self, actor: &Actor, input_token: String) -> Result<String, StoreError> {
+        use crate::schema::{org_invitations, org_members, users};
+
+        let mut conn = self.pool.get().await?;
+
+        let now = Utc::now().naive_utc();
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let (user_email, verified) = users::table
+                    .filter(users::id.eq(&actor.user_id))
+                    .select((users::email, users::email_verified))
+                    .first::<(String, bool)>(conn)
+                    .await?;
+
+                if !verified {
+                    return Err(StoreError::Unauthorized);
+                }
+
+                let invitation = diesel::update(
+                    org_invitations::table
+                        .filter(org_invitations::token_hash.eq(hash_token(&input_token)))
+                        .filter(org_invitations::email.eq(user_email.to_lowercase()))
+                        .filter(org_invitations::accepted_at.is_null())
+                        .filter(org_invitations::expires_at.gt(now)),
+                )
+                .set(org_invitations::accepted_at.eq(Some(now)))
+                .returning(OrgInvitation::as_returning())
+                .get_result(conn)
+                .await?;
+
+                // Existing members keep their role rather than being changed by a link
+                diesel::insert_into(org_members::table)
+                    .values(OrgMember {
+                        org_id: invitation.org_id.clone(),
+                        user_id: actor.user_id.clone(),
+                        role: invitation.role.clone(),
+                        created_at: now,
+                    })
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+                    .await?;
+                refresh_org_plan(conn, &invitation.org_id).await?;
+
+                Change::new(ORG_INVITATION_ACCEPT, INVITATION, &invitation.id)
+                    .org(&invitation.org_id)
+                    .after(&invitation)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(invitation.org_id)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Changes a member's role. Fails with `Conflict` if it would leave the
+    /// organization without an owner.
+    pub async fn set_member_role(
+        &self,
+        actor: &Actor,
+        input_org_id: String,
+        input_user_id: String,
+        input_role: String,
+    ) -> Result<(), StoreError> {
+        use crate::schema::org_members::dsl::*;
+
+        if !ROLES.contains(&input_role.as_str()) {
+            return Err(StoreError::Invalid("unknown role"));
+        }
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let before = org_members
+                    .filter(org_id.eq(&input_org_id))
+                    .filter(user_id.eq(&input_user_id))
+                    .select(OrgMember::as_select())
+                    .first(conn)
+                    .await?;
+
+                let after = diesel::update(
+                    org_members
+                        .filter(org_id.eq(&input_org_id))
+                        .filter(user_id.eq(&input_user_id)),
+                )
+                .set(role.eq(&input_role))
+                .returning(OrgMember::as_returning())
+                .get_result(conn)
+                .await?;
+
+                if Self::count_owners(conn, &input_org_id).await? == 0 {
+                    return Err(StoreError::Conflict("an organization needs an owner"));
+                }
+                refresh_org_plan(conn, &input_org_id).await?;
+
+                Change::new(ORG_MEMBER_ROLE, MEMBER, &input_user_id)
+                    .org(&input_org_id)
+                    .before(&before)
+                    .after(&after)
+                    .record(conn, actor)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Removes a member. Fails with `Conflict` if it would leave the
+    /// organization without an owner.
+    pub async fn remove_member(&self, actor: &Actor, input_org_id: String, input_user_id: String) -> Result<(), StoreError> {
+        use crate::schema::org_members::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let removed = diesel::delete(
+                    org_members
+                        .filter(org_id.eq(&input_org_id))
+                        .filter(user_id.eq(&input_user_id)),
+                )
+                .returning(OrgMember::as_returning())
+                .get_result(conn)
+                .await?;
+
+                if Self::count_owners(conn, &input_org_id).await? == 0 {
+                    return Err(StoreError::Conflict("an organization needs an owner"));
+                }
+                refresh_org_plan(conn, &input_org_id).await?;
+
+                Change::new(ORG_MEMBER_REMOVE, MEMBER, &input_user_id)
+                    .org(&input_org_id)
+                    .before(&removed)
+                    .record(conn, actor)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn count_owners<C>(conn: &mut C, input_org_id: &str) -> Result<i64, StoreError>
+    where
+        C: AsyncConnection<Backend = diesel::pg::Pg>,
+    {
+        use crate::schema::org_members::dsl::*;
+
+        org_members
+            .filter(org_id.eq(input_org_id))
+            .filter(role.eq(OWNER))
+            .count()
+            .get_result::<i64>(conn)
+            .await
+            .map_err(StoreError::from)
+    }
+
+    pub async fn set_org_require_two_factor(&self, actor: &Actor, input_org_id: String, required: bool) -> Result<(), StoreError> {
+        use crate::schema::organizations::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let before = organizations
+                    .filter(id.eq(&input_org_id))
+                    .select(require_two_factor)
+                    .first::<bool>(conn)
+                    .await?;
+
+                diesel::update(organizations.filter(id.eq(&input_org_id)))
+                    .set(require_two_factor.eq(required))
+                    .execute(conn)
+                    .await?;
+
+                Change::new(ORG_REQUIRE_TWO_FACTOR, ORG, &input_org_id)
+                    .org(&input_org_id)
+                    .before(&serde_json::json!({ "require_two_factor": before }))
+                    .after(&serde_json::json!({ "require_two_factor": required }))
+                    .record(conn, actor)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::test_store;
+
+    #[test]
+    fn roles_grant_everything_below_them() {
+        let expected = [
+            // role, then whether it allows owner, admin, editor, viewer
+            (OWNER, [true, true, true, true]),
+            (ADMIN, [false, true, true, true]),
+            (EDITOR, [false, false, true, true]),
+            (VIEWER, [false, false, false, true]),
+            ("stranger", [false, false, false, false]),
+        ];
+
+        for (role, allows) in expected {
+            for (required, allowed) in ROLES.iter().zip(allows) {
+                assert_eq!(role_allows(role, required), allowed, "{} for {}", role, required);
+            }
+        }
+    }
+
+    async fn user(s: &Store, email: &str) -> Actor {
+        let user_id = s.sign_up(email.to_string(), "hunter22".to_string(), email.to_string()).await.unwrap();
+        Actor { user_id, ip: None }
+    }
+
+    async fn invite(s: &Store, owner: &Actor, org_id: &str, member: &Actor, email: &str, role: &str) {
+        let (_, token) = s
+            .create_org_invitation(owner, org_id.to_string(), email.to_string(), role.to_string())
+            .await
+            .unwrap();
+        s.accept_org_invitation(member, token).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn members_reach_websites_with_their_role() {
+        let Some(s) = test_store().await else { return };
+        let owner = user(&s, "owner@example.com").await;
+        let team = s.create_organization(&owner, "Team".to_string()).await.unwrap();
+        s.create_website(&owner, team.id.clone(), "https://example.com".to_string(), String::new())
+            .await
+            .unwrap();
+
+        let mut conn = s.pool.get().await.unwrap();
+        diesel::update(crate::schema::users::table)
+            .set(crate::schema::users::email_verified.eq(true))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let unverified = user(&s, "unverified@example.com").await;
+        let (_, token) = s
+            .create_org_invitation(&owner, team.id.clone(), "unverified@example.com".to_string(), VIEWER.to_string())
+            .await
+            .unwrap();
+        assert!(matches!(s.accept_org_invitation(&unverified, token).await, Err(StoreError::Unauthorized)));
+
+        for role in [ADMIN, EDITOR, VIEWER] {
+            let email = format!("{}@example.com", role);
+            let member = user(&s, &email).await;
+            diesel::update(crate::schema::users::table.filter(crate::schema::users::id.eq(&member.user_id)))
+                .set(crate::schema::users::email_verified.eq(true))
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            invite(&s, &owner, &team.id, &member, &email, role).await;
+
+            let access = s.get_website_access("https://example.com".to_string(), member.user_id).await.unwrap();
+            assert_eq!((access.org_id.as_str(), access.role.as_str()), (team.id.as_str(), role));
+        }
+
+        let outsider = user(&s, "outsider@example.com").await;
+        let res = s.get_website_access("https://example.com".to_string(), outsider.user_id).await;
+        assert!(matches!(res, Err(StoreError::NotFound)));
+        let res = s.get_website_access("https://unknown.example.com".to_string(), owner.user_id.clone()).await;
+        assert!(matches!(res, Err(StoreError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn organizations_keep_an_owner() {
+        let Some(s) = test_store().await else { return };
+        let owner = user(&s, "owner@example.com").await;
+        let team = s.create_organization(&owner, "Team".to_string()).await.unwrap();
+
+        let demoted = s.set_member_role(&owner, team.id.clone(), owner.user_id.clone(), ADMIN.to_string()).await;
+        assert!(matches!(demoted, Err(StoreError::Conflict(_))));
+        let removed = s.remove_member(&owner, team.id.clone(), owner.user_id.clone()).await;
+        assert!(matches!(removed, Err(StoreError::Conflict(_))));
+        let unknown = s.set_member_role(&owner, team.id.clone(), owner.user_id.clone(), "root".to_string()).await;
+        assert!(matches!(unknown, Err(StoreError::Invalid(_))));
+
+        assert_eq!(s.get_member_role(team.id.clone(), owner.user_id.clone()).await.unwrap(), OWNER);
+
+        // Personal organizations stay personal
+        let personal = s.get_personal_org_id(owner.user_id.clone()).await.unwrap();
+        let res = s
+            .create_org_invitation(&owner, personal, "b@example.com".to_string(), VIEWER.to_string())
+            .await;
+        assert!(matches!(res, Err(StoreError::Invalid(_))));
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use crate::{error::StoreError, models::org::OWNER, store::Store};
+use diesel::prelude::*;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = crate::schema::plan)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Plan {
+    pub id: String,
+    pub name: String,
+    pub price: String,
+    pub tick_retention_days: i32,
+    pub visit_retention_days: i32,
+    pub rollup_retention_days: i32,
+    pub max_monitors: i32,
+    pub min_check_interval_secs: i32,
+    pub allowed_regions: Option<Vec<String>>,
+    pub monthly_event_quota: i64,
+    pub provider_price_id: Option<String>,
+}
+
+impl Plan {
+    pub fn allows_region(&self, region: &str) -> bool {
+        self.allowed_regions
+            .as_ref()
+            .is_none_or(|regions| regions.iter().any(|r| r == region))
+    }
+}
+
+/// A plan limit that an action would exceed.
+#[derive(Debug)]
+pub enum QuotaError {
+    Monitors { plan: String, limit: i32 },
+    Events { plan: String, limit: i64 },
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaError::Monitors { plan, limit } => write!(
+                f,
+                "Quota exceeded: the {} plan allows {} monitors",
+                plan, limit
+            ),
+            QuotaError::Events { plan, limit } => write!(
+                f,
+                "Quota exceeded: the {} plan allows {} analytics events per month",
+                plan, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// The owner an organization's plan comes from, and that plan's name: the
+/// owner on the biggest plan, whose subscription pays for it. Earlier owners
+/// win ties.
+pub(crate) async fn org_billing_owner<C>(conn: &mut C, input_org_id: &str) -> Result<(String, String), StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::{org_members, plan, users};
+
+    org_members::table
+        .inner_join(users::table)
+        .inner_join(plan::table.on(plan::name.eq(users::plan_name)))
+        .filter(org_members::org_id.eq(input_org_id))
+        .filter(org_members::role.eq(OWNER))
+        .order((plan::max_monitors.desc(), org_members::created_at.asc()))
+        .select((users::id, users::plan_name))
+        .first(conn)
+        .await
+        .map_err(StoreError::from)
+}
+
+async fn org_plan<C>(conn: &mut C, input_org_id: &str) -> Result<Plan, StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::plan::dsl::*;
+
+    let (_, plan_name) = org_billing_owner(conn, input_org_id).await?;
+
+    plan.filter(name.eq(plan_name))
+        .select(Plan::as_select())
+        .first(conn)
+        .await
+        .map_err(StoreError::from)
+}
+
+/// Fails with `QuotaError::Monitors` once the organization has as many
+/// websites as its plan allows, and returns the plan otherwise. Locks the
+/// organization until the caller's transaction ends, so concurrent inserts
+/// can't both pass the check.
+pub(crate) async fn check_monitor_quota<C>(conn: &mut C, input_org_id: &str) -> Result<Plan, StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::{organizations, websites};
+
+    organizations::table
+        .filter(organizations::id.eq(input_org_id))
+        .select(organizations::id)
+        .for_update()
+        .first::<String>(conn)
+        .await?;
+
+    let org_plan = org_plan(conn, input_org_id).await?;
+
+    let count = websites::table
+        .filter(websites::org_id.eq(input_org_id))
+        .count()
+        .get_result::<i64>(conn)
+        .await?;
+
+    if count >= org_plan.max_monitors as i64 {
+        return Err(QuotaError::Monitors {
+            plan: org_plan.name,
+            limit: org_plan.max_monitors,
+        }
+        .into());
+    }
+
+    Ok(org_plan)
+}
+
+/// Moves the organization's websites to its billing owner's plan. Called
+/// whenever an owner's plan or the set of owners changes.
+pub(crate) async fn refresh_org_plan<C>(conn: &mut C, input_org_id: &str) -> Result<(), StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::websites;
+
+    let (_, org_plan) = org_billing_owner(conn, input_org_id).await?;
+
+    diesel::update(websites::table.filter(websites::org_id.eq(input_org_id)))
+        .set(websites::plan_name.eq(org_plan))
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+impl Store {
+    pub async fn get_plan(&self, input_name: String) -> Result<Plan, StoreError> {
+        use crate::schema::plan::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = plan
+            .filter(name.eq(input_name))
+            .select(Plan::as_select())
+            .first(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_users_plan(&self, input_user_id: String) -> Result<Plan, StoreError> {
+        use crate::schema::{plan, users};
+
+        let mut conn = self.pool.get().await?;
+
+        let res = plan::table
+            .inner_join(users::table.on(users::plan_name.eq(plan::name)))
+            .filter(users::id.eq(input_user_id))
+            .select(Plan::as_select())
+            .first(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// The plan of the organization's billing owner, which its websites run on.
+    pub async fn get_org_plan(&self, input_org_id: String) -> Result<Plan, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        org_plan(&mut conn, &input_org_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        models::{
+            audit::Actor,
+            org::{OrgMember, EDITOR},
+        },
+        test_db::test_store,
+    };
+
+    async fn user(s: &Store, email: &str) -> Actor {
+        let user_id = s.sign_up(email.to_string(), "hunter22".to_string(), email.to_string()).await.unwrap();
+        Actor { user_id, ip: None }
+    }
+
+    #[tokio::test]
+    async fn organizations_run_on_their_owners_plan() {
+        let Some(s) = test_store().await else { return };
+        let owner = user(&s, "owner@example.com").await;
+        let editor = user(&s, "editor@example.com").await;
+
+        let team = s.create_organization(&owner, "Team".to_string()).await.unwrap();
+        let mut conn = s.pool.get().await.unwrap();
+        diesel::insert_into(crate::schema::org_members::table)
+            .values(OrgMember {
+                org_id: team.id.clone(),
+                user_id: editor.user_id.clone(),
+                role: EDITOR.to_string(),
+                created_at: Utc::now().naive_utc(),
+            })
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        // The editor's websites count against the team, not against them
+        for i in 0..5 {
+            s.create_website(&editor, team.id.clone(), format!("https://{}.example.com", i), String::new())
+                .await
+                .unwrap();
+        }
+        let over = s
+            .create_website(&editor, team.id.clone(), "https://5.example.com".to_string(), String::new())
+            .await;
+        assert!(matches!(over, Err(StoreError::Quota(QuotaError::Monitors { limit: 5, .. }))));
+
+        let personal = s.get_personal_org_id(editor.user_id.clone()).await.unwrap();
+        s.create_website(&editor, personal, "https://mine.example.com".to_string(), String::new())
+            .await
+            .unwrap();
+
+        // The owner's subscription moves the team and its websites to Pro
+        s.start_subscription(
+            owner.user_id.clone(),
+            "cus_1".to_string(),
+            "sub_1".to_string(),
+            "Pro".to_string(),
+            Utc::now().naive_utc(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(s.get_org_plan(team.id.clone()).await.unwrap().name, "Pro");
+        let website = s
+            .create_website(&editor, team.id.clone(), "https://5.example.com".to_string(), String::new())
+            .await
+            .unwrap();
+        assert_eq!(website.plan_name, "Pro");
+        assert_eq!(s.search_website("https://0.example.com").await.unwrap().plan_name, "Pro");
+        assert_eq!(s.search_website("https://mine.example.com").await.unwrap().plan_name, "Basic");
+    }
+
+    #[tokio::test]
+    async fn concurrent_creates_stay_within_the_quota() {
+        let Some(s) = test_store().await else { return };
+        let owner = user(&s, "owner@example.com").await;
+        let org_id = s.get_personal_org_id(owner.user_id.clone()).await.unwrap();
+
+        let creates = (0..10).map(|i| {
+            s.create_website(&owner, org_id.clone(), format!("https://{}.example.com", i), String::new())
+        });
+        let results = futures_util::future::join_all(creates).await;
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 5);
+        assert!(results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|e| matches!(e, StoreError::Quota(QuotaError::Monitors { .. }))));
+    }
+}
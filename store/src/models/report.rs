@@ -0,0 +1,339 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::audit::{Actor, Change};
+
+const REPORT_SCHEDULE: &str = "report_schedule";
+const REPORT_SCHEDULE_CREATE: &str = "report_schedule.create";
+const REPORT_SCHEDULE_DELETE: &str = "report_schedule.delete";
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::report_schedules)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ReportSchedule {
+    pub id: String,
+    pub user_id: String,
+    pub website_url: Option<String>,
+    pub email: String,
+    pub last_sent_month: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(QueryableByName, Debug, Serialize, Deserialize)]
+pub struct OutageSummary {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub outage_count: i64,
+
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub downtime_minutes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MonthlyReport {
+    pub website_url: String,
+    pub month: String,
+    pub total_checks: i64,
+    pub uptime_percent: Option<f64>,
+    pub outage_count: i64,
+    pub downtime_minutes: i64,
+    pub mttr_minutes: Option<f64>,
+    pub avg_response_ms: Option<f64>,
+    pub p50_response_ms: Option<f64>,
+    pub p95_response_ms: Option<f64>,
+    pub p99_response_ms: Option<f64>,
+}
+
+/// Parses a `YYYY-MM` month into its first instant and the first instant of the
+/// following month.
+pub fn month_bounds(month: &str) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let start = NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").ok()?;
+    let end = start.checked_add_months(Months::new(1))?;
+    Some((start.and_hms_opt(0, 0, 0)?, end.and_hms_opt(0, 0, 0)?))
+}
+
+/// The last fully elapsed month as `YYYY-MM`.
+pub fn previous_month() -> String {
+    let today = Utc::now().date_naive();
+    let first = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today);
+    first
+        .checked_sub_months(Months::new(1))
+        .unwrap_or(first)
+        .format("%Y-%m")
+        .to_string()
+}
+
+impl Store {
+    /// Minutes where most ticks were not `Up` count as downtime; each run of
+    /// consecutive down minutes is one outage. Minutes already rolled up are
+    /// read from the minutely rollup.
+    pub async fn get_outage_summary(
+        &self,
+        input_website: String,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<OutageSummary, StoreError> {
+        let rolled_up_to = self.get_minutely_watermark().await?;
+
+        let mut conn = self.pool.get().await?;
+
+        let query = r#"
+            WITH counts AS (
+                SELECT bucket AS minute, up_count AS up, up_count + down_count + unknown_count AS total
+                FROM website_tick_minutely
+                WHERE website_url = $1 AND bucket >= $2 AND bucket < LEAST($3, $4)
+                UNION ALL
+                SELECT DATE_TRUNC('minute', "createdAt"), (status = 'Up')::INT, 1
+                FROM website_tick
+                WHERE website_url = $1 AND "createdAt" >= GREATEST($2, $4) AND "createdAt" < $3
+            ), minutes AS (
+                SELECT minute, (SUM(total) - SUM(up)) * 2 > SUM(total) AS is_down
+                FROM counts
+                GROUP BY minute
+            ), down AS (
+                SELECT minute - (ROW_NUMBER() OVER (ORDER BY minute)) * INTERVAL '1 minute' AS grp
+                FROM minutes
+                WHERE is_down
+            )
+            SELECT COUNT(DISTINCT grp) AS outage_count, COUNT(*) AS downtime_minutes
+            FROM down;
+        "#;
+
+        let result = diesel::sql_query(query)
+            .bind::<diesel::sql_types::Text, _>(input_website)
+            .bind::<diesel::sql_types::Timestamp, _>(from)
+            .bind::<diesel::sql_types::Timestamp, _>(to)
+            .bind::<diesel::sql_types::Timestamp, _>(rolled_up_to)
+            .get_result::<OutageSummary>(&mut conn)
+            .await?;
+
+        Ok(result)
+    }
+
+    pub async fn get_monthly_report(
+        &self,
+        input_website: String,
+        month: String,
+    ) -> Result<MonthlyReport, StoreError> {
+        let (from, to) = month_bounds(&month).ok_or(StoreError::Invalid("month must look like 2025-01"))?;
+
+        let counts = self.get_tick_counts_between(input_website.clone(), from, to).await?;
+        let outages = self.get_outage_summary(input_website.clone(), from, to).await?;
+        let response = self
+            .get_response_time_stats_between(input_website.clone(), from, to)
+            .await?;
+
+        let uptime_percent = if counts.total > 0 {
+            Some(counts.up as f64 * 100.0 / counts.total as f64)
+        } else {
+            None
+        };
+        let mttr_minutes = if outages.outage_count > 0 {
+            Some(outages.downtime_minutes as f64 / outages.outage_count as f64)
+        } else {
+            None
+        };
+
+        Ok(MonthlyReport {
+            website_url: input_website,
+            month,
+            total_checks: counts.total,
+            uptime_percent,
+            outage_count: outages.outage_count,
+            downtime_minutes: outages.downtime_minutes,
+            mttr_minutes,
+            avg_response_ms: response.avg,
+            p50_response_ms: response.p50,
+            p95_response_ms: response.p95,
+            p99_response_ms: response.p99,
+        })
+    }
+
+    /// Reports for one of the websites the user can see, or all of them when
+    /// `input_website` is `None`. `input_org_id` narrows them to one
+    /// organization's.
+    pub async fn get_users_monthly_reports(
+        &self,
+        input_user_id: String,
+        input_org_id: Option<String>,
+        input_website: Option<String>,
+        month: String,
+    ) -> Result<Vec<MonthlyReport>, StoreError> {
+        let two_factor = self.is_totp_enabled(input_user_id.clone()).await?;
+        let websites = self.get_member_websites(input_user_id, input_org_id, two_factor).await?;
+
+        let mut reports = Vec::new();
+        for w in websites {
+            if input_website.as_ref().is_some_and(|i| *i != w.url) {
+                continue;
+            }
+            reports.push(self.get_monthly_report(w.url, month.clone()).await?);
+        }
+
+        if input_website.is_some() && reports.is_empty() {
+            return Err(StoreError::NotFound);
+        }
+
+        Ok(reports)
+    }
+
+    pub async fn create_report_schedule(
+        &self,
+        actor: &Actor,
+        input_website_url: Option<String>,
+        input_email: String,
+    ) -> Result<ReportSchedule, StoreError> {
+        use crate::schema::{report_schedules, websites};
+
+        let mut conn = self.pool.get().await?;
+
+        // Callers check the user's access to the website
+        if let Some(w) = &input_website_url {
+            let _website = websites::table
+                .filter(websites::url.eq(w))
+                .select(websites::id)
+                .first::<String>(&mut conn)
+                .await?;
+        }
+
+        let new_schedule = ReportSchedule {
+            id: Uuid::new_v4().to_string(),
+            user_id: actor.user_id.clone(),
+            website_url: input_website_url,
+            email: input_email,
+            // Start with the coming month rather than mailing last month right away
+            last_sent_month: Some(previous_month()),
+            created_at: Utc::now().naive_utc(),
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let res = diesel::insert_into(report_schedules::table)
+                    .values(new_schedule)
+                    .returning(ReportSchedule::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                Change::new(REPORT_SCHEDULE_CREATE, REPORT_SCHEDULE, &res.id)
+                    .after(&res)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(res)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_users_report_schedules(
+        &self,
+        input_user_id: String,
+    ) -> Result<Vec<ReportSchedule>, StoreError> {
+        use crate::schema::report_schedules::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = report_schedules
+            .filter(user_id.eq(input_user_id))
+            .select(ReportSchedule::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn delete_report_schedule(
+        &self,
+        actor: &Actor,
+        input_schedule_id: String,
+    ) -> Result<usize, StoreError> {
+        use crate::schema::report_schedules::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let deleted = diesel::delete(
+                    report_schedules
+                        .filter(id.eq(input_schedule_id))
+                        .filter(user_id.eq(&actor.user_id)),
+                )
+                .returning(ReportSchedule::as_returning())
+                .get_result(conn)
+                .await
+                .optional()?;
+
+                let Some(schedule) = deleted else {
+                    return Ok(0);
+                };
+
+                Change::new(REPORT_SCHEDULE_DELETE, REPORT_SCHEDULE, &schedule.id)
+                    .before(&schedule)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(1)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_due_report_schedules(&self, month: String) -> Result<Vec<ReportSchedule>, StoreError> {
+        use crate::schema::report_schedules::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = report_schedules
+            .filter(last_sent_month.is_null().or(last_sent_month.ne(month)))
+            .select(ReportSchedule::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn mark_report_schedule_sent(
+        &self,
+        input_schedule_id: String,
+        month: String,
+    ) -> Result<usize, StoreError> {
+        use crate::schema::report_schedules::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = diesel::update(report_schedules.filter(id.eq(input_schedule_id)))
+            .set(last_sent_month.eq(Some(month)))
+            .execute(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn midnight(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn month_bounds_span_the_whole_month() {
+        assert_eq!(month_bounds("2025-02"), Some((midnight(2025, 2, 1), midnight(2025, 3, 1))));
+        assert_eq!(month_bounds("2024-12"), Some((midnight(2024, 12, 1), midnight(2025, 1, 1))));
+    }
+
+    #[test]
+    fn month_bounds_reject_anything_else() {
+        for month in ["2025-13", "2025-00", "2025", "2025-02-01", "february", ""] {
+            assert_eq!(month_bounds(month), None, "{} was accepted", month);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{error::StoreError, store::Store};
+use diesel_async::RunQueryDsl;
+
+/// Data that expires according to the retention days of the website's plan.
+#[derive(Debug, Clone, Copy)]
+pub enum PurgeTarget {
+    Ticks,
+    PageVisits,
+    MinutelyRollups,
+    HourlyRollups,
+    DailyRollups,
+}
+
+impl PurgeTarget {
+    pub const ALL: [PurgeTarget; 5] = [
+        PurgeTarget::Ticks,
+        PurgeTarget::PageVisits,
+        PurgeTarget::MinutelyRollups,
+        PurgeTarget::HourlyRollups,
+        PurgeTarget::DailyRollups,
+    ];
+
+    // Each query deletes at most $1 rows and skips rows other transactions hold,
+    // so a purge never waits on (or blocks) the workers writing new data.
+    fn query(&self) -> &'static str {
+        match self {
+            // Ticks are kept until both raw rollups have read them
+            PurgeTarget::Ticks => r#"
+                DELETE FROM website_tick WHERE id IN (
+                    SELECT t.id
+                    FROM website_tick t
+                    JOIN websites w ON w.url = t.website_url
+                    JOIN plan p ON p.name = w.plan_name
+                    WHERE t."createdAt" < (NOW() AT TIME ZONE 'UTC') - p.tick_retention_days * INTERVAL '1 day'
+                    AND t."createdAt" < (
+                        SELECT CASE WHEN COUNT(*) = 2 THEN MIN(rolled_up_to) ELSE '-infinity' END
+                        FROM rollup_watermark
+                        WHERE granularity IN ('minutely', 'hourly')
+                    )
+                    LIMIT $1
+                    FOR UPDATE OF t SKIP LOCKED
+                );
+            "#,
+            PurgeTarget::PageVisits => r#"
+                DELETE FROM page_visits WHERE id IN (
+                    SELECT v.id
+                    FROM page_visits v
+                    JOIN websites w ON w.url = v.website
+                    JOIN plan p ON p.name = w.plan_name
+                    WHERE v.visited_at < (NOW() AT TIME ZONE 'UTC') - p.visit_retention_days * INTERVAL '1 day'
+                    LIMIT $1
+                    FOR UPDATE OF v SKIP LOCKED
+                );
+            "#,
+            // Minutely buckets are as fine grained as raw ticks and expire with them
+            PurgeTarget::MinutelyRollups => r#"
+                DELETE FROM website_tick_minutely WHERE (website_url, region, bucket) IN (
+                    SELECT r.website_url, r.region, r.bucket
+                    FROM website_tick_minutely r
+                    JOIN websites w ON w.url = r.website_url
+                    JOIN plan p ON p.name = w.plan_name
+                    WHERE r.bucket < (NOW() AT TIME ZONE 'UTC') - p.tick_retention_days * INTERVAL '1 day'
+                    LIMIT $1
+                    FOR UPDATE OF r SKIP LOCKED
+                );
+            "#,
+            // Hourly buckets are kept until the daily rollup has read them
+            PurgeTarget::HourlyRollups => r#"
+                DELETE FROM website_tick_hourly WHERE (website_url, region, bucket) IN (
+                    SELECT r.website_url, r.region, r.bucket
+                    FROM website_tick_hourly r
+                    JOIN websites w ON w.url = r.website_url
+                    JOIN plan p ON p.name = w.plan_name
+                    WHERE r.bucket < (NOW() AT TIME ZONE 'UTC') - p.rollup_retention_days * INTERVAL '1 day'
+                    AND r.bucket < (
+                        SELECT COALESCE(MAX(rolled_up_to), '-infinity')
+                        FROM rollup_watermark
+                        WHERE granularity = 'daily'
+                    )
+                    LIMIT $1
+                    FOR UPDATE OF r SKIP LOCKED
+                );
+            "#,
+            PurgeTarget::DailyRollups => r#"
+                DELETE FROM website_tick_daily WHERE (website_url, region, bucket) IN (
+                    SELECT r.website_url, r.region, r.bucket
+                    FROM website_tick_daily r
+                    JOIN websites w ON w.url = r.website_url
+                    JOIN plan p ON p.name = w.plan_name
+                    WHERE r.bucket < (NOW() AT TIME ZONE 'UTC') - p.rollup_retention_days * INTERVAL '1 day'
+                    LIMIT $1
+                    FOR UPDATE OF r SKIP LOCKED
+                );
+            "#,
+        }
+    }
+}
+
+impl Store {
+    /// Deletes one batch of expired rows and returns how many were removed.
+    /// Callers repeat until fewer than `batch_size` rows come back.
+    pub async fn purge_expired(&self, target: PurgeTarget, batch_size: i64) -> Result<usize, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let deleted = diesel::sql_query(target.query())
+            .bind::<diesel::sql_types::BigInt, _>(batch_size)
+            .execute(&mut conn)
+            .await?;
+
+        Ok(deleted)
+    }
+}
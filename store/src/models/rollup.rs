@@ -0,0 +1,493 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{DateTime, Duration, DurationRound, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+pub const MINUTELY: &str = "minutely";
+pub const HOURLY: &str = "hourly";
+pub const DAILY: &str = "daily";
+
+/// Ticks are only rolled up once their bucket closed this long ago, so a slow
+/// worker can still land its tick in the right bucket.
+const GRACE: Duration = Duration::minutes(2);
+
+/// Windows up to this length are always aggregated from raw ticks.
+const RAW_WINDOW: Duration = Duration::days(2);
+
+#[derive(QueryableByName, Debug, Serialize, Deserialize)]
+pub struct TickAggregate {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub total: i64,
+
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub up: i64,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+    pub first_at: Option<NaiveDateTime>,
+
+    /// Average over every tick, whatever its status.
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    pub avg_all: Option<f64>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    pub avg: Option<f64>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Integer>)]
+    pub min: Option<i32>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Integer>)]
+    pub max: Option<i32>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    pub p50: Option<f64>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    pub p95: Option<f64>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    pub p99: Option<f64>,
+}
+
+impl TickAggregate {
+    pub fn uptime_percent(&self) -> Option<f64> {
+        if self.total > 0 {
+            Some(self.up as f64 * 100.0 / self.total as f64)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+struct Watermarks {
+    minutely: Option<NaiveDateTime>,
+    hourly: Option<NaiveDateTime>,
+    daily: Option<NaiveDateTime>,
+}
+
+type Range = (NaiveDateTime, NaiveDateTime);
+
+/// Which time ranges of a window are read from which table. Ranges are
+/// half-open and an empty range has `start >= end`.
+struct TierRanges {
+    daily: Range,
+    hourly: Range,
+    raw: [Range; 2],
+}
+
+fn epoch() -> NaiveDateTime {
+    DateTime::UNIX_EPOCH.naive_utc()
+}
+
+fn floor(at: NaiveDateTime, unit: Duration) -> NaiveDateTime {
+    at.duration_trunc(unit).unwrap_or(at)
+}
+
+fn ceil(at: NaiveDateTime, unit: Duration) -> NaiveDateTime {
+    let floored = floor(at, unit);
+    if floored == at {
+        at
+    } else {
+        floored + unit
+    }
+}
+
+/// Splits `[from, to)` so fully rolled up hours (and days, for all-time
+/// windows) come from the rollup tables and only the edges are read raw.
+fn tier_ranges(from: Option<NaiveDateTime>, to: NaiveDateTime, wm: &Watermarks) -> TierRanges {
+    let empty = (epoch(), epoch());
+    let hour = Duration::hours(1);
+
+    match from {
+        Some(from) if to - from <= RAW_WINDOW => TierRanges {
+            daily: empty,
+            hourly: empty,
+            raw: [(from, to), empty],
+        },
+        Some(from) => {
+            let start = ceil(from, hour);
+            let end = wm.hourly.unwrap_or(start).min(floor(to, hour)).max(start);
+            TierRanges {
+                daily: empty,
+                hourly: (start, end),
+                raw: [(from, start), (end, to)],
+            }
+        }
+        None => {
+            let daily_end = wm.daily.unwrap_or(epoch()).min(floor(to, hour));
+            let hourly_end = wm
+                .hourly
+                .unwrap_or(daily_end)
+                .min(floor(to, hour))
+                .max(daily_end);
+            TierRanges {
+                daily: (epoch(), daily_end),
+                hourly: (daily_end, hourly_end),
+                raw: [(hourly_end, to), empty],
+            }
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct FirstTick {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+    first_at: Option<NaiveDateTime>,
+}
+
+impl Store {
+    async fn get_rollup_watermarks(&self, conn: &mut AsyncPgConnection) -> Result<Watermarks, StoreError> {
+        use crate::schema::rollup_watermark::dsl::*;
+
+        let rows = rollup_watermark
+            .select((granularity, rolled_up_to))
+            .load::<(String, NaiveDateTime)>(conn)
+            .await?;
+
+        let mut wm = Watermarks::default();
+        for (g, at) in rows {
+            match g.as_str() {
+                MINUTELY => wm.minutely = Some(at),
+                HOURLY => wm.hourly = Some(at),
+                DAILY => wm.daily = Some(at),
+                _ => {}
+            }
+        }
+
+        Ok(wm)
+    }
+
+    async fn set_rollup_watermark(
+        &self,
+        conn: &mut AsyncPgConnection,
+        input_granularity: &str,
+        at: NaiveDateTime,
+    ) -> Result<(), StoreError> {
+        use crate::schema::rollup_watermark::dsl::*;
+
+        diesel::insert_into(rollup_watermark)
+            .values((granularity.eq(input_granularity), rolled_up_to.eq(at)))
+            .on_conflict(granularity)
+            .do_update()
+            .set(rolled_up_to.eq(at))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Rolls up every closed bucket since the last run. Each granularity
+    /// advances by at most one chunk per call so a backfill of old ticks is
+    /// spread over several runs. Re-running a bucket overwrites it.
+    pub async fn refresh_rollups(&self) -> Result<(), StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let wm = self.get_rollup_watermarks(&mut conn).await?;
+        let closed = Utc::now().naive_utc() - GRACE;
+
+        // The first run starts at the day of the oldest tick
+        let first = diesel::sql_query(r#"SELECT MIN("createdAt") AS first_at FROM website_tick;"#)
+            .get_result::<FirstTick>(&mut conn)
+            .await?;
+        let Some(start) = first.first_at.map(|at| floor(at, Duration::days(1))) else {
+            return Ok(());
+        };
+
+        // Minutely and hourly buckets come straight from raw ticks so their
+        // percentiles are exact.
+        for (name, unit, chunk, from) in [
+            (MINUTELY, Duration::minutes(1), Duration::hours(6), wm.minutely.unwrap_or(start)),
+            (HOURLY, Duration::hours(1), Duration::days(7), wm.hourly.unwrap_or(start)),
+        ] {
+            let to = (from + chunk).min(floor(closed, unit));
+            if to <= from {
+                continue;
+            }
+
+            let query = format!(
+                r#"
+                INSERT INTO website_tick_{name} (
+                    website_url, region, bucket, up_count, down_count, unknown_count,
+                    sum_response_ms, up_sum_response_ms, min_response_ms, max_response_ms,
+                    p50_response_ms, p95_response_ms, p99_response_ms
+                )
+                SELECT
+                    website_url,
+                    region,
+                    DATE_TRUNC('{trunc}', "createdAt"),
+                    COUNT(*) FILTER (WHERE status = 'Up'),
+                    COUNT(*) FILTER (WHERE status = 'Down'),
+                    COUNT(*) FILTER (WHERE status NOT IN ('Up', 'Down')),
+                    SUM(response_time_ms),
+                    COALESCE(SUM(response_time_ms) FILTER (WHERE status = 'Up'), 0),
+                    MIN(response_time_ms) FILTER (WHERE status = 'Up'),
+                    MAX(response_time_ms) FILTER (WHERE status = 'Up'),
+                    PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY response_time_ms) FILTER (WHERE status = 'Up'),
+                    PERCENTILE_CONT(0.95) WITHIN GROUP (ORDER BY response_time_ms) FILTER (WHERE status = 'Up'),
+                    PERCENTILE_CONT(0.99) WITHIN GROUP (ORDER BY response_time_ms) FILTER (WHERE status = 'Up')
+                FROM website_tick
+                WHERE "createdAt" >= $1 AND "createdAt" < $2
+                GROUP BY 1, 2, 3
+                ON CONFLICT (website_url, region, bucket) DO UPDATE SET
+                    up_count = EXCLUDED.up_count,
+                    down_count = EXCLUDED.down_count,
+                    unknown_count = EXCLUDED.unknown_count,
+                    sum_response_ms = EXCLUDED.sum_response_ms,
+                    up_sum_response_ms = EXCLUDED.up_sum_response_ms,
+                    min_response_ms = EXCLUDED.min_response_ms,
+                    max_response_ms = EXCLUDED.max_response_ms,
+                    p50_response_ms = EXCLUDED.p50_response_ms,
+                    p95_response_ms = EXCLUDED.p95_response_ms,
+                    p99_response_ms = EXCLUDED.p99_response_ms;
+                "#,
+                name = name,
+                trunc = if name == MINUTELY { "minute" } else { "hour" },
+            );
+
+            diesel::sql_query(query)
+                .bind::<diesel::sql_types::Timestamp, _>(from)
+                .bind::<diesel::sql_types::Timestamp, _>(to)
+                .execute(&mut conn)
+                .await?;
+
+            self.set_rollup_watermark(&mut conn, name, to).await?;
+        }
+
+        // Daily buckets are built from complete hourly ones. Their percentiles
+        // are the tick-weighted mean of the hourly percentiles, an approximation.
+        let wm = self.get_rollup_watermarks(&mut conn).await?;
+        let from = wm.daily.unwrap_or(start);
+        let to = (from + Duration::days(90))
+            .min(floor(wm.hourly.unwrap_or(from), Duration::days(1)));
+        if to > from {
+            let query = r#"
+                INSERT INTO website_tick_daily (
+                    website_url, region, bucket, up_count, down_count, unknown_count,
+                    sum_response_ms, up_sum_response_ms, min_response_ms, max_response_ms,
+                    p50_response_ms, p95_response_ms, p99_response_ms
+                )
+                SELECT
+                    website_url,
+                    region,
+                    DATE_TRUNC('day', bucket),
+                    SUM(up_count),
+                    SUM(down_count),
+                    SUM(unknown_count),
+                    SUM(sum_response_ms),
+                    SUM(up_sum_response_ms),
+                    MIN(min_response_ms),
+                    MAX(max_response_ms),
+                    SUM(p50_response_ms * up_count) / NULLIF(SUM(up_count) FILTER (WHERE p50_response_ms IS NOT NULL), 0),
+                    SUM(p95_response_ms * up_count) / NULLIF(SUM(up_count) FILTER (WHERE p95_response_ms IS NOT NULL), 0),
+                    SUM(p99_response_ms * up_count) / NULLIF(SUM(up_count) FILTER (WHERE p99_response_ms IS NOT NULL), 0)
+                FROM website_tick_hourly
+                WHERE bucket >= $1 AND bucket < $2
+                GROUP BY 1, 2, 3
+                ON CONFLICT (website_url, region, bucket) DO UPDATE SET
+                    up_count = EXCLUDED.up_count,
+                    down_count = EXCLUDED.down_count,
+                    unknown_count = EXCLUDED.unknown_count,
+                    sum_response_ms = EXCLUDED.sum_response_ms,
+                    up_sum_response_ms = EXCLUDED.up_sum_response_ms,
+                    min_response_ms = EXCLUDED.min_response_ms,
+                    max_response_ms = EXCLUDED.max_response_ms,
+                    p50_response_ms = EXCLUDED.p50_response_ms,
+                    p95_response_ms = EXCLUDED.p95_response_ms,
+                    p99_response_ms = EXCLUDED.p99_response_ms;
+            "#;
+
+            diesel::sql_query(query)
+                .bind::<diesel::sql_types::Timestamp, _>(from)
+                .bind::<diesel::sql_types::Timestamp, _>(to)
+                .execute(&mut conn)
+                .await?;
+
+            self.set_rollup_watermark(&mut conn, DAILY, to).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Tick counts and response times for a website in `[from, to)`, or over all
+    /// time when `from` is `None`, optionally limited to one region. Windows
+    /// longer than two days read the rolled up buckets and only scan raw ticks
+    /// at the edges; their percentiles are then tick-weighted approximations.
+    pub async fn get_tick_aggregate(
+        &self,
+        input_website: String,
+        input_region: Option<String>,
+        from: Option<NaiveDateTime>,
+        to: NaiveDateTime,
+    ) -> Result<TickAggregate, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let wm = self.get_rollup_watermarks(&mut conn).await?;
+        let tiers = tier_ranges(from, to, &wm);
+
+        let query = r#"
+            WITH parts AS (
+                SELECT
+                    up_count AS up,
+                    up_count + down_count + unknown_count AS total,
+                    bucket AS first_at,
+                    sum_response_ms AS sum_ms,
+                    up_sum_response_ms AS up_sum_ms,
+                    min_response_ms AS min_ms,
+                    max_response_ms AS max_ms,
+                    p50_response_ms AS p50,
+                    p95_response_ms AS p95,
+                    p99_response_ms AS p99
+                FROM website_tick_daily
+                WHERE website_url = $1 AND ($2::text IS NULL OR region = $2)
+                AND bucket >= $3 AND bucket < $4
+                UNION ALL
+                SELECT
+                    up_count,
+                    up_count + down_count + unknown_count,
+                    bucket,
+                    sum_response_ms,
+                    up_sum_response_ms,
+                    min_response_ms,
+                    max_response_ms,
+                    p50_response_ms,
+                    p95_response_ms,
+                    p99_response_ms
+                FROM website_tick_hourly
+                WHERE website_url = $1 AND ($2::text IS NULL OR region = $2)
+                AND bucket >= $5 AND bucket < $6
+                UNION ALL
+                SELECT
+                    COUNT(*) FILTER (WHERE status = 'Up'),
+                    COUNT(*),
+                    MIN("createdAt"),
+                    COALESCE(SUM(response_time_ms), 0),
+                    COALESCE(SUM(response_time_ms) FILTER (WHERE status = 'Up'), 0),
+                    MIN(response_time_ms) FILTER (WHERE status = 'Up'),
+                    MAX(response_time_ms) FILTER (WHERE status = 'Up'),
+                    PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY response_time_ms) FILTER (WHERE status = 'Up'),
+                    PERCENTILE_CONT(0.95) WITHIN GROUP (ORDER BY response_time_ms) FILTER (WHERE status = 'Up'),
+                    PERCENTILE_CONT(0.99) WITHIN GROUP (ORDER BY response_time_ms) FILTER (WHERE status = 'Up')
+                FROM website_tick
+                WHERE website_url = $1 AND ($2::text IS NULL OR region = $2)
+                AND (("createdAt" >= $7 AND "createdAt" < $8) OR ("createdAt" >= $9 AND "createdAt" < $10))
+            )
+            SELECT
+                COALESCE(SUM(total), 0)::BIGINT AS total,
+                COALESCE(SUM(up), 0)::BIGINT AS up,
+                MIN(first_at) AS first_at,
+                (SUM(sum_ms) / NULLIF(SUM(total), 0))::DOUBLE PRECISION AS avg_all,
+                (SUM(up_sum_ms) / NULLIF(SUM(up), 0))::DOUBLE PRECISION AS avg,
+                MIN(min_ms) AS min,
+                MAX(max_ms) AS max,
+                (SUM(p50 * up) / NULLIF(SUM(up) FILTER (WHERE p50 IS NOT NULL), 0))::DOUBLE PRECISION AS p50,
+                (SUM(p95 * up) / NULLIF(SUM(up) FILTER (WHERE p95 IS NOT NULL), 0))::DOUBLE PRECISION AS p95,
+                (SUM(p99 * up) / NULLIF(SUM(up) FILTER (WHERE p99 IS NOT NULL), 0))::DOUBLE PRECISION AS p99
+            FROM parts;
+        "#;
+
+        let result = diesel::sql_query(query)
+            .bind::<diesel::sql_types::Text, _>(input_website)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Text>, _>(input_region)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.daily.0)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.daily.1)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.hourly.0)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.hourly.1)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.raw[0].0)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.raw[0].1)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.raw[1].0)
+            .bind::<diesel::sql_types::Timestamp, _>(tiers.raw[1].1)
+            .get_result::<TickAggregate>(&mut conn)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// End of the last minute covered by the minutely rollup.
+    pub(crate) async fn get_minutely_watermark(&self) -> Result<NaiveDateTime, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let wm = self.get_rollup_watermarks(&mut conn).await?;
+        Ok(wm.minutely.unwrap_or(epoch()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2025, 3, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn is_empty((start, end): Range) -> bool {
+        start >= end
+    }
+
+    /// The non-empty ranges in order, which must cover the window without gaps
+    fn covered(ranges: &TierRanges) -> Vec<Range> {
+        let mut parts: Vec<Range> = [ranges.daily, ranges.hourly, ranges.raw[0], ranges.raw[1]]
+            .into_iter()
+            .filter(|r| !is_empty(*r))
+            .collect();
+        parts.sort();
+        for pair in parts.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0, "gap or overlap in {:?}", parts);
+        }
+        parts
+    }
+
+    #[test]
+    fn short_windows_read_raw_ticks() {
+        let wm = Watermarks { hourly: Some(at(10, 0, 0)), ..Default::default() };
+        let ranges = tier_ranges(Some(at(8, 12, 30)), at(10, 12, 30), &wm);
+
+        assert!(is_empty(ranges.daily) && is_empty(ranges.hourly));
+        assert_eq!(covered(&ranges), vec![(at(8, 12, 30), at(10, 12, 30))]);
+    }
+
+    #[test]
+    fn long_windows_read_whole_rolled_up_hours() {
+        let wm = Watermarks { hourly: Some(at(9, 6, 0)), ..Default::default() };
+        let ranges = tier_ranges(Some(at(1, 10, 15)), at(9, 8, 45), &wm);
+
+        assert!(is_empty(ranges.daily));
+        assert_eq!(ranges.hourly, (at(1, 11, 0), at(9, 6, 0)));
+        assert_eq!(ranges.raw, [(at(1, 10, 15), at(1, 11, 0)), (at(9, 6, 0), at(9, 8, 45))]);
+        let parts = covered(&ranges);
+        assert_eq!((parts[0].0, parts[parts.len() - 1].1), (at(1, 10, 15), at(9, 8, 45)));
+    }
+
+    #[test]
+    fn lagging_rollups_fall_back_to_raw_ticks() {
+        // Nothing rolled up inside the window yet
+        let wm = Watermarks { hourly: Some(at(1, 0, 0)), ..Default::default() };
+        let ranges = tier_ranges(Some(at(3, 10, 15)), at(9, 8, 45), &wm);
+        assert!(is_empty(ranges.hourly));
+        assert_eq!(covered(&ranges), vec![(at(3, 10, 15), at(3, 11, 0)), (at(3, 11, 0), at(9, 8, 45))]);
+
+        let ranges = tier_ranges(Some(at(3, 10, 15)), at(9, 8, 45), &Watermarks::default());
+        assert!(is_empty(ranges.hourly));
+        assert_eq!(covered(&ranges).len(), 2);
+    }
+
+    #[test]
+    fn all_time_windows_use_every_tier() {
+        let wm = Watermarks { daily: Some(at(8, 0, 0)), hourly: Some(at(9, 6, 0)), ..Default::default() };
+        let ranges = tier_ranges(None, at(9, 8, 45), &wm);
+
+        assert_eq!(ranges.daily, (epoch(), at(8, 0, 0)));
+        assert_eq!(ranges.hourly, (at(8, 0, 0), at(9, 6, 0)));
+        assert_eq!(ranges.raw[0], (at(9, 6, 0), at(9, 8, 45)));
+        covered(&ranges);
+
+        // Watermarks past the end of the window are cut back to it
+        let ranges = tier_ranges(None, at(5, 8, 45), &wm);
+        assert_eq!(ranges.daily, (epoch(), at(5, 8, 0)));
+        assert!(is_empty(ranges.hourly));
+        assert_eq!(ranges.raw[0], (at(5, 8, 0), at(5, 8, 45)));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::audit::{Actor, Change};
+
+pub const ROLLING: &str = "rolling";
+pub const CALENDAR_MONTH: &str = "calendar_month";
+
+const SLO: &str = "slo";
+const SLO_CREATE: &str = "slo.create";
+const SLO_DELETE: &str = "slo.delete";
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::slos)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Slo {
+    pub id: String,
+    pub user_id: String,
+    pub website_url: String,
+    pub name: String,
+    pub target_percent: f64,
+    pub window_kind: String,
+    pub window_days: i32,
+    pub burn_rate_threshold: f64,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::slo_alerts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SloAlert {
+    pub id: String,
+    pub slo_id: String,
+    pub burn_rate: f64,
+    pub triggered_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+pub struct NewSlo {
+    pub name: String,
+    pub target_percent: f64,
+    pub window_kind: String,
+    pub window_days: i32,
+    pub burn_rate_threshold: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SloStatus {
+    pub slo: Slo,
+    pub window_start: NaiveDateTime,
+    pub window_end: NaiveDateTime,
+    pub availability: Option<f64>,
+    pub error_budget_minutes: f64,
+    pub consumed_minutes: f64,
+    pub remaining_minutes: f64,
+    pub burn_rate_1h: Option<f64>,
+    pub burn_rate_6h: Option<f64>,
+    pub is_alerting: bool,
+}
+
+impl Slo {
+    /// Start and end of the compliance window that contains `now`.
+    pub fn window(&self, now: NaiveDateTime) -> (NaiveDateTime, NaiveDateTime) {
+        if self.window_kind == CALENDAR_MONTH {
+            let start = NaiveDate::from_ymd_opt(now.year(), now.month(), 1)
+                .unwrap_or_default()
+                .and_hms_opt(0, 0, 0)
+                .unwrap_or_default();
+            let end = start
+                .checked_add_months(chrono::Months::new(1))
+                .unwrap_or(start);
+            (start, end)
+        } else {
+            (now - Duration::days(self.window_days as i64), now)
+        }
+    }
+
+    fn allowed_error_rate(&self) -> f64 {
+        1.0 - self.target_percent / 100.0
+    }
+}
+
+impl Store {
+    pub async fn create_slo(
+        &self,
+        actor: &Actor,
+        input_website_url: String,
+        input: NewSlo,
+    ) -> Result<Slo, StoreError> {
+        use crate::schema::{slos, websites};
+
+        let mut conn = self.pool.get().await?;
+
+        // Callers check the user's access to the website
+        let _website = websites::table
+            .filter(websites::url.eq(&input_website_url))
+            .select(websites::id)
+            .first::<String>(&mut conn)
+            .await?;
+
+        let new_slo = Slo {
+            id: Uuid::new_v4().to_string(),
+            user_id: actor.user_id.clone(),
+            website_url: input_website_url,
+            name: input.name,
+            target_percent: input.target_percent,
+            window_kind: input.window_kind,
+            window_days: input.window_days,
+            burn_rate_threshold: input.burn_rate_threshold,
+            created_at: Utc::now().naive_utc(),
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let created_slo = diesel::insert_into(slos::table)
+                    .values(new_slo)
+                    .returning(Slo::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                Change::new(SLO_CREATE, SLO, &created_slo.id)
+                    .after(&created_slo)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(created_slo)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_users_slos(&self, input_user_id: String) -> Result<Vec<Slo>, StoreError> {
+        use crate::schema::slos::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = slos
+            .filter(user_id.eq(input_user_id))
+            .order(created_at.desc())
+            .select(Slo::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_all_slos(&self) -> Result<Vec<Slo>, StoreError> {
+        use crate::schema::slos::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = slos.select(Slo::as_select()).load(&mut conn).await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_slo(&self, input_user_id: String, input_slo_id: String) -> Result<Slo, StoreError> {
+        use crate::schema::slos::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = slos
+            .filter(id.eq(input_slo_id))
+            .filter(user_id.eq(input_user_id))
+            .select(Slo::as_select())
+            .first(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn delete_slo(&self, actor: &Actor, input_slo_id: String) -> Result<usize, StoreError> {
+        use crate::schema::slos::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let deleted = diesel::delete(slos.filter(id.eq(input_slo_id)).filter(user_id.eq(&actor.user_id)))
+                    .returning(Slo::as_returning())
+                    .get_result(conn)
+                    .await
+                    .optional()?;
+
+                let Some(slo) = deleted else {
+                    return Ok(0);
+                };
+
+                Change::new(SLO_DELETE, SLO, &slo.id)
+                    .before(&slo)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(1)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Burn rate over the last `lookback`: how many times faster than allowed the
+    /// error budget is being spent. `None` when there were no ticks.
+    pub async fn get_slo_burn_rate(&self, slo: &Slo, lookback: Duration) -> Result<Option<f64>, StoreError> {
+        let now = Utc::now().naive_utc();
+        let counts = self
+            .get_tick_counts_between(slo.website_url.clone(), now - lookback, now)
+            .await?;
+
+        if counts.total == 0 {
+            return Ok(None);
+        }
+
+        let error_rate = (counts.total - counts.up) as f64 / counts.total as f64;
+        Ok(Some(error_rate / slo.allowed_error_rate()))
+    }
+
+    /// Availability and error budget of an SLO for its current window. Consumed
+    /// budget only counts the part of the window we have ticks for.
+    pub async fn get_slo_status(&self, slo: Slo) -> Result<SloStatus, StoreError> {
+        let now = Utc::now().naive_utc();
+        let (window_start, window_end) = slo.window(now);
+
+        let counts = self
+            .get_tick_counts_between(slo.website_url.clone(), window_start, now)
+            .await?;
+
+        let window_minutes = (window_end - window_start).num_seconds() as f64 / 60.0;
+        let error_budget_minutes = window_minutes * slo.allowed_error_rate();
+
+        let availability = if counts.total > 0 {
+            Some(counts.up as f64 * 100.0 / counts.total as f64)
+        } else {
+            None
+        };
+
+        let covered_minutes = counts
+            .first_at
+            .map(|first| (now - first.max(window_start)).num_seconds() as f64 / 60.0)
+            .unwrap_or(0.0);
+        let consumed_minutes = availability
+            .map(|a| (1.0 - a / 100.0) * covered_minutes)
+            .unwrap_or(0.0);
+
+        let burn_rate_1h = self.get_slo_burn_rate(&slo, Duration::hours(1)).await?;
+        let burn_rate_6h = self.get_slo_burn_rate(&slo, Duration::hours(6)).await?;
+        let is_alerting = self.get_open_slo_alert(slo.id.clone()).await?.is_some();
+
+        Ok(SloStatus {
+            slo,
+            window_start,
+            window_end,
+            availability,
+            error_budget_minutes,
+            consumed_minutes,
+            remaining_minutes: error_budget_minutes - consumed_minutes,
+            burn_rate_1h,
+            burn_rate_6h,
+            is_alerting,
+        })
+    }
+
+    pub async fn get_open_slo_alert(&self, input_slo_id: String) -> Result<Option<SloAlert>, StoreError> {
+        use crate::schema::slo_alerts::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = slo_alerts
+            .filter(slo_id.eq(input_slo_id))
+            .filter(resolved_at.is_null())
+            .select(SloAlert::as_select())
+            .first(&mut conn)
+            .await
+            .optional()?;
+
+        Ok(res)
+    }
+
+    pub async fn get_slo_alerts(&self, input_slo_id: String) -> Result<Vec<SloAlert>, StoreError> {
+        use crate::schema::slo_alerts::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = slo_alerts
+            .filter(slo_id.eq(input_slo_id))
+            .order(triggered_at.desc())
+            .limit(100)
+            .select(SloAlert::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn open_slo_alert(&self, input_slo_id: String, input_burn_rate: f64) -> Result<SloAlert, StoreError> {
+        use crate::schema::slo_alerts::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = diesel::insert_into(slo_alerts)
+            .values(SloAlert {
+                id: Uuid::new_v4().to_string(),
+                slo_id: input_slo_id,
+                burn_rate: input_burn_rate,
+                triggered_at: Utc::now().naive_utc(),
+                resolved_at: None,
+            })
+            .returning(SloAlert::as_returning())
+            .get_result(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn resolve_slo_alert(&self, input_alert_id: String) -> Result<usize, StoreError> {
+        use crate::schema::slo_alerts::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = diesel::update(slo_alerts.filter(id.eq(input_alert_id)))
+            .set(resolved_at.eq(Some(Utc::now().naive_utc())))
+            .execute(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::audit::Change;
+
+pub const EMAIL_CHANNEL: &str = "email";
+pub const WEBHOOK_CHANNEL: &str = "webhook";
+
+const SUBSCRIBER: &str = "subscriber";
+const SUBSCRIBER_ADD: &str = "subscriber.add";
+const SUBSCRIBER_CONFIRM: &str = "subscriber.confirm";
+const SUBSCRIBER_REMOVE: &str = "subscriber.remove";
+
+/// Subscribers sign up through public pages, so their changes are recorded
+/// without an actor, under the organization owning the website. Tokens are
+/// left out.
+async fn record_subscriber_change<C>(
+    conn: &mut C,
+    action: &'static str,
+    subscriber: &Subscriber,
+    removed: bool,
+    ip: Option<String>,
+) -> Result<(), StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::websites;
+
+    let website_org_id = websites::table
+        .filter(websites::url.eq(&subscriber.website_url))
+        .select(websites::org_id)
+        .first::<String>(conn)
+        .await?;
+
+    let state = serde_json::json!({
+        "website_url": subscriber.website_url,
+        "channel": subscriber.channel,
+        "target": subscriber.target,
+        "is_confirmed": subscriber.is_confirmed,
+    });
+
+    let change = Change::new(action, SUBSCRIBER, &subscriber.id).org(website_org_id);
+    let change = if removed { change.before(&state) } else { change.after(&state) };
+    change.record_anonymous(conn, ip).await
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize, Clone)]
+#[diesel(table_name = crate::schema::subscribers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Subscriber {
+    pub id: String,
+    pub website_url: String,
+    pub channel: String,
+    pub target: String,
+    pub confirm_token: Option<String>,
+    pub unsubscribe_token: String,
+    pub is_confirmed: bool,
+    pub created_at: NaiveDateTime,
+    /// Status changes up to here reached the subscriber, set after a failed
+    /// delivery. `None` once it is caught up with the website's cursor.
+    pub notified_until: Option<NaiveDateTime>,
+}
+
+impl Store {
+    /// Registers a subscriber for a website. Email subscribers stay unconfirmed until
+    /// the confirm token is used; webhooks are active straight away. Subscribing twice
+    /// with the same target returns the existing row.
+    pub async fn add_subscriber(
+        &self,
+        input_website_url: String,
+        input_channel: String,
+        input_target: String,
+        input_ip: Option<String>,
+    ) -> Result<Subscriber, StoreError> {
+        use crate::schema::subscribers::dsl::*;
+
+        // Fails with NotFound when we don't monitor this website
+        let _website = self.search_website(&input_website_url).await?;
+
+        let mut conn = self.pool.get().await?;
+
+        let existing = subscribers
+            .filter(website_url.eq(&input_website_url))
+            .filter(channel.eq(&input_channel))
+            .filter(target.eq(&input_target))
+            .select(Subscriber::as_select())
+            .first(&mut conn)
+            .await
+            .optional()?;
+
+        if let Some(s) = existing {
+            return Ok(s);
+        }
+
+        let is_email = input_channel == EMAIL_CHANNEL;
+        let new_subscriber = Subscriber {
+            id: Uuid::new_v4().to_string(),
+            website_url: input_website_url,
+            channel: input_channel,
+            target: input_target,
+            confirm_token: if is_email { Some(Uuid::new_v4().to_string()) } else { None },
+            unsubscribe_token: Uuid::new_v4().to_string(),
+            is_confirmed: !is_email,
+            created_at: Utc::now().naive_utc(),
+            notified_until: None,
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let created_subscriber = diesel::insert_into(subscribers)
+                    .values(new_subscriber)
+                    .returning(Subscriber::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                record_subscriber_change(conn, SUBSCRIBER_ADD, &created_subscriber, false, input_ip).await?;
+                Ok(created_subscriber)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn confirm_subscriber(&self, input_token: String, input_ip: Option<String>) -> Result<Subscriber, StoreError> {
+        use crate::schema::subscribers::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let confirmed = diesel::update(subscribers.filter(confirm_token.eq(Some(input_token))))
+                    .set((is_confirmed.eq(true), confirm_token.eq(None::<String>)))
+                    .returning(Subscriber::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                record_subscriber_change(conn, SUBSCRIBER_CONFIRM, &confirmed, false, input_ip).await?;
+                Ok(confirmed)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn remove_subscriber(&self, input_token: String, input_ip: Option<String>) -> Result<usize, StoreError> {
+        use crate::schema::subscribers::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let deleted = diesel::delete(subscribers.filter(unsubscribe_token.eq(input_token)))
+                    .returning(Subscriber::as_returning())
+                    .get_result(conn)
+                    .await
+                    .optional()?;
+
+                let Some(subscriber) = deleted else {
+                    return Ok(0);
+                };
+
+                record_subscriber_change(conn, SUBSCRIBER_REMOVE, &subscriber, true, input_ip).await?;
+                Ok(1)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_confirmed_subscribers(
+        &self,
+        input_website_url: String,
+    ) -> Result<Vec<Subscriber>, StoreError> {
+        use crate::schema::subscribers::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = subscribers
+            .filter(website_url.eq(input_website_url))
+            .filter(is_confirmed.eq(true))
+            .select(Subscriber::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_notification_cursor(
+        &self,
+        input_website_url: String,
+    ) -> Result<Option<NaiveDateTime>, StoreError> {
+        use crate::schema::notification_cursor::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = notification_cursor
+            .filter(website_url.eq(input_website_url))
+            .select(last_tick_at)
+            .first::<NaiveDateTime>(&mut conn)
+            .await
+            .optional()?;
+
+        Ok(res)
+    }
+
+    pub async fn set_notification_cursor(
+        &self,
+        input_website_url: String,
+        input_last_tick_at: NaiveDateTime,
+    ) -> Result<(), StoreError> {
+        use crate::schema::notification_cursor::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        diesel::insert_into(notification_cursor)
+            .values((
+                website_url.eq(input_website_url),
+                last_tick_at.eq(input_last_tick_at),
+            ))
+            .on_conflict(website_url)
+            .do_update()
+            .set(last_tick_at.eq(input_last_tick_at))
+            .execute(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_subscriber_notified_until(
+        &self,
+        input_id: String,
+        input_notified_until: Option<NaiveDateTime>,
+    ) -> Result<(), StoreError> {
+        use crate::schema::subscribers::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        diesel::update(subscribers.filter(id.eq(input_id)))
+            .set(notified_until.eq(input_notified_until))
+            .execute(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{
+    error::StoreError,
+    models::plan::{org_billing_owner, QuotaError},
+    store::Store,
+};
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::usage_daily)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UsageDay {
+    pub day: NaiveDate,
+    pub events: i64,
+    pub checks: i64,
+    #[serde(skip_serializing)]
+    pub org_id: String,
+}
+
+#[derive(QueryableByName)]
+struct UsageTotal {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total: i64,
+}
+
+/// An organization's event quota for its current billing period.
+#[derive(Clone)]
+pub struct EventQuota {
+    pub plan: String,
+    pub limit: i64,
+    pub period_start: NaiveDate,
+    /// Exclusive
+    pub period_end: NaiveDate,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UsageReport {
+    pub period_start: NaiveDate,
+    /// Exclusive
+    pub period_end: NaiveDate,
+    pub plan: String,
+    pub events: i64,
+    pub event_quota: i64,
+    pub events_over_quota: bool,
+    pub checks: i64,
+    pub monitors: i64,
+    pub max_monitors: i32,
+    pub monitors_over_limit: bool,
+    pub days: Vec<UsageDay>,
+}
+
+impl Store {
+    /// The plan limit and billing period the organization's page views are
+    /// metered against.
+    pub async fn get_event_quota(&self, input_org_id: String) -> Result<EventQuota, StoreError> {
+        let org_plan = self.get_org_plan(input_org_id.clone()).await?;
+        let (period_start, period_end) = self.get_org_billing_period(input_org_id).await?;
+
+        Ok(EventQuota {
+            plan: org_plan.name,
+            limit: org_plan.monthly_event_quota,
+            period_start,
+            period_end,
+        })
+    }
+
+    /// Counts one tracked page view against the organization, or fails with
+    /// `QuotaError::Events` without counting it once the period's quota is
+    /// used up. Checking and counting is one statement, so views arriving
+    /// together overshoot the quota by at most their number.
+    pub async fn meter_event(&self, input_org_id: String, quota: &EventQuota) -> Result<(), StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let query = r#"
+            INSERT INTO usage_daily (org_id, day, events, checks)
+            SELECT $1, (NOW() AT TIME ZONE 'UTC')::DATE, 1, 0
+            WHERE (
+                SELECT COALESCE(SUM(events), 0)
+                FROM usage_daily
+                WHERE org_id = $1 AND day >= $2 AND day < $3
+            ) < $4
+            ON CONFLICT (org_id, day) DO UPDATE SET events = usage_daily.events + 1
+            RETURNING events AS total;
+        "#;
+
+        let metered = diesel::sql_query(query)
+            .bind::<diesel::sql_types::Text, _>(input_org_id)
+            .bind::<diesel::sql_types::Date, _>(quota.period_start)
+            .bind::<diesel::sql_types::Date, _>(quota.period_end)
+            .bind::<diesel::sql_types::BigInt, _>(quota.limit)
+            .get_result::<UsageTotal>(&mut conn)
+            .await
+            .optional()?;
+
+        if metered.is_none() {
+            return Err(QuotaError::Events {
+                plan: quota.plan.clone(),
+                limit: quota.limit,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Counts one uptime check against the organization owning `input_website_url`.
+    pub async fn record_check(&self, input_website_url: String) -> Result<(), StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let query = r#"
+            INSERT INTO usage_daily (org_id, day, events, checks)
+            SELECT org_id, (NOW() AT TIME ZONE 'UTC')::DATE, 0, 1
+            FROM websites
+            WHERE url = $1
+            ON CONFLICT (org_id, day) DO UPDATE SET checks = usage_daily.checks + 1;
+        "#;
+
+        diesel::sql_query(query)
+            .bind::<diesel::sql_types::Text, _>(input_website_url)
+            .execute(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// The billing period containing today: the current paid subscription
+    /// period when there is one, otherwise the calendar month.
+    pub async fn get_billing_period(&self, input_user_id: String) -> Result<(NaiveDate, NaiveDate), StoreError> {
+        let today = Utc::now().date_naive();
+
+        if let Some(end) = self
+            .get_users_subscription(input_user_id)
+            .await?
+            .and_then(|s| s.current_period_end)
+            .map(|end| end.date())
+            .filter(|end| *end > today)
+        {
+            let start = end.checked_sub_months(Months::new(1)).unwrap_or(today);
+            return Ok((start, end));
+        }
+
+        let start = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today);
+        let end = start.checked_add_months(Months::new(1)).unwrap_or(today);
+        Ok((start, end))
+    }
+
+    /// The billing period of the organization's billing owner.
+    pub async fn get_org_billing_period(&self, input_org_id: String) -> Result<(NaiveDate, NaiveDate), StoreError> {
+        let mut conn = self.pool.get().await?;
+        let (owner, _) = org_billing_owner(&mut conn, &input_org_id).await?;
+
+        self.get_billing_period(owner).await
+    }
+
+    /// Daily usage in `[from, to)`, oldest first.
+    pub async fn get_usage_between(
+        &self,
+        input_org_id: String,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<UsageDay>, StoreError> {
+        use crate::schema::usage_daily::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = usage_daily
+            .filter(org_id.eq(input_org_id))
+            .filter(day.ge(from))
+            .filter(day.lt(to))
+            .order(day.asc())
+            .select(UsageDay::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_events_between(
+        &self,
+        input_org_id: String,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<i64, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let query = r#"
+            SELECT COALESCE(SUM(events), 0)::BIGINT AS total
+            FROM usage_daily
+            WHERE org_id = $1 AND day >= $2 AND day < $3;
+        "#;
+
+        let result = diesel::sql_query(query)
+            .bind::<diesel::sql_types::Text, _>(input_org_id)
+            .bind::<diesel::sql_types::Date, _>(from)
+            .bind::<diesel::sql_types::Date, _>(to)
+            .get_result::<UsageTotal>(&mut conn)
+            .await?;
+
+        Ok(result.total)
+    }
+
+    /// The organization's usage in the period `[from, to)`, or the current
+    /// billing period, against its plan.
+    pub async fn get_usage_report(
+        &self,
+        input_org_id: String,
+        period: Option<(NaiveDate, NaiveDate)>,
+    ) -> Result<UsageReport, StoreError> {
+        use crate::schema::websites;
+
+        let (from, to) = match period {
+            Some(p) => p,
+            None => self.get_org_billing_period(input_org_id.clone()).await?,
+        };
+
+        let org_plan = self.get_org_plan(input_org_id.clone()).await?;
+        let days = self.get_usage_between(input_org_id.clone(), from, to).await?;
+
+        let mut conn = self.pool.get().await?;
+
+        let monitors = websites::table
+            .filter(websites::org_id.eq(&input_org_id))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+
+        let events = days.iter().map(|d| d.events).sum::<i64>();
+        let checks = days.iter().map(|d| d.checks).sum::<i64>();
+
+        Ok(UsageReport {
+            period_start: from,
+            period_end: to,
+            plan: org_plan.name,
+            events,
+            event_quota: org_plan.monthly_event_quota,
+            events_over_quota: events > org_plan.monthly_event_quota,
+            checks,
+            monitors,
+            max_monitors: org_plan.max_monitors,
+            monitors_over_limit: monitors > org_plan.max_monitors as i64,
+            days,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::audit::Actor, test_db::test_store};
+
+    #[tokio::test]
+    async fn usage_counts_against_the_websites_organization() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let actor = Actor { user_id: user_id.clone(), ip: None };
+        let personal = s.get_personal_org_id(user_id).await.unwrap();
+        let team = s.create_organization(&actor, "Team".to_string()).await.unwrap();
+
+        let website = s
+            .create_website(&actor, team.id.clone(), "https://team.example.com".to_string(), String::new())
+            .await
+            .unwrap();
+        let quota = s.get_event_quota(team.id.clone()).await.unwrap();
+        s.meter_event(website.org_id.clone(), &quota).await.unwrap();
+        s.meter_event(website.org_id.clone(), &quota).await.unwrap();
+        s.record_check(website.url).await.unwrap();
+
+        let report = s.get_usage_report(team.id, None).await.unwrap();
+        assert_eq!((report.events, report.checks, report.monitors), (2, 1, 1));
+        assert_eq!(report.plan, "Basic");
+
+        let report = s.get_usage_report(personal, None).await.unwrap();
+        assert_eq!((report.events, report.checks, report.monitors), (0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn events_past_the_quota_are_refused_and_not_counted() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let org_id = s.get_personal_org_id(user_id).await.unwrap();
+
+        let mut quota = s.get_event_quota(org_id.clone()).await.unwrap();
+        assert_eq!(quota.limit, 10_000);
+        quota.limit = 2;
+
+        s.meter_event(org_id.clone(), &quota).await.unwrap();
+        s.meter_event(org_id.clone(), &quota).await.unwrap();
+        let over = s.meter_event(org_id.clone(), &quota).await;
+        assert!(matches!(over, Err(StoreError::Quota(QuotaError::Events { limit: 2, .. }))));
+
+        let report = s.get_usage_report(org_id, None).await.unwrap();
+        assert_eq!(report.events, 2);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use uuid::Uuid;
+
+use super::{
+    password::hash_password,
+    session::{hash_token, new_refresh_token},
+};
+use crate::models::audit::{Actor, Change};
+
+pub const VERIFY_EMAIL: &str = "verify_email";
+pub const CHANGE_EMAIL: &str = "change_email";
+pub const RESET_PASSWORD: &str = "reset_password";
+
+const USER: &str = "user";
+const EMAIL_VERIFY: &str = "user.email_verify";
+const EMAIL_CHANGE: &str = "user.email_change";
+const PASSWORD_RESET: &str = "user.password_reset";
+
+#[derive(Queryable, Insertable, Selectable)]
+#[diesel(table_name = crate::schema::email_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct EmailToken {
+    pub id: String,
+    pub user_id: String,
+    pub purpose: String,
+    pub token_hash: String,
+    pub new_email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+/// Reset links grant account access, so they expire quickly.
+fn token_ttl(purpose: &str) -> Duration {
+    match purpose {
+        RESET_PASSWORD => Duration::hours(1),
+        _ => Duration::days(1),
+    }
+}
+
+impl Store {
+    /// Issues a one-time token for `purpose`, replacing any unused one the user
+    /// had for it. Only its hash is stored, the token itself goes into the link.
+    pub async fn create_email_token(
+        &self,
+        input_user_id: String,
+        input_purpose: &str,
+        input_new_email: Option<String>,
+    ) -> Result<String, StoreError> {
+        use crate::schema::email_tokens::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let token = new_refresh_token();
+        let now = Utc::now().naive_utc();
+        let new_token = EmailToken {
+            id: Uuid::new_v4().to_string(),
+            user_id: input_user_id.clone(),
+            purpose: input_purpose.to_string(),
+            token_hash: hash_token(&token),
+            new_email: input_new_email,
+            created_at: now,
+            expires_at: now + token_ttl(input_purpose),
+            used_at: None,
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                diesel::delete(
+                    email_tokens
+                        .filter(user_id.eq(&input_user_id))
+                        .filter(purpose.eq(input_purpose))
+                        .filter(used_at.is_null()),
+                )
+                .execute(conn)
+                .await?;
+
+                diesel::insert_into(email_tokens)
+                    .values(new_token)
+                    .execute(conn)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Marks an unused, unexpired token for `input_purpose` as used and returns
+    /// it. Anything else fails with `NotFound`.
+    async fn consume_email_token<C>(conn: &mut C, input_token: &str, input_purpose: &str) -> Result<EmailToken, StoreError>
+    where
+        C: diesel_async::AsyncConnection<Backend = diesel::pg::Pg>,
+    {
+        use crate::schema::email_tokens::dsl::*;
+
+        let now = Utc::now().naive_utc();
+
+        diesel::update(
+            email_tokens
+                .filter(token_hash.eq(hash_token(input_token)))
+                .filter(purpose.eq(input_purpose))
+                .filter(used_at.is_null())
+                .filter(expires_at.gt(now)),
+        )
+        .set(used_at.eq(Some(now)))
+        .returning(EmailToken::as_returning())
+        .get_result(conn)
+        .await
+        .map_err(StoreError::from)
+    }
+
+    /// Marks the user's email as verified. Returns the user id.
+    pub async fn verify_email(&self, input_token: String, input_ip: Option<String>) -> Result<String, StoreError> {
+        use crate::schema::users;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let token = Self::consume_email_token(conn, &input_token, VERIFY_EMAIL).await?;
+
+                let address = diesel::update(users::table.filter(users::id.eq(&token.user_id)))
+                    .set(users::email_verified.eq(true))
+                    .returning(users::email)
+                    .get_result::<String>(conn)
+                    .await?;
+
+                let actor = Actor { user_id: token.user_id, ip: input_ip };
+                Change::new(EMAIL_VERIFY, USER, &actor.user_id)
+                    .after(&serde_json::json!({ "email": address }))
+                    .record(conn, &actor)
+                    .await?;
+
+                Ok(actor.user_id)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Switches the user to the address the token was sent to, which is
+    /// verified by following it. Returns the new address.
+    pub async fn confirm_email_change(&self, input_token: String, input_ip: Option<String>) -> Result<String, StoreError> {
+        use crate::schema::users;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let token = Self::consume_email_token(conn, &input_token, CHANGE_EMAIL).await?;
+                let address = token.new_email.ok_or(StoreError::NotFound)?;
+
+                let previous = users::table
+                    .filter(users::id.eq(&token.user_id))
+                    .select(users::email)
+                    .first::<String>(conn)
+                    .await?;
+
+                // Fails on the unique email if someone took the address meanwhile
+                diesel::update(users::table.filter(users::id.eq(&token.user_id)))
+                    .set((users::email.eq(&address), users::email_verified.eq(true)))
+                    .execute(conn)
+                    .await?;
+
+                let actor = Actor { user_id: token.user_id, ip: input_ip };
+                Change::new(EMAIL_CHANGE, USER, &actor.user_id)
+                    .before(&serde_json::json!({ "email": previous }))
+                    .after(&serde_json::json!({ "email": address }))
+                    .record(conn, &actor)
+                    .await?;
+
+                Ok(address)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Sets a new password and signs the user out everywhere.
+    pub async fn reset_password(
+        &self,
+        input_token: String,
+        new_password: String,
+        input_ip: Option<String>,
+    ) -> Result<(), StoreError> {
+        use crate::schema::{sessions, users};
+
+        let new_hash = hash_password(new_password).await?;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let token = Self::consume_email_token(conn, &input_token, RESET_PASSWORD).await?;
+
+                // Receiving the link proves the address too
+                diesel::update(users::table.filter(users::id.eq(&token.user_id)))
+                    .set((users::password.eq(Some(new_hash)), users::email_verified.eq(true)))
+                    .execute(conn)
+                    .await?;
+
+                let revoked = diesel::update(
+                    sessions::table
+                        .filter(sessions::user_id.eq(&token.user_id))
+                        .filter(sessions::revoked_at.is_null()),
+                )
+                .set(sessions::revoked_at.eq(Some(Utc::now().naive_utc())))
+                .execute(conn)
+                .await?;
+
+                let actor = Actor { user_id: token.user_id, ip: input_ip };
+                Change::new(PASSWORD_RESET, USER, &actor.user_id)
+                    .after(&serde_json::json!({ "sessions_revoked": revoked }))
+                    .record(conn, &actor)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// The id and verification state of the account using `input_email`.
+    pub async fn find_user_by_email(&self, input_email: String) -> Result<Option<(String, bool)>, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = users
+            .filter(email.eq(input_email))
+            .select((id, email_verified))
+            .first::<(String, bool)>(&mut conn)
+            .await
+            .optional()?;
+
+        Ok(res)
+    }
+
+    pub async fn get_users_email(&self, input_user_id: String) -> Result<String, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = users
+            .filter(id.eq(input_user_id))
+            .select(email)
+            .first::<String>(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::test_store;
+
+    #[tokio::test]
+    async fn email_tokens_work_once_for_their_purpose() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+
+        let token = s.create_email_token(user_id.clone(), VERIFY_EMAIL, None).await.unwrap();
+
+        // Not good for anything else
+        let wrong_purpose = s.reset_password(token.clone(), "new password".to_string(), None).await;
+        assert!(matches!(wrong_purpose, Err(StoreError::NotFound)));
+
+        assert_eq!(s.verify_email(token.clone(), None).await.unwrap(), user_id);
+        assert_eq!(s.find_user_by_email("a@example.com".to_string()).await.unwrap(), Some((user_id.clone(), true)));
+        assert!(matches!(s.verify_email(token, None).await, Err(StoreError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn new_tokens_replace_unused_ones_and_expired_ones_fail() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+
+        let first = s.create_email_token(user_id.clone(), RESET_PASSWORD, None).await.unwrap();
+        let second = s.create_email_token(user_id.clone(), RESET_PASSWORD, None).await.unwrap();
+        let replaced = s.reset_password(first, "new password".to_string(), None).await;
+        assert!(matches!(replaced, Err(StoreError::NotFound)));
+
+        let mut conn = s.pool.get().await.unwrap();
+        diesel::update(crate::schema::email_tokens::table)
+            .set(crate::schema::email_tokens::expires_at.eq(Utc::now().naive_utc() - Duration::minutes(1)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let expired = s.reset_password(second, "new password".to_string(), None).await;
+        assert!(matches!(expired, Err(StoreError::NotFound)));
+
+        // Nothing changed
+        assert!(s.sign_in("a@example.com".to_string(), "hunter22".to_string()).await.is_ok());
+    }
+}
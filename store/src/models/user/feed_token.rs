@@ -0,0 +1,68 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+
+use crate::{
+    error::StoreError,
+    models::audit::{Actor, Change},
+    store::Store,
+};
+
+const USER: &str = "user";
+const FEED_TOKEN: &str = "user.feed_token";
+
+impl Store {
+    /// Turns the user's combined status feed on with a fresh token, or off.
+    /// Turning it on again replaces the old token.
+    pub async fn set_feed_token(&self, actor: &Actor, enabled: bool) -> Result<Option<String>, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let token = enabled.then(|| {
+            let mut bytes = [0u8; 24];
+            OsRng.fill_bytes(&mut bytes);
+            hex::encode(bytes)
+        });
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let was_enabled = users
+                    .filter(id.eq(&actor.user_id))
+                    .select(feed_token.is_not_null())
+                    .first::<bool>(conn)
+                    .await?;
+
+                diesel::update(users.filter(id.eq(&actor.user_id)))
+                    .set(feed_token.eq(&token))
+                    .execute(conn)
+                    .await?;
+
+                Change::new(FEED_TOKEN, USER, &actor.user_id)
+                    .before(&serde_json::json!({ "enabled": was_enabled }))
+                    .after(&serde_json::json!({ "enabled": enabled }))
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(token)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// The user whose feed `input_token` opens.
+    pub async fn get_feed_user(&self, input_token: String) -> Result<String, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = users
+            .filter(feed_token.eq(input_token))
+            .select(id)
+            .first::<String>(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+}
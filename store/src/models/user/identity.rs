@@ -0,0 +1,342 @@
+use crate::{error::StoreError, store::Store};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::User;
+use crate::models::{
+    audit::{Actor, Change},
+    org::create_personal_org,
+};
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::identities)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Identity {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: NaiveDateTime,
+}
+
+const USER: &str = "user";
+const SSO_RECLAIM: &str = "user.sso_reclaim";
+
+/// What was taken away from an unverified account when its address was
+/// proven through SSO, recorded in the audit log.
+#[derive(Serialize)]
+struct Reclaimed {
+    sessions_revoked: usize,
+    api_keys_revoked: usize,
+    two_factor_removed: bool,
+    recovery_codes_removed: usize,
+    invitations_revoked: usize,
+    memberships_removed: usize,
+    email_tokens_revoked: usize,
+}
+
+/// Whoever signed up with an address they never verified may not be its
+/// owner, so they lose every way back into the account: the password,
+/// sessions, API keys, 2FA they could pass, invitations they sent,
+/// organizations they joined and emailed links, like an email change to an
+/// address of theirs.
+async fn reclaim_unverified_account<C>(conn: &mut C, input_user_id: &str) -> Result<Reclaimed, StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::{api_keys, email_tokens, org_invitations, org_members, organizations, recovery_codes, sessions, users};
+
+    let now = Utc::now().naive_utc();
+
+    let two_factor_removed = users::table
+        .filter(users::id.eq(input_user_id))
+        .select(users::totp_secret.is_not_null())
+        .get_result::<bool>(conn)
+        .await?;
+
+    diesel::update(users::table.filter(users::id.eq(input_user_id)))
+        .set((
+            users::email_verified.eq(true),
+            users::password.eq(None::<String>),
+            users::totp_secret.eq(None::<String>),
+            users::totp_enabled_at.eq(None::<NaiveDateTime>),
+            users::totp_last_step.eq(None::<i64>),
+            users::feed_token.eq(None::<String>),
+        ))
+        .execute(conn)
+        .await?;
+
+    let sessions_revoked = diesel::update(
+        sessions::table
+            .filter(sessions::user_id.eq(input_user_id))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(Some(now)))
+    .execute(conn)
+    .await?;
+
+    let api_keys_revoked = diesel::update(
+        api_keys::table
+            .filter(api_keys::user_id.eq(input_user_id))
+            .filter(api_keys::revoked_at.is_null()),
+    )
+    .set(api_keys::revoked_at.eq(Some(now)))
+    .execute(conn)
+    .await?;
+
+    let recovery_codes_removed = diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(input_user_id)))
+        .execute(conn)
+        .await?;
+
+    let invitations_revoked = diesel::delete(
+        org_invitations::table
+            .filter(org_invitations::invited_by.eq(input_user_id))
+            .filter(org_invitations::accepted_at.is_null()),
+    )
+    .execute(conn)
+    .await?;
+
+    let team_orgs = organizations::table
+        .filter(organizations::personal.eq(false))
+        .select(organizations::id);
+    let memberships_removed = diesel::delete(
+        org_members::table
+            .filter(org_members::user_id.eq(input_user_id))
+            .filter(org_members::org_id.eq_any(team_orgs)),
+    )
+    .execute(conn)
+    .await?;
+
+    let email_tokens_revoked = diesel::update(
+        email_tokens::table
+            .filter(email_tokens::user_id.eq(input_user_id))
+            .filter(email_tokens::used_at.is_null()),
+    )
+    .set(email_tokens::used_at.eq(Some(now)))
+    .execute(conn)
+    .await?;
+
+    Ok(Reclaimed {
+        sessions_revoked,
+        api_keys_revoked,
+        two_factor_removed,
+        recovery_codes_removed,
+        invitations_revoked,
+        memberships_removed,
+        email_tokens_revoked,
+    })
+}
+
+/// A login asserted by an external provider, mapped from its claims.
+pub struct ExternalIdentity {
+    pub provider: String,
+    /// The provider's stable id for the account, never the email
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+impl Store {
+    /// Returns the user linked to `external`. On its first login the identity
+    /// is linked to the account with the same email, or to a new account
+    /// without a password. An unverified account with that email is taken
+    /// from whoever created it first.
+    pub async fn sign_in_with_identity(&self, external: ExternalIdentity) -> Result<String, StoreError> {
+        use crate::schema::{identities, users};
+
+        let mut conn = self.pool.get().await?;
+
+        let now = Utc::now().naive_utc();
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let linked = diesel::update(
+                    identities::table
+                        .filter(identities::provider.eq(&external.provider))
+                        .filter(identities::subject.eq(&external.subject)),
+                )
+                .set(identities::last_login_at.eq(now))
+                .returning(identities::user_id)
+                .get_result::<String>(conn)
+                .await
+                .optional()?;
+
+                if let Some(linked_user_id) = linked {
+                    return Ok(linked_user_id);
+                }
+
+                // Unverified addresses could belong to anyone, so they never link or sign up
+                let email = match external.email {
+                    Some(email) if external.email_verified => email,
+                    _ => return Err(StoreError::Unauthorized),
+                };
+
+                let existing = users::table
+                    .filter(users::email.eq(&email))
+                    .select((users::id, users::email_verified))
+                    .first::<(String, bool)>(conn)
+                    .await
+                    .optional()?;
+
+                let linked_user_id = match existing {
+                    Some((existing_id, true)) => existing_id,
+                    Some((existing_id, false)) => {
+                        let reclaimed = reclaim_unverified_account(conn, &existing_id).await?;
+
+                        let actor = Actor { user_id: existing_id.clone(), ip: None };
+                        Change::new(SSO_RECLAIM, USER, &existing_id)
+                            .after(&reclaimed)
+                            .record(conn, &actor)
+                            .await?;
+                        existing_id
+                    }
+                    None => {
+                        let new_user_id = diesel::insert_into(users::table)
+                            .values(User {
+                                id: Uuid::new_v4().to_string(),
+                                email: email.clone(),
+                                password: None,
+                                name: external.name.unwrap_or_else(|| email.clone()),
+                                plan_name: "Basic".to_string(),
+                                email_verified: true,
+                            })
+                            .returning(users::id)
+                            .get_result::<String>(conn)
+                            .await?;
+
+                        create_personal_org(conn, &new_user_id, &email).await?;
+                        new_user_id
+                    }
+                };
+
+                diesel::insert_into(identities::table)
+                    .values(Identity {
+                        id: Uuid::new_v4().to_string(),
+                        user_id: linked_user_id.clone(),
+                        provider: external.provider,
+                        subject: external.subject,
+                        email: Some(email),
+                        created_at: now,
+                        last_login_at: now,
+                    })
+                    .execute(conn)
+                    .await?;
+
+                Ok(linked_user_id)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_users_identities(&self, input_user_id: String) -> Result<Vec<Identity>, StoreError> {
+        use crate::schema::identities::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = identities
+            .filter(user_id.eq(input_user_id))
+            .order(created_at.asc())
+            .select(Identity::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{api_key::MONITORS_READ, audit::AuditFilter},
+        test_db::test_store,
+    };
+
+    fn google(subject: &str, email: &str, email_verified: bool) -> ExternalIdentity {
+        ExternalIdentity {
+            provider: "google".to_string(),
+            subject: subject.to_string(),
+            email: Some(email.to_string()),
+            email_verified,
+            name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn sso_takes_unverified_accounts_from_whoever_made_them() {
+        let Some(s) = test_store().await else { return };
+
+        // Someone signs up with the victim's address and sets up ways back in
+        let squatter = s
+            .sign_up("victim@example.com".to_string(), "hunter22".to_string(), "Squatter".to_string())
+            .await
+            .unwrap();
+        let actor = Actor { user_id: squatter.clone(), ip: None };
+        let (session, _) = s.create_session(squatter.clone(), None, None).await.unwrap();
+        let (_, key) = s
+            .create_api_key(&actor, "ci".to_string(), vec![MONITORS_READ.to_string()], None, None)
+            .await
+            .unwrap();
+        s.begin_totp_enrollment(squatter.clone()).await.unwrap();
+        let mut conn = s.pool.get().await.unwrap();
+        diesel::update(crate::schema::users::table.filter(crate::schema::users::id.eq(&squatter)))
+            .set(crate::schema::users::totp_enabled_at.eq(Some(Utc::now().naive_utc())))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        s.regenerate_recovery_codes(&actor).await.unwrap();
+        let team = s.create_organization(&actor, "Team".to_string()).await.unwrap();
+        s.create_org_invitation(&actor, team.id.clone(), "friend@example.com".to_string(), "viewer".to_string())
+            .await
+            .unwrap();
+
+        let user_id = s.sign_in_with_identity(google("sub-1", "victim@example.com", true)).await.unwrap();
+        assert_eq!(user_id, squatter);
+
+        assert!(s.sign_in("victim@example.com".to_string(), "hunter22".to_string()).await.is_err());
+        assert!(!s.is_session_active(session.id).await.unwrap());
+        assert!(s.authenticate_api_key(key).await.is_err());
+        assert!(!s.is_totp_enabled(user_id.clone()).await.unwrap());
+        assert_eq!(s.get_unused_recovery_code_count(user_id.clone()).await.unwrap(), 0);
+        assert!(s.get_org_invitations(team.id.clone()).await.unwrap().is_empty());
+        let orgs = s.get_users_organizations(user_id.clone()).await.unwrap();
+        assert!(orgs.iter().all(|m| m.organization.personal));
+
+        let filter = AuditFilter { actor_id: Some(user_id), action: Some(SSO_RECLAIM.to_string()), ..Default::default() };
+        let entries = s.get_audit_log(filter, 10, 0).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].after.as_ref().unwrap()["sessions_revoked"], 1);
+    }
+
+    #[tokio::test]
+    async fn unverified_provider_emails_never_link() {
+        let Some(s) = test_store().await else { return };
+
+        s.sign_up("owner@example.com".to_string(), "hunter22".to_string(), "Owner".to_string())
+            .await
+            .unwrap();
+
+        let res = s.sign_in_with_identity(google("sub-2", "owner@example.com", false)).await;
+        assert!(matches!(res, Err(StoreError::Unauthorized)));
+        assert!(s.sign_in("owner@example.com".to_string(), "hunter22".to_string()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn returning_identities_sign_in_to_the_same_account() {
+        let Some(s) = test_store().await else { return };
+
+        let first = s.sign_in_with_identity(google("sub-3", "new@example.com", true)).await.unwrap();
+        let again = s.sign_in_with_identity(google("sub-3", "new@example.com", true)).await.unwrap();
+        assert_eq!(first, again);
+        assert_eq!(s.get_users_identities(first).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{error::StoreError, store::Store};
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use uuid::Uuid;
+
+use super::{
+    audit::{Actor, Change},
+    org::create_personal_org,
+};
+
+pub mod email_token;
+pub mod feed_token;
+pub mod identity;
+pub mod password;
+pub mod session;
+pub mod two_factor;
+
+use password::{hash_password, verify_dummy_password, verify_password, Verification};
+
+/// Password Google sign-ups were created with before ID tokens were checked
+/// and identities were linked. It must never sign anyone in.
+const LEGACY_GOOGLE_PASSWORD: &str = "GOOGLE_AUTH";
+
+const USER: &str = "user";
+const PASSWORD_CHANGE: &str = "user.password_change";
+
+#[derive(Queryable, Insertable, Selectable)]
+#[diesel(table_name = crate::schema::users)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    /// `None` for accounts that only sign in through an external provider
+    pub password: Option<String>,
+    pub name: String,
+    pub plan_name: String,
+    pub email_verified: bool,
+}
+
+pub struct UserOutput {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    pub plan_type: String,
+}
+
+impl Store {
+    pub async fn sign_up(
+        &self,
+        username: String,
+        user_password: String,
+        user_name: String,
+    ) -> Result<String, StoreError> {
+        let password_hash = hash_password(user_password).await?;
+
+        let mut conn = self.pool.get().await?;
+        let new_user = User {
+            id: Uuid::new_v4().to_string(),
+            email: username,
+            password: Some(password_hash),
+            name: user_name,
+            plan_name: "Basic".to_string(),
+            email_verified: false,
+        };
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let u = diesel::insert_into(crate::schema::users::table)
+                    .values(new_user)
+                    .returning(User::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                create_personal_org(conn, &u.id, &u.name).await?;
+                Ok(u.id)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn sign_in(
+        &self,
+        input_email: String,
+        user_password: String,
+    ) -> Result<UserOutput, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        if user_password == LEGACY_GOOGLE_PASSWORD {
+            return Err(StoreError::Unauthorized);
+        }
+
+        let mut conn = self.pool.get().await?;
+        let u = users
+            .filter(email.eq(input_email))
+            .select(User::as_select())
+            .first(&mut conn)
+            .await
+            .optional()?;
+
+        // Unknown emails and accounts without a password fail like wrong
+        // passwords, after as long a check, so neither gives accounts away
+        let Some((u, stored)) = u.and_then(|u| u.password.clone().map(|stored| (u, stored))) else {
+            verify_dummy_password(user_password).await?;
+            return Err(StoreError::Unauthorized);
+        };
+
+        match verify_password(user_password.clone(), stored).await? {
+            Verification::Invalid => return Err(StoreError::Unauthorized),
+            Verification::Valid => {}
+            Verification::ValidNeedsRehash => {
+                // Upgrade plaintext rows and outdated parameters while we have the password
+                if let Ok(new_hash) = hash_password(user_password).await {
+                    let _ = diesel::update(users.filter(id.eq(&u.id)))
+                        .set(password.eq(new_hash))
+                        .execute(&mut conn)
+                        .await;
+                }
+            }
+        }
+
+        Ok(UserOutput {
+            id: u.id,
+            email: u.email,
+            name: u.name,
+            plan_type: u.plan_name,
+        })
+    }
+
+    /// Fails with `Unauthorized` when `old_password` does not match. Signs the
+    /// user out of every session but `current_session`.
+    pub async fn update_password(
+        &self,
+        actor: &Actor,
+        current_session: String,
+        old_password: String,
+        new_password: String,
+    ) -> Result<usize, StoreError> {
+        use crate::schema::{sessions, users::dsl::*};
+
+        let mut conn = self.pool.get().await?;
+
+        let stored = users
+            .filter(id.eq(&actor.user_id))
+            .select(password)
+            .first::<Option<String>>(&mut conn)
+            .await?
+            .ok_or(StoreError::Unauthorized)?;
+
+        if verify_password(old_password, stored).await? == Verification::Invalid {
+            return Err(StoreError::Unauthorized);
+        }
+
+        let new_hash = hash_password(new_password).await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let res = diesel::update(users.filter(id.eq(&actor.user_id)))
+                    .set(password.eq(new_hash))
+                    .execute(conn)
+                    .await?;
+
+                let revoked = diesel::update(
+                    sessions::table
+                        .filter(sessions::user_id.eq(&actor.user_id))
+                        .filter(sessions::id.ne(&current_session))
+                        .filter(sessions::revoked_at.is_null()),
+                )
+                .set(sessions::revoked_at.eq(Some(Utc::now().naive_utc())))
+                .execute(conn)
+                .await?;
+
+                // Password hashes never go into the log, only that it changed
+                Change::new(PASSWORD_CHANGE, USER, &actor.user_id)
+                    .after(&serde_json::json!({ "sessions_revoked": revoked }))
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(res)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -0,0 +1,137 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params,
+};
+
+use crate::error::StoreError;
+
+/// Outcome of checking a password against what is stored for the user.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// The password matched but was stored in plaintext or with older
+    /// parameters and should be hashed again.
+    ValidNeedsRehash,
+}
+
+/// Hash of a throwaway password with the current parameters. Sign-ins for
+/// unknown or password-less accounts are checked against it, so they take
+/// as long as a wrong password does.
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$fWfN9SsCoarlVJETrf6Q6g$DQ5e4dvDWNVPAu3n1M64aBLxgPZ9uoo0xZ9jmSlReGQ";
+
+/// Hashes with Argon2id, the default parameters and a random per-user salt.
+/// The PHC string carries the salt and parameters. Argon2 is deliberately
+/// slow, so it runs on the blocking pool instead of stalling the runtime.
+pub async fn hash_password(password: String) -> Result<String, StoreError> {
+    tokio::task::spawn_blocking(move || hash(&password))
+        .await
+        .map_err(|e| StoreError::Database(Box::new(e)))?
+        .map_err(|e| StoreError::Database(e.to_string().into()))
+}
+
+/// Checks `password` against the stored hash on the blocking pool.
+pub async fn verify_password(password: String, stored: String) -> Result<Verification, StoreError> {
+    tokio::task::spawn_blocking(move || verify(&password, &stored))
+        .await
+        .map_err(|e| StoreError::Database(Box::new(e)))
+}
+
+/// Spends the time of a password check without any account to check against.
+pub async fn verify_dummy_password(password: String) -> Result<(), StoreError> {
+    verify_password(password, DUMMY_HASH.to_string()).await.map(|_| ())
+}
+
+fn hash(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+fn verify(password: &str, stored: &str) -> Verification {
+    let Ok(parsed) = PasswordHash::new(stored) else {
+        // Rows from before hashing hold the plaintext password
+        return if !stored.starts_with('$') && stored == password {
+            Verification::ValidNeedsRehash
+        } else {
+            Verification::Invalid
+        };
+    };
+
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return Verification::Invalid;
+    }
+
+    // Parsed params also carry the output length, which the defaults leave
+    // unset, so only the costs are compared
+    let current = Params::default();
+    let is_current = parsed.algorithm == Algorithm::Argon2id.ident()
+        && Params::try_from(&parsed).is_ok_and(|p| {
+            (p.m_cost(), p.t_cost(), p.p_cost()) == (current.m_cost(), current.t_cost(), current.p_cost())
+        });
+
+    if is_current {
+        Verification::Valid
+    } else {
+        Verification::ValidNeedsRehash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_with(algorithm: Algorithm, params: Params, password: &str) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::new(algorithm, argon2::Version::V0x13, params)
+            .hash_password(password.as_bytes(), &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn verifies_current_hashes() {
+        let stored = hash_password("correct horse".to_string()).await.unwrap();
+
+        assert!(stored.starts_with("$argon2id$"));
+        assert_eq!(
+            verify_password("correct horse".to_string(), stored.clone()).await.unwrap(),
+            Verification::Valid
+        );
+        assert_eq!(
+            verify_password("wrong horse".to_string(), stored).await.unwrap(),
+            Verification::Invalid
+        );
+    }
+
+    #[test]
+    fn rehashes_outdated_parameters() {
+        let weaker = hash_with(Algorithm::Argon2id, Params::new(8192, 1, 1, None).unwrap(), "pw");
+        assert_eq!(verify("pw", &weaker), Verification::ValidNeedsRehash);
+
+        let argon2i = hash_with(Algorithm::Argon2i, Params::default(), "pw");
+        assert_eq!(verify("pw", &argon2i), Verification::ValidNeedsRehash);
+
+        assert_eq!(verify("other", &weaker), Verification::Invalid);
+    }
+
+    #[test]
+    fn upgrades_legacy_plaintext() {
+        assert_eq!(verify("hunter2", "hunter2"), Verification::ValidNeedsRehash);
+        assert_eq!(verify("hunter3", "hunter2"), Verification::Invalid);
+
+        // Broken hashes never fall back to a plaintext comparison
+        assert_eq!(verify("$argon2id$broken", "$argon2id$broken"), Verification::Invalid);
+    }
+
+    #[test]
+    fn dummy_hash_uses_current_parameters() {
+        // A cheaper dummy would let response times tell unknown accounts apart
+        assert_eq!(verify("not the password", DUMMY_HASH), Verification::Valid);
+        assert_eq!(verify("guess", DUMMY_HASH), Verification::Invalid);
+    }
+}
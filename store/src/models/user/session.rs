@@ -0,0 +1,295 @@
+use crate::{error::StoreError, store::Store};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::models::audit::{Actor, Change};
+
+const USER: &str = "user";
+const SESSION: &str = "session";
+const SESSION_REVOKE: &str = "session.revoke";
+const SESSION_REVOKE_ALL: &str = "user.sessions_revoke";
+
+/// How long a refresh token stays valid without being used.
+pub const REFRESH_TOKEN_TTL: Duration = Duration::days(30);
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[diesel(table_name = crate::schema::sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub refresh_token_hash: String,
+    #[serde(skip_serializing)]
+    pub previous_refresh_token_hash: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+pub(crate) fn new_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl Store {
+    /// Starts a session and returns it with its refresh token, which is only
+    /// ever available here.
+    pub async fn create_session(
+        &self,
+        input_user_id: String,
+        input_user_agent: Option<String>,
+        input_ip: Option<String>,
+    ) -> Result<(Session, String), StoreError> {
+        use crate::schema::sessions;
+
+        let mut conn = self.pool.get().await?;
+
+        let refresh_token = new_refresh_token();
+        let now = Utc::now().naive_utc();
+
+        let session = diesel::insert_into(sessions::table)
+            .values(Session {
+                id: Uuid::new_v4().to_string(),
+                user_id: input_user_id,
+                refresh_token_hash: hash_token(&refresh_token),
+                previous_refresh_token_hash: None,
+                user_agent: input_user_agent,
+                ip: input_ip,
+                created_at: now,
+                last_used_at: now,
+                expires_at: now + REFRESH_TOKEN_TTL,
+                revoked_at: None,
+            })
+            .returning(Session::as_returning())
+            .get_result(&mut conn)
+            .await?;
+
+        Ok((session, refresh_token))
+    }
+
+    /// Swaps a refresh token for a new one. Unknown, expired and revoked tokens
+    /// fail with `Unauthorized`. Presenting a token that was already rotated out
+    /// revokes the whole session, as only a copy of it can still be around.
+    pub async fn rotate_refresh_token(
+        &self,
+        input_refresh_token: String,
+        input_ip: Option<String>,
+    ) -> Result<(Session, String), StoreError> {
+        use crate::schema::sessions::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let presented = hash_token(&input_refresh_token);
+        let new_token = new_refresh_token();
+        let now = Utc::now().naive_utc();
+
+        let rotated = conn
+            .transaction::<_, StoreError, _>(|conn| {
+                async move {
+                    let reused = diesel::update(
+                        sessions
+                            .filter(previous_refresh_token_hash.eq(&presented))
+                            .filter(revoked_at.is_null()),
+                    )
+                    .set(revoked_at.eq(Some(now)))
+                    .execute(conn)
+                    .await?;
+                    if reused > 0 {
+                        return Ok(None);
+                    }
+
+                    let session = diesel::update(
+                        sessions
+                            .filter(refresh_token_hash.eq(&presented))
+                            .filter(revoked_at.is_null())
+                            .filter(expires_at.gt(now)),
+                    )
+                    .set((
+                        previous_refresh_token_hash.eq(Some(&presented)),
+                        refresh_token_hash.eq(hash_token(&new_token)),
+                        last_used_at.eq(now),
+                        expires_at.eq(now + REFRESH_TOKEN_TTL),
+                        ip.eq(input_ip),
+                    ))
+                    .returning(Session::as_returning())
+                    .get_result(conn)
+                    .await
+                    .optional()?;
+
+                    Ok(session.map(|s| (s, new_token)))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        // The reuse revocation above has to be committed before failing
+        rotated.ok_or(StoreError::Unauthorized)
+    }
+
+    pub async fn is_session_active(&self, input_session_id: String) -> Result<bool, StoreError> {
+        use crate::schema::sessions::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let count = sessions
+            .filter(id.eq(input_session_id))
+            .filter(revoked_at.is_null())
+            .filter(expires_at.gt(Utc::now().naive_utc()))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Active sessions, most recently used first.
+    pub async fn get_users_sessions(&self, input_user_id: String) -> Result<Vec<Session>, StoreError> {
+        use crate::schema::sessions::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let res = sessions
+            .filter(user_id.eq(input_user_id))
+            .filter(revoked_at.is_null())
+            .filter(expires_at.gt(Utc::now().naive_utc()))
+            .order(last_used_at.desc())
+            .select(Session::as_select())
+            .load(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    pub async fn revoke_session(&self, actor: &Actor, input_session_id: String) -> Result<usize, StoreError> {
+        use crate::schema::sessions::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let revoked = diesel::update(
+                    sessions
+                        .filter(id.eq(&input_session_id))
+                        .filter(user_id.eq(&actor.user_id))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(Some(Utc::now().naive_utc())))
+                .returning(Session::as_returning())
+                .get_result(conn)
+                .await
+                .optional()?;
+
+                let Some(session) = revoked else {
+                    return Ok(0);
+                };
+
+                Change::new(SESSION_REVOKE, SESSION, &session.id)
+                    .after(&session)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(1)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Signs the user out everywhere.
+    pub async fn revoke_all_sessions(&self, actor: &Actor) -> Result<usize, StoreError> {
+        use crate::schema::sessions::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let res = diesel::update(
+                    sessions
+                        .filter(user_id.eq(&actor.user_id))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(Some(Utc::now().naive_utc())))
+                .execute(conn)
+                .await?;
+
+                Change::new(SESSION_REVOKE_ALL, USER, &actor.user_id)
+                    .after(&serde_json::json!({ "sessions_revoked": res }))
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(res)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::audit::AuditFilter, test_db::test_store};
+
+    #[tokio::test]
+    async fn refresh_tokens_rotate_and_reuse_revokes_the_session() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let (session, first) = s.create_session(user_id, None, None).await.unwrap();
+
+        let (rotated, second) = s.rotate_refresh_token(first.clone(), None).await.unwrap();
+        assert_eq!(rotated.id, session.id);
+        assert_ne!(second, first);
+        let (_, third) = s.rotate_refresh_token(second.clone(), None).await.unwrap();
+
+        // The token just rotated out shows up again: someone kept a copy
+        assert!(matches!(s.rotate_refresh_token(second, None).await, Err(StoreError::Unauthorized)));
+        assert!(!s.is_session_active(session.id.clone()).await.unwrap());
+        // Which ends the session for the current token too
+        assert!(matches!(s.rotate_refresh_token(third, None).await, Err(StoreError::Unauthorized)));
+
+        assert!(matches!(
+            s.rotate_refresh_token("made up".to_string(), None).await,
+            Err(StoreError::Unauthorized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn revoking_sessions_is_logged() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let actor = Actor { user_id: user_id.clone(), ip: None };
+        let (first, _) = s.create_session(user_id.clone(), None, None).await.unwrap();
+        s.create_session(user_id.clone(), None, None).await.unwrap();
+
+        assert_eq!(s.revoke_session(&actor, first.id.clone()).await.unwrap(), 1);
+        assert_eq!(s.revoke_session(&actor, first.id.clone()).await.unwrap(), 0);
+        assert_eq!(s.revoke_all_sessions(&actor).await.unwrap(), 1);
+        assert!(s.get_users_sessions(user_id.clone()).await.unwrap().is_empty());
+
+        let filter = AuditFilter { actor_id: Some(user_id), ..Default::default() };
+        let actions = s
+            .get_audit_log(filter, 10, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.action)
+            .collect::<Vec<_>>();
+        assert_eq!(actions, vec![SESSION_REVOKE_ALL, SESSION_REVOKE]);
+    }
+}
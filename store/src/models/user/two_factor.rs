@@ -0,0 +1,418 @@
+use crate::{error::StoreError, store::Store};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use super::session::hash_token;
+use crate::models::audit::{Actor, Change};
+
+const TOTP_ISSUER: &str = "Nexus";
+const TOTP_STEP: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+const USER: &str = "user";
+const TWO_FACTOR_ENABLE: &str = "user.two_factor_enable";
+const TWO_FACTOR_DISABLE: &str = "user.two_factor_disable";
+const RECOVERY_CODES_REGENERATE: &str = "user.recovery_codes_regenerate";
+const RECOVERY_CODE_USE: &str = "user.recovery_code_use";
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TotpEnrollment {
+    /// Base32, for entering the secret by hand
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::recovery_codes)]
+struct NewRecoveryCode {
+    id: String,
+    user_id: String,
+    code_hash: String,
+}
+
+fn totp(secret: &str, account: &str) -> Result<TOTP, StoreError> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| StoreError::Database(format!("{:?}", e).into()))?;
+
+    // Authenticator apps assume SHA-1, 6 digits and 30 second steps
+    TOTP::new(Algorithm::SHA1, 6, 0, TOTP_STEP, bytes, Some(TOTP_ISSUER.to_string()), account.to_string())
+        .map_err(|e| StoreError::Database(e.to_string().into()))
+}
+
+/// The step `code` is valid for, allowing one step of clock drift either way.
+fn matching_step(totp: &TOTP, code: &str) -> Option<i64> {
+    let current = (Utc::now().timestamp() as u64) / TOTP_STEP;
+
+    [current - 1, current, current + 1]
+        .into_iter()
+        .find(|step| totp.check(code, step * TOTP_STEP))
+        .map(|step| step as i64)
+}
+
+/// Codes are shown as `xxxxx-xxxxx-xxxxx-xxxxx` but accepted in any case and
+/// with or without separators.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Replaces all of the user's recovery codes inside the caller's transaction.
+async fn replace_recovery_codes<C>(conn: &mut C, input_user_id: &str) -> Result<Vec<String>, StoreError>
+where
+    C: AsyncConnection<Backend = diesel::pg::Pg>,
+{
+    use crate::schema::recovery_codes::dsl::*;
+
+    let codes = (0..RECOVERY_CODE_COUNT).map(|_| new_recovery_code()).collect::<Vec<_>>();
+    let rows = codes
+        .iter()
+        .map(|c| NewRecoveryCode {
+            id: Uuid::new_v4().to_string(),
+            user_id: input_user_id.to_string(),
+            code_hash: hash_token(&normalize_recovery_code(c)),
+        })
+        .collect::<Vec<_>>();
+
+    diesel::delete(recovery_codes.filter(user_id.eq(input_user_id)))
+        .execute(conn)
+        .await?;
+
+    diesel::insert_into(recovery_codes)
+        .values(rows)
+        .execute(conn)
+        .await?;
+
+    Ok(codes)
+}
+
+fn new_recovery_code() -> String {
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+
+    code.as_bytes()
+        .chunks(5)
+        .map(|c| String::from_utf8_lossy(c).into_owned())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+impl Store {
+    pub async fn is_totp_enabled(&self, input_user_id: String) -> Result<bool, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let enabled_at = users
+            .filter(id.eq(input_user_id))
+            .select(totp_enabled_at)
+            .first::<Option<chrono::NaiveDateTime>>(&mut conn)
+            .await?;
+
+        Ok(enabled_at.is_some())
+    }
+
+    /// Starts enrolling a new authenticator, replacing any unconfirmed one.
+    /// Fails with `Conflict` while 2FA is already on.
+    pub async fn begin_totp_enrollment(&self, input_user_id: String) -> Result<TotpEnrollment, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let account = users
+            .filter(id.eq(&input_user_id))
+            .select(email)
+            .first::<String>(&mut conn)
+            .await?;
+
+        let secret = match Secret::generate_secret().to_encoded() {
+            Secret::Encoded(s) => s,
+            Secret::Raw(_) => return Err(StoreError::Database("secret could not be encoded".into())),
+        };
+        let otpauth_url = totp(&secret, &account)?.get_url();
+
+        let updated = diesel::update(
+            users
+                .filter(id.eq(&input_user_id))
+                .filter(totp_enabled_at.is_null()),
+        )
+        .set((totp_secret.eq(Some(&secret)), totp_last_step.eq(None::<i64>)))
+        .execute(&mut conn)
+        .await?;
+
+        if updated == 0 {
+            return Err(StoreError::Conflict("two-factor authentication is already on"));
+        }
+
+        Ok(TotpEnrollment { secret, otpauth_url })
+    }
+
+    /// Turns 2FA on once a code from the new authenticator checks out, and
+    /// returns the recovery codes, which are only ever shown here.
+    pub async fn confirm_totp_enrollment(&self, actor: &Actor, code: String) -> Result<Vec<String>, StoreError> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let (account, pending) = users
+            .filter(id.eq(&actor.user_id))
+            .filter(totp_enabled_at.is_null())
+            .select((email, totp_secret))
+            .first::<(String, Option<String>)>(&mut conn)
+            .await?;
+
+        let pending = pending.ok_or(StoreError::NotFound)?;
+        let step = matching_step(&totp(&pending, &account)?, &code).ok_or(StoreError::Unauthorized)?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                diesel::update(users.filter(id.eq(&actor.user_id)))
+                    .set((totp_enabled_at.eq(Some(Utc::now().naive_utc())), totp_last_step.eq(Some(step))))
+                    .execute(conn)
+                    .await?;
+
+                let codes = replace_recovery_codes(conn, &actor.user_id).await?;
+
+                Change::new(TWO_FACTOR_ENABLE, USER, &actor.user_id)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(codes)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Replaces all recovery codes with new ones.
+    pub async fn regenerate_recovery_codes(&self, actor: &Actor) -> Result<Vec<String>, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let codes = replace_recovery_codes(conn, &actor.user_id).await?;
+
+                Change::new(RECOVERY_CODES_REGENERATE, USER, &actor.user_id)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(codes)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Checks a TOTP code or, failing that, uses up a recovery code. Users
+    /// without 2FA never pass.
+    pub async fn verify_second_factor(&self, actor: &Actor, code: String) -> Result<bool, StoreError> {
+        use crate::schema::{recovery_codes, users};
+
+        let mut conn = self.pool.get().await?;
+
+        let (account, secret) = users::table
+            .filter(users::id.eq(&actor.user_id))
+            .filter(users::totp_enabled_at.is_not_null())
+            .select((users::email, users::totp_secret))
+            .first::<(String, Option<String>)>(&mut conn)
+            .await?;
+
+        let code = code.trim();
+        if let Some(secret) = secret {
+            if let Some(step) = matching_step(&totp(&secret, &account)?, code) {
+                // Only moving the step forward accepts the code, so each is good once
+                let accepted = diesel::update(
+                    users::table
+                        .filter(users::id.eq(&actor.user_id))
+                        .filter(users::totp_last_step.is_null().or(users::totp_last_step.lt(step))),
+                )
+                .set(users::totp_last_step.eq(Some(step)))
+                .execute(&mut conn)
+                .await?;
+
+                return Ok(accepted > 0);
+            }
+        }
+
+        let code_hash = hash_token(&normalize_recovery_code(code));
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let used = diesel::update(
+                    recovery_codes::table
+                        .filter(recovery_codes::user_id.eq(&actor.user_id))
+                        .filter(recovery_codes::code_hash.eq(code_hash))
+                        .filter(recovery_codes::used_at.is_null()),
+                )
+                .set(recovery_codes::used_at.eq(Some(Utc::now().naive_utc())))
+                .returning(recovery_codes::id)
+                .get_result::<String>(conn)
+                .await
+                .optional()?;
+
+                let Some(code_id) = used else {
+                    return Ok(false);
+                };
+
+                Change::new(RECOVERY_CODE_USE, USER, &actor.user_id)
+                    .after(&serde_json::json!({ "recovery_code": code_id }))
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(true)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_unused_recovery_code_count(&self, input_user_id: String) -> Result<i64, StoreError> {
+        use crate::schema::recovery_codes::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let count = recovery_codes
+            .filter(user_id.eq(input_user_id))
+            .filter(used_at.is_null())
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Turns 2FA off and drops the secret and recovery codes.
+    pub async fn disable_totp(&self, actor: &Actor) -> Result<(), StoreError> {
+        use crate::schema::{recovery_codes, users};
+
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                diesel::update(users::table.filter(users::id.eq(&actor.user_id)))
+                    .set((
+                        users::totp_secret.eq(None::<String>),
+                        users::totp_enabled_at.eq(None::<chrono::NaiveDateTime>),
+                        users::totp_last_step.eq(None::<i64>),
+                    ))
+                    .execute(conn)
+                    .await?;
+
+                diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(&actor.user_id)))
+                    .execute(conn)
+                    .await?;
+
+                Change::new(TWO_FACTOR_DISABLE, USER, &actor.user_id)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::audit::AuditFilter, test_db::test_store};
+
+    fn current_step() -> u64 {
+        Utc::now().timestamp() as u64 / TOTP_STEP
+    }
+
+    fn code_for(totp: &TOTP, step: u64) -> String {
+        totp.generate(step * TOTP_STEP)
+    }
+
+    fn new_secret() -> String {
+        Secret::generate_secret().to_encoded().to_string()
+    }
+
+    #[test]
+    fn codes_match_their_step_with_one_step_of_drift() {
+        let totp = totp(&new_secret(), "a@example.com").unwrap();
+        let now = current_step();
+
+        assert_eq!(matching_step(&totp, &code_for(&totp, now)), Some(now as i64));
+        assert_eq!(matching_step(&totp, &code_for(&totp, now - 1)), Some(now as i64 - 1));
+        assert_eq!(matching_step(&totp, &code_for(&totp, now + 1)), Some(now as i64 + 1));
+        assert_eq!(matching_step(&totp, &code_for(&totp, now - 3)), None);
+        assert_eq!(matching_step(&totp, "12345"), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        assert_eq!(normalize_recovery_code("AbCdE-fGhIj-12345"), "abcdefghij12345");
+        assert_eq!(normalize_recovery_code(" abcde fghij\t12345 "), "abcdefghij12345");
+        assert_eq!(normalize_recovery_code("abcde-fghij"), normalize_recovery_code("ABCDEFGHIJ"));
+    }
+
+    #[tokio::test]
+    async fn totp_codes_are_good_once() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let actor = Actor { user_id, ip: None };
+
+        let enrollment = s.begin_totp_enrollment(actor.user_id.clone()).await.unwrap();
+        let totp = totp(&enrollment.secret, "a@example.com").unwrap();
+        let now = current_step();
+
+        // Not on until a code from the authenticator confirms it
+        assert!(!s.is_totp_enabled(actor.user_id.clone()).await.unwrap());
+        let stale = s.confirm_totp_enrollment(&actor, code_for(&totp, now - 5)).await;
+        assert!(matches!(stale, Err(StoreError::Unauthorized)));
+        s.confirm_totp_enrollment(&actor, code_for(&totp, now)).await.unwrap();
+        assert!(s.is_totp_enabled(actor.user_id.clone()).await.unwrap());
+
+        // The confirming code and older ones can't be replayed
+        assert!(!s.verify_second_factor(&actor, code_for(&totp, now)).await.unwrap());
+        assert!(!s.verify_second_factor(&actor, code_for(&totp, now - 1)).await.unwrap());
+
+        // The next step is accepted once, and moves the bar past the current one
+        assert!(s.verify_second_factor(&actor, code_for(&totp, now + 1)).await.unwrap());
+        assert!(!s.verify_second_factor(&actor, code_for(&totp, now + 1)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn recovery_codes_work_once_and_are_logged() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let actor = Actor { user_id: user_id.clone(), ip: Some("203.0.113.7".to_string()) };
+
+        let mut conn = s.pool.get().await.unwrap();
+        diesel::update(crate::schema::users::table.filter(crate::schema::users::id.eq(&user_id)))
+            .set(crate::schema::users::totp_enabled_at.eq(Some(Utc::now().naive_utc())))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let codes = replace_recovery_codes(&mut conn, &user_id).await.unwrap();
+
+        // Typed in upper case and without dashes still counts
+        let typed = codes[0].replace('-', "").to_uppercase();
+        assert!(s.verify_second_factor(&actor, typed).await.unwrap());
+        assert!(!s.verify_second_factor(&actor, codes[0].clone()).await.unwrap());
+        assert!(!s.verify_second_factor(&actor, "not-a-code".to_string()).await.unwrap());
+        assert_eq!(s.get_unused_recovery_code_count(user_id.clone()).await.unwrap(), 9);
+
+        let filter = AuditFilter {
+            actor_id: Some(user_id),
+            action: Some(RECOVERY_CODE_USE.to_string()),
+            ..Default::default()
+        };
+        let logged = s.get_audit_log(filter, 10, 0).await.unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].ip.as_deref(), Some("203.0.113.7"));
+    }
+}
@@ -1,10 +1,20 @@
-use crate::store::Store;
+use crate::{error::StoreError, store::Store};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use chrono::{NaiveDateTime, Utc};
-use diesel::{prelude::*, result::Error, sql_types::Double};
-use diesel_async::RunQueryDsl;
+use diesel::{prelude::*, sql_types::Double};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use super::{
+    audit::{Actor, Change},
+    plan::check_monitor_quota,
+};
+
+const WEBSITE: &str = "website";
+const WEBSITE_CREATE: &str = "website.create";
+const WEBSITE_SHARE: &str = "website.share";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum WebsiteStatus {
     UP,
@@ -13,6 +23,7 @@ pub enum WebsiteStatus {
 }
 
 #[derive(Queryable, Insertable, Selectable, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[diesel(table_name = crate::schema::websites)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Website {
@@ -23,6 +34,7 @@ pub struct Website {
     pub is_snippet_added: bool,
     pub about: String,
     pub plan_name: String,
+    pub org_id: String,
 }
 
 #[derive(Queryable, Insertable, Selectable)]
@@ -37,6 +49,7 @@ pub struct WebsiteTick {
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct HourlyView {
     #[diesel(sql_type = diesel::sql_types::Timestamp)]
     pub hour: chrono::NaiveDateTime,
@@ -46,12 +59,14 @@ pub struct HourlyView {
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Status {
     #[diesel(sql_type = diesel::sql_types::Text)]
     pub status: String
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct DailyView {
     #[diesel(sql_type = diesel::sql_types::Timestamp)]
     pub day: chrono::NaiveDateTime,
@@ -61,6 +76,7 @@ pub struct DailyView {
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MinuteView {
     #[diesel(sql_type = diesel::sql_types::Timestamp)]
     pub minute: chrono::NaiveDateTime,
@@ -70,6 +86,7 @@ pub struct MinuteView {
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TotalViewsPerPage {
     #[diesel(sql_type = diesel::sql_types::Text)]
     pub page_path: String,
@@ -79,80 +96,165 @@ pub struct TotalViewsPerPage {
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TotalUniqueUsers {
     #[diesel(sql_type = diesel::sql_types::BigInt)]
     pub unique_users: i64,
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TotalViews {
     #[diesel(sql_type = diesel::sql_types::BigInt)]
     pub total_views: i64,
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct AvgRespTime {
     #[diesel(sql_type = diesel::sql_types::Nullable<Double>)]
     pub avg: Option<f64>,
 }
 
 #[derive(QueryableByName, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UptimePercentage {
     #[diesel(sql_type = diesel::sql_types::Nullable<Double>)]
     pub uptime_percent: Option<f64>,
 }
 
+#[derive(QueryableByName, Debug, Serialize, Deserialize)]
+pub struct ResponseTimeStats {
+    #[diesel(sql_type = diesel::sql_types::Nullable<Double>)]
+    pub avg: Option<f64>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Double>)]
+    pub p50: Option<f64>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Double>)]
+    pub p95: Option<f64>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Double>)]
+    pub p99: Option<f64>,
+}
+
+#[derive(QueryableByName, Debug, Serialize, Deserialize)]
+pub struct TickCounts {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub total: i64,
+
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub up: i64,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+    pub first_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(QueryableByName, Debug, Serialize, Deserialize)]
+pub struct StatusTransition {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub region: String,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub previous_status: Option<String>,
+
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub status: String,
+
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub changed_at: chrono::NaiveDateTime,
+}
+
+#[derive(QueryableByName, Debug, Serialize, Deserialize)]
+pub struct StatusChange {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub website_url: String,
+
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub region: String,
+
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub previous_status: String,
+
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub status: String,
+
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub changed_at: chrono::NaiveDateTime,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+    pub ended_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Queryable, Debug)]
+pub struct ScheduledWebsite {
+    pub url: String,
+    pub id: String,
+    pub user_id: String,
+    pub is_snippet_added: bool,
+    pub min_check_interval_secs: i32,
+    pub allowed_regions: Option<Vec<String>>,
+}
+
+#[derive(QueryableByName, Debug, Serialize, Deserialize)]
+pub struct LatestTick {
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+    pub created_at: Option<chrono::NaiveDateTime>,
+}
+
 impl Store {
+    /// Creates a website in `input_org_id`, counted against the plan of the
+    /// user adding it.
     pub async fn create_website(
         &self,
-        u_i: String,
+        actor: &Actor,
+        input_org_id: String,
         new_url: String,
         input_about: String,
-    ) -> Result<Website, Error> {
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
-
-        let new_website = Website {
-            id: Uuid::new_v4().to_string(),
-            url: new_url,
-            time_added: Utc::now().naive_local(),
-            user_id: u_i,
-            is_snippet_added: false,
-            about: input_about,
-            plan_name: "Basic".to_owned(),
-        };
-
-        let created_website = diesel::insert_into(crate::schema::websites::table)
-            .values(new_website)
-            .returning(Website::as_returning())
-            .get_result(&mut conn)
-            .await;
-
-        match created_website {
-            Ok(w) => Ok(w),
-            Err(e) => Err(e),
-        }
+    ) -> Result<Website, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let org_plan = check_monitor_quota(conn, &input_org_id).await?;
+
+                let new_website = Website {
+                    id: Uuid::new_v4().to_string(),
+                    url: new_url,
+                    time_added: Utc::now().naive_local(),
+                    user_id: actor.user_id.clone(),
+                    is_snippet_added: false,
+                    about: input_about,
+                    plan_name: org_plan.name,
+                    org_id: input_org_id,
+                };
+
+                let w = diesel::insert_into(crate::schema::websites::table)
+                    .values(new_website)
+                    .returning(Website::as_returning())
+                    .get_result(conn)
+                    .await?;
+
+                Change::new(WEBSITE_CREATE, WEBSITE, &w.id)
+                    .org(&w.org_id)
+                    .after(&w)
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(w)
+            }
+            .scope_boxed()
+        })
+        .await
     }
 
+    /// The status of the latest check, or `NotFound` before the first one.
+    /// Callers check the user's access to the website first.
     pub async fn get_website_recent_status(
         &self,
         input_website_url: String,
-        input_user_id: String,
-    ) -> Result<Status, Error> {
-        use crate::schema::websites::dsl::*;
-    
-        let mut conn = self.pool.get().await.map_err(|e| {
-            println!("{}", e);
-            Error::NotFound
-        })?;
-    
-        // 🔐 Verify website ownership
-        let _website = websites
-            .filter(url.eq(&input_website_url))
-            .filter(user_id.eq(&input_user_id))
-            .select(Website::as_select())
-            .first(&mut conn)
-            .await?;
+    ) -> Result<Status, StoreError> {
+        let mut conn = self.pool.get().await?;
     
         // 🔎 Fetch most recent tick
         let query = r#"
@@ -166,38 +268,22 @@ impl Store {
         let result = diesel::sql_query(query)
             .bind::<diesel::sql_types::Text, _>(&input_website_url)
             .get_result::<Status>(&mut conn)
-            .await;
-    
-        match result {
-            Ok(s) => {
-                Ok(s)
-            }
-            Err(_) => Ok(Status { status: "Unknown".into() })
-        }
+            .await?;
+
+        Ok(result)
     }
     
     pub async fn get_website_details_hourly(
         &self,
         input_website_url: String,
-        input_user_id: String,
         mut hours: String,
-    ) -> Result<Vec<HourlyView>, Error> {
-        use crate::schema::websites::dsl::*;
-
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+    ) -> Result<Vec<HourlyView>, StoreError> {
+        let mut conn = self.pool.get().await?;
 
         if hours.trim().is_empty() {
             hours = "2 hours".to_string();
         }
 
-        let _website_result = websites
-            .filter(url.eq(&input_website_url))
-            .filter(user_id.eq(input_user_id))
-            .select(Website::as_select())
-            .first(&mut conn)
-            .await?;
-
         let query = r#"
         SELECT 
             DATE_TRUNC('hour', visited_at) AS hour,
@@ -222,25 +308,14 @@ impl Store {
     pub async fn get_website_details_daily(
         &self,
         input_website_url: String,
-        input_user_id: String,
         mut days: String,
-    ) -> Result<Vec<DailyView>, Error> {
-        use crate::schema::websites::dsl::*;
-
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+    ) -> Result<Vec<DailyView>, StoreError> {
+        let mut conn = self.pool.get().await?;
 
         if days.trim().is_empty() {
             days = "2 day".to_string();
         }
 
-        let _website_result = websites
-            .filter(url.eq(&input_website_url))
-            .filter(user_id.eq(input_user_id))
-            .select(Website::as_select())
-            .first(&mut conn)
-            .await?;
-
         let query = r#"
             SELECT 
                 d.day,
@@ -270,19 +345,8 @@ impl Store {
     pub async fn get_website_details_last_hour(
         &self,
         input_website_url: String,
-        input_user_id: String,
-    ) -> Result<Vec<MinuteView>, Error> {
-        use crate::schema::websites::dsl::*;
-
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
-
-        let _website_result = websites
-            .filter(url.eq(&input_website_url))
-            .filter(user_id.eq(input_user_id))
-            .select(Website::as_select())
-            .first(&mut conn)
-            .await?;
+    ) -> Result<Vec<MinuteView>, StoreError> {
+        let mut conn = self.pool.get().await?;
 
         // Query: generate a 1-minute series for the past 60 minutes
         let query = r#"
@@ -310,11 +374,10 @@ impl Store {
         Ok(results)
     }
 
-    pub async fn search_website(&self, input_url: &str) -> Result<Website, Error> {
+    pub async fn search_website(&self, input_url: &str) -> Result<Website, StoreError> {
         use crate::schema::websites::dsl::*;
 
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
 
         let found_website = websites
             .filter(url.eq(input_url))
@@ -325,13 +388,73 @@ impl Store {
         Ok(found_website)
     }
 
+    /// Turns the public dashboard link for a website on or off. Turning it on
+    /// issues a new token, so old links stop working.
+    pub async fn set_website_sharing(
+        &self,
+        actor: &Actor,
+        input_url: String,
+        enabled: bool,
+    ) -> Result<Option<String>, StoreError> {
+        use crate::schema::websites::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let token = enabled.then(|| {
+            let mut bytes = [0u8; 24];
+            OsRng.fill_bytes(&mut bytes);
+            hex::encode(bytes)
+        });
+
+        conn.transaction::<_, StoreError, _>(|conn| {
+            async move {
+                let (website_id, website_org_id, was_shared) = websites
+                    .filter(url.eq(&input_url))
+                    .select((id, org_id, share_token.is_not_null()))
+                    .first::<(String, String, bool)>(conn)
+                    .await?;
+
+                diesel::update(websites.filter(id.eq(&website_id)))
+                    .set(share_token.eq(&token))
+                    .execute(conn)
+                    .await?;
+
+                Change::new(WEBSITE_SHARE, WEBSITE, website_id)
+                    .org(website_org_id)
+                    .before(&serde_json::json!({ "shared": was_shared }))
+                    .after(&serde_json::json!({ "shared": enabled }))
+                    .record(conn, actor)
+                    .await?;
+
+                Ok(token)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Whether `input_token` is the current public link for `input_url`.
+    pub async fn is_website_shared(&self, input_url: String, input_token: String) -> Result<bool, StoreError> {
+        use crate::schema::websites::dsl::*;
+
+        let mut conn = self.pool.get().await?;
+
+        let count = websites
+            .filter(url.eq(input_url))
+            .filter(share_token.eq(input_token))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+
+        Ok(count > 0)
+    }
+
     pub async fn get_all_websites(
         &self,
-    ) -> Result<Vec<(String, String, String, bool)>, diesel::result::Error> {
+    ) -> Result<Vec<(String, String, String, bool)>, StoreError> {
         use crate::schema::websites::dsl::*;
 
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
 
         let websites_result = websites
             .select((url, id, user_id, is_snippet_added))
@@ -341,28 +464,67 @@ impl Store {
         Ok(websites_result)
     }
 
-    pub async fn get_users_all_websites(
+    /// Every website with the check interval and regions its plan allows.
+    pub async fn get_scheduled_websites(&self) -> Result<Vec<ScheduledWebsite>, StoreError> {
+        use crate::schema::{plan, websites};
+
+        let mut conn = self.pool.get().await?;
+
+        let res = websites::table
+            .inner_join(plan::table.on(plan::name.eq(websites::plan_name)))
+            .select((
+                websites::url,
+                websites::id,
+                websites::user_id,
+                websites::is_snippet_added,
+                plan::min_check_interval_secs,
+                plan::allowed_regions,
+            ))
+            .load::<ScheduledWebsite>(&mut conn)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// The websites of every organization the user belongs to, or of just
+    /// `input_org_id`. Organizations requiring 2FA are left out unless
+    /// `has_two_factor`.
+    pub async fn get_member_websites(
         &self,
         input_user_id: String,
-    ) -> Result<Vec<Website>, Error> {
-        use crate::schema::websites::dsl::*;
+        input_org_id: Option<String>,
+        has_two_factor: bool,
+    ) -> Result<Vec<Website>, StoreError> {
+        use crate::schema::{org_members, organizations, websites};
 
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
 
-        let websites_result = websites
-            .filter(user_id.eq(input_user_id))
+        let mut query = websites::table
+            .inner_join(organizations::table)
+            .inner_join(org_members::table.on(org_members::org_id.eq(websites::org_id)))
+            .filter(org_members::user_id.eq(input_user_id))
             .select(Website::as_select())
+            .into_boxed();
+
+        if let Some(o) = input_org_id {
+            query = query.filter(websites::org_id.eq(o));
+        }
+
+        if !has_two_factor {
+            query = query.filter(organizations::require_two_factor.eq(false));
+        }
+
+        let websites_result = query
+            .order(websites::time_added.asc())
             .load(&mut conn)
             .await?;
 
         Ok(websites_result)
     }
 
-    pub async fn update_website_snippet(&self, input_website_url: &str) -> Result<(), Error> {
+    pub async fn update_website_snippet(&self, input_website_url: &str) -> Result<(), StoreError> {
 
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
 
         let query = r#"
         UPDATE websites SET is_snippet_added=TRUE where url = $1;
@@ -379,10 +541,9 @@ impl Store {
     pub async fn get_per_page_views(
         &self,
         input_website: String,
-    ) -> Result<Vec<TotalViewsPerPage>, Error> {
+    ) -> Result<Vec<TotalViewsPerPage>, StoreError> {
 
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
 
         let query = r#"SELECT 
             page_path,
@@ -408,10 +569,9 @@ impl Store {
     pub async fn get_total_unique_users(
         &self,
         input_website: String,
-    ) -> Result<TotalUniqueUsers, Error> {
+    ) -> Result<TotalUniqueUsers, StoreError> {
 
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
 
         let query = r#"
         SELECT
@@ -429,10 +589,9 @@ impl Store {
         Ok(res)
     }
 
-    pub async fn get_total_views(&self, input_website: String) -> Result<TotalViews, Error> {
+    pub async fn get_total_views(&self, input_website: String) -> Result<TotalViews, StoreError> {
 
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        let mut conn = self.pool.get().await?;
 
         let query = r#"
             SELECT 
@@ -451,82 +610,220 @@ impl Store {
         Ok(res)
     }
 
-    pub async fn get_average_resp_time(&self, input_website: String) -> Result<AvgRespTime, Error>{
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+    pub async fn get_average_resp_time(&self, input_website: String) -> Result<AvgRespTime, StoreError>{
+        let agg = self
+            .get_tick_aggregate(input_website, None, None, Utc::now().naive_utc())
+            .await?;
 
-        let query = r#"
-        SELECT AVG(response_time_ms)::DOUBLE PRECISION AS avg
-        FROM website_tick
-        WHERE website_url = $1;
-        "#;
+        Ok(AvgRespTime { avg: agg.avg_all })
+    }
 
-        let result: AvgRespTime = diesel::sql_query(query).bind::<diesel::sql_types::Text, _>(input_website).get_result::<AvgRespTime>(&mut conn).await?;
+    pub async fn get_average_resp_time_by_region(&self, input_website: String, input_region: String) -> Result<AvgRespTime, StoreError>{
+        let agg = self
+            .get_tick_aggregate(input_website, Some(input_region), None, Utc::now().naive_utc())
+            .await?;
 
-        return Ok(result);
+        Ok(AvgRespTime { avg: agg.avg_all })
     }
 
-    pub async fn get_average_resp_time_by_region(&self, input_website: String, input_region: String) -> Result<AvgRespTime, Error>{
-        
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+    pub async fn get_average_uptime_percentage(
+        &self,
+        input_website: String,
+    ) -> Result<UptimePercentage, StoreError> {
+        let agg = self
+            .get_tick_aggregate(input_website, None, None, Utc::now().naive_utc())
+            .await?;
 
-        let query = r#"
-        SELECT AVG(response_time_ms)::DOUBLE PRECISION AS avg
-        FROM website_tick
-        WHERE website_url = $1 
-        AND region = $2;
-        "#;
+        Ok(UptimePercentage { uptime_percent: agg.uptime_percent() })
+    }
 
-        let result: AvgRespTime = diesel::sql_query(query).bind::<diesel::sql_types::Text, _>(input_website).bind::<diesel::sql_types::Text, _>(input_region).get_result::<AvgRespTime>(&mut conn).await?;
+    pub async fn get_average_uptime_percentage_by_region(
+        &self,
+        input_website: String,
+        input_region: String
+    ) -> Result<UptimePercentage, StoreError> {
+        let agg = self
+            .get_tick_aggregate(input_website, Some(input_region), None, Utc::now().naive_utc())
+            .await?;
 
-        return Ok(result);
+        Ok(UptimePercentage { uptime_percent: agg.uptime_percent() })
     }
 
-    pub async fn get_average_uptime_percentage(
+    pub async fn get_latest_tick_time(
         &self,
         input_website: String,
-    ) -> Result<UptimePercentage, Error> {
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+    ) -> Result<Option<NaiveDateTime>, StoreError> {
+        let mut conn = self.pool.get().await?;
+
         let query = r#"
-            SELECT 
-                (COUNT(*) FILTER (WHERE status = 'Up') * 100.0 / NULLIF(COUNT(*), 0))::DOUBLE PRECISION
-                AS uptime_percent
+            SELECT MAX("createdAt") AS created_at
             FROM website_tick
             WHERE website_url = $1;
         "#;
-    
-        let result: UptimePercentage = diesel::sql_query(query)
+
+        let result = diesel::sql_query(query)
             .bind::<diesel::sql_types::Text, _>(input_website)
-            .get_result::<UptimePercentage>(&mut conn)
+            .get_result::<LatestTick>(&mut conn)
             .await?;
-    
-        Ok(result)
+
+        Ok(result.created_at)
     }
-    
-    pub async fn get_average_uptime_percentage_by_region(
+
+    /// Status changes between consecutive ticks of the same region in `(since, until]`.
+    /// Ticks up to an hour before `since` are scanned so the first tick in the range
+    /// is still compared with its predecessor.
+    pub async fn get_status_transitions(
         &self,
         input_website: String,
-        input_region: String
-    ) -> Result<UptimePercentage, Error> {
-        let mut conn = self.pool.get().await
-        .map_err(|e| { println!("{}", e.to_string()); return Error::NotFound })?;
+        since: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<StatusTransition>, StoreError> {
+        let mut conn = self.pool.get().await?;
+
         let query = r#"
-            SELECT 
-                (COUNT(*) FILTER (WHERE status = 'Up') * 100.0 / NULLIF(COUNT(*), 0))::DOUBLE PRECISION
-                AS uptime_percent
-            FROM website_tick
-            WHERE website_url = $1 AND region = $2;
+            SELECT region, previous_status, status, changed_at
+            FROM (
+                SELECT
+                    region,
+                    status,
+                    "createdAt" AS changed_at,
+                    LAG(status) OVER (PARTITION BY region ORDER BY "createdAt") AS previous_status
+                FROM website_tick
+                WHERE website_url = $1
+                AND "createdAt" > $2 - INTERVAL '1 hour'
+                AND "createdAt" <= $3
+            ) t
+            WHERE changed_at > $2
+            AND previous_status IS NOT NULL
+            AND previous_status <> status
+            ORDER BY changed_at;
         "#;
-    
-        let result: UptimePercentage = diesel::sql_query(query)
+
+        let results = diesel::sql_query(query)
             .bind::<diesel::sql_types::Text, _>(input_website)
-            .bind::<diesel::sql_types::Text, _>(input_region)
-            .get_result::<UptimePercentage>(&mut conn)
+            .bind::<diesel::sql_types::Timestamp, _>(since)
+            .bind::<diesel::sql_types::Timestamp, _>(until)
+            .load::<StatusTransition>(&mut conn)
             .await?;
-    
-        Ok(result)
+
+        Ok(results)
+    }
+
+    /// Most recent status changes across a set of websites, newest first. `ended_at`
+    /// is when the same region changed again, `None` while the status still holds.
+    pub async fn get_status_history(
+        &self,
+        input_websites: Vec<String>,
+        since: NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<StatusChange>, StoreError> {
+        let mut conn = self.pool.get().await?;
+
+        let query = r#"
+            WITH ticks AS (
+                SELECT
+                    website_url,
+                    region,
+                    status,
+                    "createdAt",
+                    LAG(status) OVER (PARTITION BY website_url, region ORDER BY "createdAt") AS previous_status
+                FROM website_tick
+                WHERE website_url = ANY($1)
+                AND "createdAt" > $2
+            ), changes AS (
+                SELECT
+                    website_url,
+                    region,
+                    previous_status,
+                    status,
+                    "createdAt" AS changed_at,
+                    LEAD("createdAt") OVER (PARTITION BY website_url, region ORDER BY "createdAt") AS ended_at
+                FROM ticks
+                WHERE previous_status IS DISTINCT FROM status
+            )
+            SELECT website_url, region, previous_status, status, changed_at, ended_at
+            FROM changes
+            WHERE previous_status IS NOT NULL
+            ORDER BY changed_at DESC
+            LIMIT $3;
+        "#;
+
+        let results = diesel::sql_query(query)
+            .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(input_websites)
+            .bind::<diesel::sql_types::Timestamp, _>(since)
+            .bind::<diesel::sql_types::BigInt, _>(limit)
+            .load::<StatusChange>(&mut conn)
+            .await?;
+
+        Ok(results)
+    }
+
+    pub async fn get_uptime_percentage_since(
+        &self,
+        input_website: String,
+        since: NaiveDateTime,
+    ) -> Result<UptimePercentage, StoreError> {
+        let agg = self
+            .get_tick_aggregate(input_website, None, Some(since), Utc::now().naive_utc())
+            .await?;
+
+        Ok(UptimePercentage { uptime_percent: agg.uptime_percent() })
+    }
+
+    /// Response times of `Up` ticks in `[from, to)`.
+    pub async fn get_response_time_stats_between(
+        &self,
+        input_website: String,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<ResponseTimeStats, StoreError> {
+        let agg = self
+            .get_tick_aggregate(input_website, None, Some(from), to)
+            .await?;
+
+        Ok(ResponseTimeStats {
+            avg: agg.avg,
+            p50: agg.p50,
+            p95: agg.p95,
+            p99: agg.p99,
+        })
+    }
+
+    pub async fn get_tick_counts_between(
+        &self,
+        input_website: String,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<TickCounts, StoreError> {
+        let agg = self
+            .get_tick_aggregate(input_website, None, Some(from), to)
+            .await?;
+
+        Ok(TickCounts {
+            total: agg.total,
+            up: agg.up,
+            first_at: agg.first_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db::test_store;
+
+    #[tokio::test]
+    async fn websites_never_checked_have_no_status() {
+        let Some(s) = test_store().await else { return };
+        let user_id = s.sign_up("a@example.com".to_string(), "hunter22".to_string(), "A".to_string()).await.unwrap();
+        let org_id = s.get_personal_org_id(user_id.clone()).await.unwrap();
+        let actor = Actor { user_id, ip: None };
+        let website = s
+            .create_website(&actor, org_id, "https://a.example.com".to_string(), String::new())
+            .await
+            .unwrap();
+
+        let status = s.get_website_recent_status(website.url).await;
+        assert!(matches!(status, Err(StoreError::NotFound)));
     }
-    
 }
@@ -1,5 +1,138 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Text,
+        user_id -> Text,
+        name -> Text,
+        prefix -> Text,
+        key_hash -> Text,
+        scopes -> Array<Text>,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        org_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Text,
+        actor_id -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        org_id -> Nullable<Text>,
+        action -> Text,
+        target_type -> Text,
+        target_id -> Text,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    billing_events (id) {
+        id -> Text,
+        event_type -> Text,
+        received_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    email_tokens (id) {
+        id -> Text,
+        user_id -> Text,
+        purpose -> Text,
+        token_hash -> Text,
+        new_email -> Nullable<Text>,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    identities (id) {
+        id -> Text,
+        user_id -> Text,
+        provider -> Text,
+        subject -> Text,
+        email -> Nullable<Text>,
+        created_at -> Timestamp,
+        last_login_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    incident_updates (id) {
+        id -> Text,
+        incident_id -> Text,
+        status -> Text,
+        message -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    incident_websites (incident_id, website_url) {
+        incident_id -> Text,
+        website_url -> Text,
+    }
+}
+
+diesel::table! {
+    incidents (id) {
+        id -> Text,
+        user_id -> Text,
+        title -> Text,
+        status -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    notification_cursor (website_url) {
+        website_url -> Text,
+        last_tick_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    org_invitations (id) {
+        id -> Text,
+        org_id -> Text,
+        email -> Text,
+        role -> Text,
+        token_hash -> Text,
+        invited_by -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        accepted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    org_members (org_id, user_id) {
+        org_id -> Text,
+        user_id -> Text,
+        role -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    organizations (id) {
+        id -> Text,
+        name -> Text,
+        personal -> Bool,
+        require_two_factor -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     page_visits (id) {
         id -> Int8,
@@ -17,6 +150,33 @@ diesel::table! {
         id -> Text,
         name -> Text,
         price -> Text,
+        tick_retention_days -> Int4,
+        visit_retention_days -> Int4,
+        rollup_retention_days -> Int4,
+        max_monitors -> Int4,
+        min_check_interval_secs -> Int4,
+        allowed_regions -> Nullable<Array<Text>>,
+        monthly_event_quota -> Int8,
+        provider_price_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    postmortems (incident_id) {
+        incident_id -> Text,
+        content -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Text,
+        user_id -> Text,
+        code_hash -> Text,
+        created_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
     }
 }
 
@@ -27,13 +187,112 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    report_schedules (id) {
+        id -> Text,
+        user_id -> Text,
+        website_url -> Nullable<Text>,
+        email -> Text,
+        last_sent_month -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    rollup_watermark (granularity) {
+        granularity -> Text,
+        rolled_up_to -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Text,
+        user_id -> Text,
+        refresh_token_hash -> Text,
+        previous_refresh_token_hash -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    slo_alerts (id) {
+        id -> Text,
+        slo_id -> Text,
+        burn_rate -> Float8,
+        triggered_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    slos (id) {
+        id -> Text,
+        user_id -> Text,
+        website_url -> Text,
+        name -> Text,
+        target_percent -> Float8,
+        window_kind -> Text,
+        window_days -> Int4,
+        burn_rate_threshold -> Float8,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    subscribers (id) {
+        id -> Text,
+        website_url -> Text,
+        channel -> Text,
+        target -> Text,
+        confirm_token -> Nullable<Text>,
+        unsubscribe_token -> Text,
+        is_confirmed -> Bool,
+        created_at -> Timestamp,
+        notified_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    subscriptions (id) {
+        id -> Text,
+        user_id -> Text,
+        provider_customer_id -> Text,
+        provider_subscription_id -> Text,
+        plan_name -> Text,
+        status -> Text,
+        current_period_end -> Nullable<Timestamp>,
+        last_event_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    usage_daily (org_id, day) {
+        day -> Date,
+        events -> Int8,
+        checks -> Int8,
+        org_id -> Text,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Text,
         name -> Text,
         email -> Text,
-        password -> Text,
+        password -> Nullable<Text>,
         plan_name -> Text,
+        email_verified -> Bool,
+        totp_secret -> Nullable<Text>,
+        totp_enabled_at -> Nullable<Timestamp>,
+        totp_last_step -> Nullable<Int8>,
+        feed_token -> Nullable<Text>,
     }
 }
 
@@ -48,6 +307,60 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    website_tick_daily (website_url, region, bucket) {
+        website_url -> Text,
+        region -> Text,
+        bucket -> Timestamp,
+        up_count -> Int8,
+        down_count -> Int8,
+        unknown_count -> Int8,
+        sum_response_ms -> Int8,
+        up_sum_response_ms -> Int8,
+        min_response_ms -> Nullable<Int4>,
+        max_response_ms -> Nullable<Int4>,
+        p50_response_ms -> Nullable<Float8>,
+        p95_response_ms -> Nullable<Float8>,
+        p99_response_ms -> Nullable<Float8>,
+    }
+}
+
+diesel::table! {
+    website_tick_hourly (website_url, region, bucket) {
+        website_url -> Text,
+        region -> Text,
+        bucket -> Timestamp,
+        up_count -> Int8,
+        down_count -> Int8,
+        unknown_count -> Int8,
+        sum_response_ms -> Int8,
+        up_sum_response_ms -> Int8,
+        min_response_ms -> Nullable<Int4>,
+        max_response_ms -> Nullable<Int4>,
+        p50_response_ms -> Nullable<Float8>,
+        p95_response_ms -> Nullable<Float8>,
+        p99_response_ms -> Nullable<Float8>,
+    }
+}
+
+diesel::table! {
+    website_tick_minutely (website_url, region, bucket) {
+        website_url -> Text,
+        region -> Text,
+        bucket -> Timestamp,
+        up_count -> Int8,
+        down_count -> Int8,
+        unknown_count -> Int8,
+        sum_response_ms -> Int8,
+        up_sum_response_ms -> Int8,
+        min_response_ms -> Nullable<Int4>,
+        max_response_ms -> Nullable<Int4>,
+        p50_response_ms -> Nullable<Float8>,
+        p95_response_ms -> Nullable<Float8>,
+        p99_response_ms -> Nullable<Float8>,
+    }
+}
+
 diesel::table! {
     websites (id) {
         id -> Text,
@@ -57,16 +370,63 @@ diesel::table! {
         is_snippet_added -> Bool,
         about -> Text,
         plan_name -> Text,
+        share_token -> Nullable<Text>,
+        org_id -> Text,
     }
 }
 
+diesel::joinable!(api_keys -> organizations (org_id));
+diesel::joinable!(api_keys -> users (user_id));
+diesel::joinable!(email_tokens -> users (user_id));
+diesel::joinable!(identities -> users (user_id));
+diesel::joinable!(incident_updates -> incidents (incident_id));
+diesel::joinable!(incident_websites -> incidents (incident_id));
+diesel::joinable!(incidents -> users (user_id));
+diesel::joinable!(org_invitations -> organizations (org_id));
+diesel::joinable!(org_invitations -> users (invited_by));
+diesel::joinable!(org_members -> organizations (org_id));
+diesel::joinable!(org_members -> users (user_id));
+diesel::joinable!(postmortems -> incidents (incident_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(report_schedules -> users (user_id));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(slo_alerts -> slos (slo_id));
+diesel::joinable!(slos -> users (user_id));
+diesel::joinable!(subscriptions -> users (user_id));
+diesel::joinable!(usage_daily -> organizations (org_id));
+diesel::joinable!(websites -> organizations (org_id));
 diesel::joinable!(websites -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    audit_log,
+    billing_events,
+    email_tokens,
+    identities,
+    incident_updates,
+    incident_websites,
+    incidents,
+    notification_cursor,
+    org_invitations,
+    org_members,
+    organizations,
     page_visits,
     plan,
+    postmortems,
+    recovery_codes,
     region,
+    report_schedules,
+    rollup_watermark,
+    sessions,
+    slo_alerts,
+    slos,
+    subscribers,
+    subscriptions,
+    usage_daily,
     users,
     website_tick,
+    website_tick_daily,
+    website_tick_hourly,
+    website_tick_minutely,
     websites,
 );
@@ -16,13 +16,17 @@ pub struct Store {
 impl Store {
     pub async fn new() -> Self  {
         let config = Config::default();
-        
+        Self::connect(config.db_url).await
+    }
+
+    /// A store on the database at `db_url`.
+    pub async fn connect(db_url: String) -> Self {
         let mut manager_config = ManagerConfig::default();
         // Use our native-tls setup function
         manager_config.custom_setup = Box::new(establish_connection);
 
         let mgr = AsyncDieselConnectionManager::<AsyncPgConnection>::new_with_config(
-            config.db_url, 
+            db_url,
             manager_config
         );
 
@@ -0,0 +1,58 @@
+//! Throwaway databases for tests of queries. Each `test_store` creates one
+//! with every migration applied, on the server at `TEST_DATABASE_URL`, whose
+//! user must be allowed to create databases. Without it those tests are
+//! skipped.
+
+use std::{env, fs, path::Path};
+
+use tokio_postgres::NoTls;
+use uuid::Uuid;
+
+use crate::store::Store;
+
+pub async fn test_store() -> Option<Store> {
+    let Ok(url) = env::var("TEST_DATABASE_URL") else {
+        println!("TEST_DATABASE_URL is not set, skipping");
+        return None;
+    };
+
+    let name = format!("test_{}", Uuid::new_v4().simple());
+    connect(&url).await.batch_execute(&format!("CREATE DATABASE {}", name)).await.unwrap();
+
+    let (server, _) = url.rsplit_once('/').expect("TEST_DATABASE_URL has no database name");
+    let db_url = format!("{}/{}", server, name);
+
+    let mut migrations: Vec<_> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    migrations.sort();
+
+    let client = connect(&db_url).await;
+    for migration in migrations {
+        let sql = fs::read_to_string(migration.join("up.sql")).unwrap();
+        client
+            .batch_execute(&sql)
+            .await
+            .unwrap_or_else(|e| panic!("{} failed: {}", migration.display(), e));
+    }
+
+    // Plans are seeded by hand in production; sign ups need the default one
+    client
+        .batch_execute(
+            r#"INSERT INTO "plan" ("id", "name", "price") VALUES ('basic', 'Basic', '0');
+               INSERT INTO "plan" ("id", "name", "price", "max_monitors", "provider_price_id")
+                   VALUES ('pro', 'Pro', '20', 50, 'price_pro');"#,
+        )
+        .await
+        .unwrap();
+
+    Some(Store::connect(db_url).await)
+}
+
+async fn connect(url: &str) -> tokio_postgres::Client {
+    let (client, connection) = tokio_postgres::connect(url, NoTls).await.unwrap();
+    tokio::spawn(connection);
+    client
+}
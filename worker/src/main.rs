@@ -17,7 +17,7 @@ async fn main_loop() -> Result<(), Error> {
     let worker_id =
         env::var("WORKER_ID").map_err(|e| Error::new(std::io::ErrorKind::InvalidInput, e))?;
 
-    if region.len() == 0 || worker_id.len() == 0 {
+    if region.is_empty() || worker_id.is_empty() {
         return Err(Error::new(
             std::io::ErrorKind::InvalidInput,
             "Invalid env inputs",
@@ -45,32 +45,37 @@ async fn main_loop() -> Result<(), Error> {
                 let _ = ensure_group(&mut r, "betteruptime:website", &cloned_region).await;
                 continue;
             }
-            Err(e) => return Err(Error::new(std::io::ErrorKind::Other, e)),
+            Err(e) => return Err(Error::other(e)),
         };
 
-        match messages {
-            Some(s) => {
-                let streams = s.keys;
-                for stream in streams {
-                    let stream_name = stream.key;
+        if let Some(s) = messages {
+            let streams = s.keys;
+            for stream in streams {
+                let stream_name = stream.key;
 
-                    println!("{}", stream_name);
+                println!("{}", stream_name);
 
-                    for stream_id in stream.ids {
-                        let message_id = stream_id.id;
-                        let map = stream_id.map;
+                for stream_id in stream.ids {
+                    let message_id = stream_id.id;
+                    let map = stream_id.map;
 
-                        let url_value = map.get("url").unwrap();
-                        let url = redis::from_redis_value::<String>(url_value).unwrap();
-                        println!("{}", url);
-                        let _ = fetch_website(&mut str, url).await;
+                    let url_value = map.get("url").unwrap();
+                    let url = redis::from_redis_value::<String>(url_value).unwrap();
+                    println!("{}", url);
 
-                        // ✅ ACK MESSAGE
-                        r.x_ack_bulk(&cloned_region, &[message_id]).await;
+                    // Skip websites whose plan does not include this region
+                    let is_allowed = map
+                        .get("regions")
+                        .and_then(|v| redis::from_redis_value::<String>(v).ok())
+                        .is_none_or(|regions| regions.split(',').any(|r| r == cloned_region));
+                    if is_allowed && fetch_website(&mut str, url.clone()).await.is_ok() {
+                        let _ = str.record_check(url).await;
                     }
+
+                    // ✅ ACK MESSAGE
+                    r.x_ack_bulk(&cloned_region, &[message_id]).await;
                 }
             }
-            None => {}
         }
 
         sleep(Duration::from_secs(10)).await;
@@ -91,8 +96,8 @@ async fn fetch_website(s: &mut Store, url: String) -> Result<(), Error> {
 
     let total_time = start_time.elapsed().as_millis() as i32;
     let mut conn = s.pool.get().await.map_err(|e| {
-        println!("{}", e.to_string());
-        return Error::new(std::io::ErrorKind::ConnectionRefused, e);
+        println!("{}", e);
+        Error::new(std::io::ErrorKind::ConnectionRefused, e)
     })?;
     match res {
         Ok(rps) => {
@@ -102,7 +107,7 @@ async fn fetch_website(s: &mut Store, url: String) -> Result<(), Error> {
                     id: Uuid::new_v4().to_string(),
                     response_time_ms: total_time,
                     status: "Up".to_owned(),
-                    region: region,
+                    region,
                     website_url: url,
                 };
 
@@ -115,10 +120,10 @@ async fn fetch_website(s: &mut Store, url: String) -> Result<(), Error> {
                 match val {
                     Ok(w) => {
                         println!("{}", w.response_time_ms);
-                        return Ok(());
+                        Ok(())
                     }
                     Err(_) => {
-                        return Ok(());
+                        Ok(())
                     }
                 }
             } else {
@@ -126,7 +131,7 @@ async fn fetch_website(s: &mut Store, url: String) -> Result<(), Error> {
                     id: Uuid::new_v4().to_string(),
                     response_time_ms: total_time,
                     status: "Down".to_owned(),
-                    region: region,
+                    region,
                     website_url: url,
                 };
 
@@ -136,7 +141,7 @@ async fn fetch_website(s: &mut Store, url: String) -> Result<(), Error> {
                     .get_result(&mut conn)
                     .await;
 
-                return Ok(());
+                Ok(())
             }
         }
 
@@ -145,7 +150,7 @@ async fn fetch_website(s: &mut Store, url: String) -> Result<(), Error> {
                 id: Uuid::new_v4().to_string(),
                 response_time_ms: total_time,
                 status: "Unknown".to_owned(),
-                region: region,
+                region,
                 website_url: url,
             };
 
@@ -155,13 +160,13 @@ async fn fetch_website(s: &mut Store, url: String) -> Result<(), Error> {
                 .get_result(&mut conn)
                 .await;
 
-            return Ok(());
+            Ok(())
         }
     }
 }
 
 async fn ensure_group(r: &mut Redis, stream: &str, group: &str) -> Result<(), RedisError> {
-    let res: Result<(), RedisError> = redis::cmd("XGROUP")
+    let _res: Result<(), RedisError> = redis::cmd("XGROUP")
         .arg("CREATE")
         .arg(stream)
         .arg(group)